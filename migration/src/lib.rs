@@ -8,6 +8,21 @@ mod m20251214_000005_create_claimed_promos;
 mod m20251214_000006_create_free_games;
 mod m20251218_000007_add_detailed_stats;
 mod m20251218_000009_create_free_items;
+mod m20251222_000010_add_user_display_name;
+mod m20251222_000011_create_weekly_leaderboards;
+mod m20251222_000012_create_promos;
+mod m20251222_000013_create_codes;
+mod m20251222_000014_create_referrals;
+mod m20251222_000015_create_broadcasts;
+mod m20251222_000016_add_user_language;
+mod m20251222_000017_add_user_profile;
+mod m20251222_000018_create_audit_log;
+mod m20251222_000019_create_staff;
+mod m20251222_000020_widen_build_columns;
+mod m20251222_000021_create_leases;
+mod m20251222_000022_create_key_events;
+mod m20251222_000023_add_broadcast_cursor;
+mod m20251222_000024_create_weekly_closes;
 
 pub struct Migrator;
 
@@ -23,6 +38,21 @@ impl MigratorTrait for Migrator {
       Box::new(m20251214_000006_create_free_games::Migration),
       Box::new(m20251218_000007_add_detailed_stats::Migration),
       Box::new(m20251218_000009_create_free_items::Migration),
+      Box::new(m20251222_000010_add_user_display_name::Migration),
+      Box::new(m20251222_000011_create_weekly_leaderboards::Migration),
+      Box::new(m20251222_000012_create_promos::Migration),
+      Box::new(m20251222_000013_create_codes::Migration),
+      Box::new(m20251222_000014_create_referrals::Migration),
+      Box::new(m20251222_000015_create_broadcasts::Migration),
+      Box::new(m20251222_000016_add_user_language::Migration),
+      Box::new(m20251222_000017_add_user_profile::Migration),
+      Box::new(m20251222_000018_create_audit_log::Migration),
+      Box::new(m20251222_000019_create_staff::Migration),
+      Box::new(m20251222_000020_widen_build_columns::Migration),
+      Box::new(m20251222_000021_create_leases::Migration),
+      Box::new(m20251222_000022_create_key_events::Migration),
+      Box::new(m20251222_000023_add_broadcast_cursor::Migration),
+      Box::new(m20251222_000024_create_weekly_closes::Migration),
     ]
   }
 }
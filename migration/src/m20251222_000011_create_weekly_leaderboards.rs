@@ -0,0 +1,86 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20251214_000001_create_users::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(WeeklyLeaderboards::Table)
+          .if_not_exists()
+          .col(
+            ColumnDef::new(WeeklyLeaderboards::Id)
+              .big_integer()
+              .not_null()
+              .auto_increment()
+              .primary_key(),
+          )
+          .col(
+            ColumnDef::new(WeeklyLeaderboards::WeekStart)
+              .date_time()
+              .not_null(),
+          )
+          .col(
+            ColumnDef::new(WeeklyLeaderboards::TgUserId)
+              .big_integer()
+              .not_null(),
+          )
+          .col(ColumnDef::new(WeeklyLeaderboards::Rank).integer().not_null())
+          .col(
+            ColumnDef::new(WeeklyLeaderboards::WeeklyXp)
+              .big_integer()
+              .not_null(),
+          )
+          .col(ColumnDef::new(WeeklyLeaderboards::DisplayName).string().null())
+          .col(
+            ColumnDef::new(WeeklyLeaderboards::CreatedAt)
+              .date_time()
+              .not_null(),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("fk_weekly_leaderboards_user")
+              .from(WeeklyLeaderboards::Table, WeeklyLeaderboards::TgUserId)
+              .to(Users::Table, Users::TgUserId)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .to_owned(),
+      )
+      .await?;
+
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_weekly_leaderboards_week_user")
+          .table(WeeklyLeaderboards::Table)
+          .col(WeeklyLeaderboards::WeekStart)
+          .col(WeeklyLeaderboards::TgUserId)
+          .unique()
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(WeeklyLeaderboards::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(DeriveIden)]
+pub enum WeeklyLeaderboards {
+  Table,
+  Id,
+  WeekStart,
+  TgUserId,
+  Rank,
+  WeeklyXp,
+  DisplayName,
+  CreatedAt,
+}
@@ -0,0 +1,97 @@
+use sea_orm_migration::{prelude::*, sea_orm::DatabaseBackend};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Promos::Table)
+          .if_not_exists()
+          .col(ColumnDef::new(Promos::Name).string().not_null().primary_key())
+          .col(ColumnDef::new(Promos::Title).string().not_null())
+          .col(ColumnDef::new(Promos::StartsAt).date_time().not_null())
+          .col(ColumnDef::new(Promos::EndsAt).date_time().not_null())
+          .col(
+            ColumnDef::new(Promos::LicenseType)
+              .string()
+              .not_null()
+              .default("trial"),
+          )
+          .col(ColumnDef::new(Promos::Days).integer().not_null())
+          .col(ColumnDef::new(Promos::MaxClaims).integer().null())
+          .col(
+            ColumnDef::new(Promos::Eligibility)
+              .string()
+              .not_null()
+              .default("all"),
+          )
+          .col(
+            ColumnDef::new(Promos::IsPaused)
+              .boolean()
+              .not_null()
+              .default(false),
+          )
+          .col(ColumnDef::new(Promos::CreatedAt).date_time().not_null())
+          .to_owned(),
+      )
+      .await?;
+
+    // postgres does not turn text into timestamps on insert
+    let date = |at: &str| -> SimpleExpr {
+      match manager.get_database_backend() {
+        DatabaseBackend::Postgres => {
+          Expr::val(at).cast_as(Alias::new("timestamp"))
+        }
+        _ => at.into(),
+      }
+    };
+
+    // the promo that used to be hardcoded, kept so its claims stay attached
+    let insert = Query::insert()
+      .into_table(Promos::Table)
+      .columns([
+        Promos::Name,
+        Promos::Title,
+        Promos::StartsAt,
+        Promos::EndsAt,
+        Promos::LicenseType,
+        Promos::Days,
+        Promos::CreatedAt,
+      ])
+      .values_panic([
+        "first_promo".into(),
+        "Get Free Trial".into(),
+        date("2025-12-14 13:00:00"),
+        date("2025-12-21 23:59:59"),
+        "trial".into(),
+        7.into(),
+        date("2025-12-14 13:00:00"),
+      ])
+      .to_owned();
+
+    manager.exec_stmt(insert).await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager.drop_table(Table::drop().table(Promos::Table).to_owned()).await
+  }
+}
+
+#[derive(DeriveIden)]
+pub enum Promos {
+  Table,
+  Name,
+  Title,
+  StartsAt,
+  EndsAt,
+  LicenseType,
+  Days,
+  MaxClaims,
+  Eligibility,
+  IsPaused,
+  CreatedAt,
+}
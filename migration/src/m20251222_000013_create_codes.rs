@@ -0,0 +1,113 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20251214_000001_create_users::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Codes::Table)
+          .if_not_exists()
+          .col(ColumnDef::new(Codes::Code).string().not_null().primary_key())
+          .col(
+            ColumnDef::new(Codes::LicenseType)
+              .string()
+              .not_null()
+              .default("pro"),
+          )
+          .col(ColumnDef::new(Codes::Days).integer().not_null())
+          .col(
+            ColumnDef::new(Codes::MaxRedemptions)
+              .integer()
+              .not_null()
+              .default(1),
+          )
+          .col(
+            ColumnDef::new(Codes::Redemptions).integer().not_null().default(0),
+          )
+          .col(ColumnDef::new(Codes::ExpiresAt).date_time().null())
+          .col(ColumnDef::new(Codes::Batch).string().not_null())
+          .col(ColumnDef::new(Codes::CreatedAt).date_time().not_null())
+          .to_owned(),
+      )
+      .await?;
+
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_codes_batch")
+          .table(Codes::Table)
+          .col(Codes::Batch)
+          .to_owned(),
+      )
+      .await?;
+
+    manager
+      .create_table(
+        Table::create()
+          .table(CodeRedemptions::Table)
+          .if_not_exists()
+          .col(ColumnDef::new(CodeRedemptions::Code).string().not_null())
+          .col(
+            ColumnDef::new(CodeRedemptions::TgUserId).big_integer().not_null(),
+          )
+          .col(
+            ColumnDef::new(CodeRedemptions::RedeemedAt).date_time().not_null(),
+          )
+          .primary_key(
+            Index::create()
+              .col(CodeRedemptions::Code)
+              .col(CodeRedemptions::TgUserId),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("fk_code_redemptions_code")
+              .from(CodeRedemptions::Table, CodeRedemptions::Code)
+              .to(Codes::Table, Codes::Code)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("fk_code_redemptions_user")
+              .from(CodeRedemptions::Table, CodeRedemptions::TgUserId)
+              .to(Users::Table, Users::TgUserId)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(CodeRedemptions::Table).to_owned())
+      .await?;
+    manager.drop_table(Table::drop().table(Codes::Table).to_owned()).await
+  }
+}
+
+#[derive(DeriveIden)]
+pub enum Codes {
+  Table,
+  Code,
+  LicenseType,
+  Days,
+  MaxRedemptions,
+  Redemptions,
+  ExpiresAt,
+  Batch,
+  CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub enum CodeRedemptions {
+  Table,
+  Code,
+  TgUserId,
+  RedeemedAt,
+}
@@ -0,0 +1,73 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20251214_000001_create_users::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Referrals::Table)
+          .if_not_exists()
+          .col(
+            ColumnDef::new(Referrals::RefereeId)
+              .big_integer()
+              .not_null()
+              .primary_key(),
+          )
+          .col(ColumnDef::new(Referrals::ReferrerId).big_integer().not_null())
+          .col(
+            ColumnDef::new(Referrals::RewardDays)
+              .integer()
+              .not_null()
+              .default(0),
+          )
+          .col(ColumnDef::new(Referrals::CreatedAt).date_time().not_null())
+          .col(ColumnDef::new(Referrals::RewardedAt).date_time().null())
+          .foreign_key(
+            ForeignKey::create()
+              .name("fk_referrals_referee")
+              .from(Referrals::Table, Referrals::RefereeId)
+              .to(Users::Table, Users::TgUserId)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .foreign_key(
+            ForeignKey::create()
+              .name("fk_referrals_referrer")
+              .from(Referrals::Table, Referrals::ReferrerId)
+              .to(Users::Table, Users::TgUserId)
+              .on_delete(ForeignKeyAction::Cascade),
+          )
+          .to_owned(),
+      )
+      .await?;
+
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_referrals_referrer")
+          .table(Referrals::Table)
+          .col(Referrals::ReferrerId)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager.drop_table(Table::drop().table(Referrals::Table).to_owned()).await
+  }
+}
+
+#[derive(DeriveIden)]
+pub enum Referrals {
+  Table,
+  RefereeId,
+  ReferrerId,
+  RewardDays,
+  CreatedAt,
+  RewardedAt,
+}
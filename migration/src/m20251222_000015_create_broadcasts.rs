@@ -0,0 +1,68 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Broadcasts::Table)
+          .if_not_exists()
+          .col(
+            ColumnDef::new(Broadcasts::Id)
+              .big_integer()
+              .not_null()
+              .auto_increment()
+              .primary_key(),
+          )
+          .col(ColumnDef::new(Broadcasts::Text).text().not_null())
+          .col(ColumnDef::new(Broadcasts::Audience).string().not_null())
+          .col(ColumnDef::new(Broadcasts::BuildVersion).string().null())
+          .col(
+            ColumnDef::new(Broadcasts::Status)
+              .string()
+              .not_null()
+              .default("pending"),
+          )
+          .col(
+            ColumnDef::new(Broadcasts::Total).integer().not_null().default(0),
+          )
+          .col(ColumnDef::new(Broadcasts::Sent).integer().not_null().default(0))
+          .col(
+            ColumnDef::new(Broadcasts::Blocked).integer().not_null().default(0),
+          )
+          .col(
+            ColumnDef::new(Broadcasts::Failed).integer().not_null().default(0),
+          )
+          .col(ColumnDef::new(Broadcasts::CreatedBy).big_integer().not_null())
+          .col(ColumnDef::new(Broadcasts::CreatedAt).date_time().not_null())
+          .col(ColumnDef::new(Broadcasts::FinishedAt).date_time().null())
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager.drop_table(Table::drop().table(Broadcasts::Table).to_owned()).await
+  }
+}
+
+#[derive(DeriveIden)]
+pub enum Broadcasts {
+  Table,
+  Id,
+  Text,
+  Audience,
+  BuildVersion,
+  Status,
+  Total,
+  Sent,
+  Blocked,
+  Failed,
+  CreatedBy,
+  CreatedAt,
+  FinishedAt,
+}
@@ -0,0 +1,31 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20251214_000001_create_users::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .alter_table(
+        Table::alter()
+          .table(Users::Table)
+          .add_column(ColumnDef::new(Alias::new("language")).string().null())
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .alter_table(
+        Table::alter()
+          .table(Users::Table)
+          .drop_column(Alias::new("language"))
+          .to_owned(),
+      )
+      .await
+  }
+}
@@ -0,0 +1,47 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20251214_000001_create_users::Users;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Telegram profile columns, sqlite alters one column at a time
+const COLUMNS: [&str; 4] =
+  ["username", "first_name", "language_code", "profile_synced_at"];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    for name in COLUMNS {
+      let mut column = ColumnDef::new(Alias::new(name));
+      if name == "profile_synced_at" {
+        column.date_time();
+      } else {
+        column.string();
+      }
+      manager
+        .alter_table(
+          Table::alter()
+            .table(Users::Table)
+            .add_column(column.null())
+            .to_owned(),
+        )
+        .await?;
+    }
+    Ok(())
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    for name in COLUMNS.into_iter().rev() {
+      manager
+        .alter_table(
+          Table::alter()
+            .table(Users::Table)
+            .drop_column(Alias::new(name))
+            .to_owned(),
+        )
+        .await?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,69 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(AuditLog::Table)
+          .if_not_exists()
+          .col(
+            ColumnDef::new(AuditLog::Id)
+              .big_integer()
+              .not_null()
+              .auto_increment()
+              .primary_key(),
+          )
+          .col(ColumnDef::new(AuditLog::Actor).string().not_null())
+          .col(ColumnDef::new(AuditLog::Action).string().not_null())
+          .col(ColumnDef::new(AuditLog::Target).string().not_null())
+          .col(ColumnDef::new(AuditLog::UserId).big_integer().null())
+          .col(ColumnDef::new(AuditLog::Before).text().null())
+          .col(ColumnDef::new(AuditLog::After).text().null())
+          .col(ColumnDef::new(AuditLog::CreatedAt).date_time().not_null())
+          .to_owned(),
+      )
+      .await?;
+
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_audit_log_target")
+          .table(AuditLog::Table)
+          .col(AuditLog::Target)
+          .to_owned(),
+      )
+      .await?;
+
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_audit_log_user")
+          .table(AuditLog::Table)
+          .col(AuditLog::UserId)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager.drop_table(Table::drop().table(AuditLog::Table).to_owned()).await
+  }
+}
+
+#[derive(DeriveIden)]
+pub enum AuditLog {
+  Table,
+  Id,
+  Actor,
+  Action,
+  Target,
+  UserId,
+  Before,
+  After,
+  CreatedAt,
+}
@@ -0,0 +1,45 @@
+use sea_orm_migration::{prelude::*, sea_orm::DatabaseBackend};
+
+use super::m20251214_000004_create_builds::Builds;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Columns read as `i64` but created as `integer`, only postgres enforces
+/// the width, sqlite keeps any integer in them
+const COLUMNS: [Builds; 2] = [Builds::Id, Builds::Downloads];
+
+async fn alter(manager: &SchemaManager<'_>, wide: bool) -> Result<(), DbErr> {
+  if manager.get_database_backend() != DatabaseBackend::Postgres {
+    return Ok(());
+  }
+
+  for column in COLUMNS {
+    let mut column = ColumnDef::new(column);
+    if wide {
+      column.big_integer();
+    } else {
+      column.integer();
+    }
+    manager
+      .alter_table(
+        Table::alter()
+          .table(Builds::Table)
+          .modify_column(column.not_null())
+          .to_owned(),
+      )
+      .await?;
+  }
+  Ok(())
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    alter(manager, true).await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    alter(manager, false).await
+  }
+}
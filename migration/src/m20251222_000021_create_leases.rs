@@ -0,0 +1,33 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(Leases::Table)
+          .if_not_exists()
+          .col(ColumnDef::new(Leases::Name).string().not_null().primary_key())
+          .col(ColumnDef::new(Leases::Holder).string().not_null())
+          .col(ColumnDef::new(Leases::ExpiresAt).date_time().not_null())
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager.drop_table(Table::drop().table(Leases::Table).to_owned()).await
+  }
+}
+
+#[derive(DeriveIden)]
+pub enum Leases {
+  Table,
+  Name,
+  Holder,
+  ExpiresAt,
+}
@@ -0,0 +1,55 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(KeyEvents::Table)
+          .if_not_exists()
+          .col(
+            ColumnDef::new(KeyEvents::Id)
+              .big_integer()
+              .not_null()
+              .auto_increment()
+              .primary_key(),
+          )
+          .col(ColumnDef::new(KeyEvents::Key).string().not_null())
+          .col(ColumnDef::new(KeyEvents::MachineId).string().not_null())
+          .col(ColumnDef::new(KeyEvents::Ip).string().null())
+          .col(ColumnDef::new(KeyEvents::Rejected).boolean().not_null())
+          .col(ColumnDef::new(KeyEvents::CreatedAt).date_time().not_null())
+          .to_owned(),
+      )
+      .await?;
+
+    manager
+      .create_index(
+        Index::create()
+          .name("idx_key_events_created_at")
+          .table(KeyEvents::Table)
+          .col(KeyEvents::CreatedAt)
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager.drop_table(Table::drop().table(KeyEvents::Table).to_owned()).await
+  }
+}
+
+#[derive(DeriveIden)]
+pub enum KeyEvents {
+  Table,
+  Id,
+  Key,
+  MachineId,
+  Ip,
+  Rejected,
+  CreatedAt,
+}
@@ -0,0 +1,33 @@
+use sea_orm_migration::prelude::*;
+
+use super::m20251222_000015_create_broadcasts::Broadcasts;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .alter_table(
+        Table::alter()
+          .table(Broadcasts::Table)
+          .add_column(
+            ColumnDef::new(Alias::new("last_recipient")).big_integer().null(),
+          )
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .alter_table(
+        Table::alter()
+          .table(Broadcasts::Table)
+          .drop_column(Alias::new("last_recipient"))
+          .to_owned(),
+      )
+      .await
+  }
+}
@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+  async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .create_table(
+        Table::create()
+          .table(WeeklyCloses::Table)
+          .if_not_exists()
+          .col(
+            ColumnDef::new(WeeklyCloses::WeekStart)
+              .date_time()
+              .not_null()
+              .primary_key(),
+          )
+          .col(ColumnDef::new(WeeklyCloses::ClosedAt).date_time().not_null())
+          .to_owned(),
+      )
+      .await
+  }
+
+  async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+    manager
+      .drop_table(Table::drop().table(WeeklyCloses::Table).to_owned())
+      .await
+  }
+}
+
+#[derive(DeriveIden)]
+pub enum WeeklyCloses {
+  Table,
+  WeekStart,
+  ClosedAt,
+}
@@ -0,0 +1,770 @@
+//! Encrypted database backups with a checksum manifest and retention.
+//! SQLite is backed up as a database file, Postgres as a logical dump.
+
+#[cfg(feature = "postgres")]
+mod postgres;
+
+use std::{
+  collections::HashSet,
+  fmt,
+  io::{self, Read, Write},
+  iter,
+  path::{Path, PathBuf},
+  str::FromStr,
+  sync::Arc,
+};
+
+use age::{scrypt, secrecy::SecretString, x25519};
+use migration::Migrator;
+use sea_orm::{DatabaseBackend, Statement, sqlx};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::prelude::*;
+
+const PREFIX: &str = "backup_";
+const TIME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+/// Encrypted database file or dump
+const EXTENSION: &str = ".db.age";
+/// Checksum manifest kept next to each backup
+const MANIFEST_EXTENSION: &str = ".json";
+
+/// Secret backups are encrypted with, from the `BACKUP_KEY` env variable.
+/// An age identity (`AGE-SECRET-KEY-1...`) is used as is, anything else
+/// as a passphrase.
+pub enum Key {
+  Identity(x25519::Identity),
+  Passphrase(SecretString),
+}
+
+impl fmt::Debug for Key {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Key::Identity(identity) => {
+        write!(f, "Key::Identity({})", identity.to_public())
+      }
+      Key::Passphrase(_) => write!(f, "Key::Passphrase(..)"),
+    }
+  }
+}
+
+impl FromStr for Key {
+  type Err = String;
+
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    let s = s.trim();
+    if s.is_empty() {
+      return Err("cannot be empty".into());
+    }
+    if s.to_ascii_uppercase().starts_with("AGE-SECRET-KEY-") {
+      return x25519::Identity::from_str(s)
+        .map(Key::Identity)
+        .map_err(|e| format!("invalid age identity: {e}"));
+    }
+    Ok(Key::Passphrase(SecretString::from(s.to_string())))
+  }
+}
+
+impl Key {
+  pub fn encrypt(&self, plain: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let encryptor = match self {
+      Key::Identity(identity) => {
+        let recipient = identity.to_public();
+        age::Encryptor::with_recipients(iter::once(&recipient as _))?
+      }
+      Key::Passphrase(passphrase) => {
+        age::Encryptor::with_user_passphrase(passphrase.clone())
+      }
+    };
+
+    let mut encrypted = Vec::with_capacity(plain.len() + 256);
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(plain)?;
+    writer.finish()?;
+    Ok(encrypted)
+  }
+
+  pub fn decrypt(&self, encrypted: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let decryptor = age::Decryptor::new_buffered(encrypted)?;
+    let mut reader = match self {
+      Key::Identity(identity) => {
+        decryptor.decrypt(iter::once(identity as &dyn age::Identity))?
+      }
+      Key::Passphrase(passphrase) => {
+        let identity = scrypt::Identity::new(passphrase.clone());
+        decryptor.decrypt(iter::once(&identity as &dyn age::Identity))?
+      }
+    };
+
+    let mut plain = Vec::new();
+    reader.read_to_end(&mut plain)?;
+    Ok(plain)
+  }
+}
+
+/// Number of backups kept per period, the newest backup of each of the
+/// latest `hourly` hours, `daily` days and `weekly` ISO weeks survives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+  pub hourly: u64,
+  pub daily: u64,
+  pub weekly: u64,
+}
+
+impl Retention {
+  /// Backups to keep out of `times`, the newest one is always kept
+  pub fn retained(&self, times: &[DateTime]) -> HashSet<DateTime> {
+    let mut sorted = times.to_vec();
+    sorted.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut keep: HashSet<DateTime> =
+      sorted.first().copied().into_iter().collect();
+    let mut keep_newest = |count: u64, period: fn(&DateTime) -> i64| {
+      let mut periods = HashSet::new();
+      for time in &sorted {
+        if periods.len() as u64 >= count {
+          break;
+        }
+        if periods.insert(period(time)) {
+          keep.insert(*time);
+        }
+      }
+    };
+
+    keep_newest(self.hourly, |t| t.and_utc().timestamp() / 3600);
+    keep_newest(self.daily, |t| t.date().num_days_from_ce() as i64);
+    keep_newest(self.weekly, |t| {
+      let week = t.iso_week();
+      week.year() as i64 * 100 + week.week() as i64
+    });
+
+    keep
+  }
+}
+
+/// Checksums and metadata written next to every backup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+  /// File name of the encrypted backup
+  pub file: String,
+  pub created_at: DateTime,
+  /// Size of the encrypted file in bytes
+  pub size: u64,
+  /// SHA-256 of the encrypted file
+  pub sha256: String,
+  /// SHA-256 of the decrypted database
+  pub db_sha256: String,
+  /// Latest migration applied to the backed up database
+  pub migration: Option<String>,
+  /// [`data_hash`] of the backed up tables, a backup is only taken when it
+  /// differs from the one of the latest backup
+  #[serde(default)]
+  pub data_hash: Option<String>,
+}
+
+impl Manifest {
+  pub fn path(dir: &Path, created_at: DateTime) -> PathBuf {
+    dir.join(format!(
+      "{}{}{}",
+      PREFIX,
+      created_at.format(TIME_FORMAT),
+      MANIFEST_EXTENSION
+    ))
+  }
+}
+
+/// Path of the encrypted backup taken at `created_at`
+pub fn path(dir: &Path, created_at: DateTime) -> PathBuf {
+  dir.join(format!("{}{}{}", PREFIX, created_at.format(TIME_FORMAT), EXTENSION))
+}
+
+/// Time a backup was taken, parsed from its file name
+fn parse_time(file_name: &str) -> Option<DateTime> {
+  let time = file_name.strip_prefix(PREFIX)?.strip_suffix(EXTENSION)?;
+  DateTime::parse_from_str(time, TIME_FORMAT).ok()
+}
+
+pub fn sha256(data: &[u8]) -> String {
+  format!("{:x}", Sha256::digest(data))
+}
+
+/// SHA-256 over every row of `tables`, missing tables are skipped
+pub async fn data_hash(
+  db: &DatabaseConnection,
+  tables: &[String],
+) -> anyhow::Result<String> {
+  let mut hasher = Sha256::new();
+  for table in tables {
+    let rows = match db.get_database_backend() {
+      #[cfg(feature = "postgres")]
+      DatabaseBackend::Postgres => postgres::rows(db, table).await?,
+      _ => sqlite_rows(db, table).await?,
+    };
+    let Some(rows) = rows else {
+      warn!("Backup table '{}' does not exist", table);
+      continue;
+    };
+
+    hasher.update(table.as_bytes());
+    hasher.update([0]);
+    for row in rows {
+      hasher.update(row.as_bytes());
+      hasher.update([b'\n']);
+    }
+  }
+  Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Rows of `table` as text in a stable order, `None` if it does not exist
+async fn sqlite_rows(
+  db: &DatabaseConnection,
+  table: &str,
+) -> anyhow::Result<Option<Vec<String>>> {
+  let columns: Vec<String> = db
+    .query_all(Statement::from_sql_and_values(
+      DatabaseBackend::Sqlite,
+      "SELECT name FROM pragma_table_info(?)",
+      [table.into()],
+    ))
+    .await?
+    .iter()
+    .map(|row| row.try_get_by_index(0))
+    .collect::<std::result::Result<_, _>>()?;
+  if columns.is_empty() {
+    return Ok(None);
+  }
+
+  // quote() keeps NULL, text and blobs apart
+  let row = columns
+    .iter()
+    .map(|c| format!("quote(\"{}\")", c))
+    .collect::<Vec<_>>()
+    .join(" || ',' || ");
+  let rows = db
+    .query_all(Statement::from_string(
+      DatabaseBackend::Sqlite,
+      format!("SELECT {} FROM \"{}\" ORDER BY rowid", row, table),
+    ))
+    .await?
+    .iter()
+    .map(|row| row.try_get_by_index(0))
+    .collect::<std::result::Result<_, _>>()?;
+  Ok(Some(rows))
+}
+
+/// Snapshot the database into `dir`, encrypt it and write its manifest
+/// with the [`data_hash`] of `tables`. The plaintext snapshot never
+/// outlives this call.
+pub async fn create(
+  db: &DatabaseConnection,
+  key: Arc<Key>,
+  dir: &Path,
+  tables: &[String],
+) -> anyhow::Result<(PathBuf, Manifest)> {
+  fs::create_dir_all(dir)
+    .await
+    .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+  let data_hash = data_hash(db, tables).await?;
+  let created_at = Utc::now().naive_utc();
+  let path = path(dir, created_at);
+  let plain = match db.get_database_backend() {
+    #[cfg(feature = "postgres")]
+    DatabaseBackend::Postgres => postgres::dump(db).await?,
+    _ => {
+      let snapshot = path.with_extension("tmp");
+      let _ = fs::remove_file(&snapshot).await;
+
+      let query = format!(
+        "VACUUM INTO '{}'",
+        snapshot.to_string_lossy().replace('\'', "''")
+      );
+      db.execute(Statement::from_string(DatabaseBackend::Sqlite, query))
+        .await?;
+
+      let plain = fs::read(&snapshot).await;
+      let _ = fs::remove_file(&snapshot).await;
+      plain?
+    }
+  };
+
+  // scrypt passphrases take about a second, keep it off the runtime
+  let (encrypted, db_sha256) =
+    tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+      Ok((key.encrypt(&plain)?, sha256(&plain)))
+    })
+    .await??;
+
+  let manifest = Manifest {
+    file: path.file_name().unwrap_or_default().to_string_lossy().into(),
+    created_at,
+    size: encrypted.len() as u64,
+    sha256: sha256(&encrypted),
+    db_sha256,
+    migration: latest_migration(db).await,
+    data_hash: Some(data_hash),
+  };
+
+  fs::write(&path, &encrypted).await?;
+  fs::write(Manifest::path(dir, created_at), json::to_vec_pretty(&manifest)?)
+    .await?;
+
+  Ok((path, manifest))
+}
+
+async fn latest_migration(db: &impl ConnectionTrait) -> Option<String> {
+  let query = Statement::from_string(
+    db.get_database_backend(),
+    "SELECT version FROM seaql_migrations ORDER BY version DESC LIMIT 1",
+  );
+  let row = db.query_one(query).await.ok()??;
+  row.try_get("", "version").ok()
+}
+
+/// Times of the backups in `dir`
+async fn list(dir: &Path) -> io::Result<Vec<DateTime>> {
+  let mut times = Vec::new();
+  let mut entries = fs::read_dir(dir).await?;
+  while let Some(entry) = entries.next_entry().await? {
+    if let Some(time) = entry.file_name().to_str().and_then(parse_time) {
+      times.push(time);
+    }
+  }
+  Ok(times)
+}
+
+/// Manifest of the newest backup in `dir`, survives restarts unlike any
+/// in-memory state
+pub async fn latest(dir: &Path) -> Option<Manifest> {
+  let time = list(dir).await.ok()?.into_iter().max()?;
+  let manifest = fs::read(Manifest::path(dir, time)).await.ok()?;
+  json::from_slice(&manifest).ok()
+}
+
+/// Delete backups (and their manifests) that fall out of `retention`,
+/// returns the number of deleted backups
+pub async fn prune(dir: &Path, retention: Retention) -> anyhow::Result<usize> {
+  let times = list(dir).await?;
+  let keep = retention.retained(&times);
+  let mut deleted = 0;
+  for time in times.into_iter().filter(|time| !keep.contains(time)) {
+    fs::remove_file(path(dir, time)).await?;
+    let _ = fs::remove_file(Manifest::path(dir, time)).await;
+    deleted += 1;
+  }
+  Ok(deleted)
+}
+
+/// Header every plain SQLite database starts with
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Counts compared between the live database and a backup before restoring,
+/// `{now}` is replaced with the current time
+const COUNTS: [(&str, &str); 5] = [
+  ("Licenses", "SELECT COUNT(*) FROM licenses"),
+  (
+    "Active licenses",
+    "SELECT COUNT(*) FROM licenses \
+    WHERE is_blocked = false AND expires_at > '{now}'",
+  ),
+  ("Blocked licenses", "SELECT COUNT(*) FROM licenses WHERE is_blocked = true"),
+  ("Users", "SELECT COUNT(*) FROM users"),
+  ("Builds", "SELECT COUNT(*) FROM builds"),
+];
+
+/// Where a checked backup waits to be restored
+#[derive(Debug, Clone)]
+pub enum Staged {
+  /// Scratch SQLite database
+  File(PathBuf),
+  /// Schema next to the live one
+  #[cfg(feature = "postgres")]
+  Schema(String),
+}
+
+/// Backup decrypted into a scratch database that passed the integrity and
+/// migration checks, applied with [`restore`] or dropped with [`discard`]
+#[derive(Debug, Clone)]
+pub struct Restore {
+  pub staged: Staged,
+  /// Latest migration of the backup before it was brought up to date
+  pub migration: Option<String>,
+  /// Migrations applied to the backup to match this version
+  pub migrated: usize,
+  /// `(what, live, backup)` for each of [`COUNTS`]
+  pub counts: Vec<(&'static str, i64, i64)>,
+}
+
+impl Restore {
+  pub fn summary(&self) -> String {
+    let mut text = format!(
+      "Migration: <code>{}</code>",
+      self.migration.as_deref().unwrap_or("none")
+    );
+    if self.migrated > 0 {
+      text.push_str(&format!(" (+{} on restore)", self.migrated));
+    }
+    for (what, live, backup) in &self.counts {
+      let diff = backup - live;
+      let diff =
+        if diff == 0 { String::new() } else { format!(" ({:+})", diff) };
+      text.push_str(&format!("\n{}: {} → {}{}", what, live, backup, diff));
+    }
+    text
+  }
+}
+
+async fn counts(db: &impl ConnectionTrait) -> anyhow::Result<Vec<i64>> {
+  let now = Utc::now().naive_utc().format("%Y-%m-%d %H:%M:%S").to_string();
+  let mut counts = Vec::new();
+  for (_, query) in COUNTS {
+    let query = Statement::from_string(
+      db.get_database_backend(),
+      query.replace("{now}", &now),
+    );
+    let row = db.query_one(query).await?.context("Empty count")?;
+    counts.push(row.try_get_by_index(0)?);
+  }
+  Ok(counts)
+}
+
+/// Backup staged for a restore, with its original migration, the number of
+/// migrations applied to it and its [`COUNTS`]
+type Checked = (Staged, Option<String>, usize, Vec<i64>);
+
+/// Decrypt `data` (a plain SQLite file or dump is accepted as well), stage
+/// it next to the live database, check its integrity and bring it up to the
+/// current migration
+pub async fn prepare(
+  db: &DatabaseConnection,
+  key: Arc<Key>,
+  data: Vec<u8>,
+  dir: &Path,
+) -> anyhow::Result<Restore> {
+  let plain = if data.starts_with(SQLITE_HEADER) || data.starts_with(b"{") {
+    data
+  } else {
+    tokio::task::spawn_blocking(move || key.decrypt(&data))
+      .await?
+      .context("Not a backup made with this BACKUP_KEY")?
+  };
+
+  let live = counts(db).await?;
+  let (staged, migration, migrated, backup) = match db.get_database_backend() {
+    #[cfg(feature = "postgres")]
+    DatabaseBackend::Postgres => postgres::stage(db, plain).await?,
+    _ => stage_file(plain, dir).await?,
+  };
+
+  let counts = COUNTS
+    .iter()
+    .zip(live.into_iter().zip(backup))
+    .map(|((what, _), (live, backup))| (*what, live, backup))
+    .collect();
+  Ok(Restore { staged, migration, migrated, counts })
+}
+
+async fn stage_file(plain: Vec<u8>, dir: &Path) -> anyhow::Result<Checked> {
+  anyhow::ensure!(plain.starts_with(SQLITE_HEADER), "Not a SQLite backup");
+
+  fs::create_dir_all(dir).await?;
+  let path = dir.join(format!("restore_{}.db", Utc::now().format(TIME_FORMAT)));
+  fs::write(&path, plain).await?;
+
+  match check(&path).await {
+    Ok((migration, migrated, counts)) => {
+      Ok((Staged::File(path), migration, migrated, counts))
+    }
+    Err(e) => {
+      let _ = fs::remove_file(&path).await;
+      Err(e)
+    }
+  }
+}
+
+async fn check(
+  path: &Path,
+) -> anyhow::Result<(Option<String>, usize, Vec<i64>)> {
+  let url = format!("sqlite:{}?mode=rw", path.display());
+  let backup = Database::connect(url).await?;
+
+  let result = async {
+    let query =
+      Statement::from_string(DatabaseBackend::Sqlite, "PRAGMA integrity_check");
+    let row =
+      backup.query_one(query).await?.context("Empty integrity check")?;
+    let status: String = row.try_get_by_index(0)?;
+    anyhow::ensure!(status == "ok", "Integrity check failed: {}", status);
+
+    let migration = latest_migration(&backup).await;
+    // fails on migrations unknown to this version
+    let pending = Migrator::get_pending_migrations(&backup)
+      .await
+      .context("Backup is from a newer version")?;
+    Migrator::up(&backup, None).await?;
+
+    Ok((migration, pending.len(), counts(&backup).await?))
+  }
+  .await;
+
+  let _ = backup.close().await;
+  result
+}
+
+/// Snapshot the live database into `dir`, then replace the contents of
+/// every table with the prepared backup in a single transaction.
+/// Returns the path of the safety snapshot.
+pub async fn restore(
+  db: &DatabaseConnection,
+  key: Arc<Key>,
+  restore: &Restore,
+  dir: &Path,
+  tables: &[String],
+) -> anyhow::Result<PathBuf> {
+  let (snapshot, _) = create(db, key, dir, tables)
+    .await
+    .context("Failed to take the pre-restore snapshot")?;
+
+  match &restore.staged {
+    Staged::File(path) => {
+      swap_file(db, path).await?;
+      let _ = fs::remove_file(path).await;
+    }
+    #[cfg(feature = "postgres")]
+    Staged::Schema(schema) => postgres::swap(db, schema).await?,
+  }
+  Ok(snapshot)
+}
+
+/// Drop a prepared backup that is not going to be restored
+pub async fn discard(db: &DatabaseConnection, restore: &Restore) {
+  match &restore.staged {
+    Staged::File(path) => {
+      let _ = fs::remove_file(path).await;
+    }
+    #[cfg(feature = "postgres")]
+    Staged::Schema(schema) => postgres::drop_schema(db, schema).await,
+  }
+  #[cfg(not(feature = "postgres"))]
+  let _ = db;
+}
+
+/// Replace every table of the live database with the one in `path`
+async fn swap_file(db: &DatabaseConnection, path: &Path) -> anyhow::Result<()> {
+  // ATTACH is per connection and not allowed inside a transaction,
+  // so the whole swap runs on one pooled connection
+  let mut conn = db.get_sqlite_connection_pool().acquire().await?;
+  let foreign_keys: i64 =
+    sqlx::query_scalar("PRAGMA foreign_keys").fetch_one(&mut *conn).await?;
+  sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+  sqlx::query("ATTACH DATABASE ? AS backup")
+    .bind(path.to_string_lossy())
+    .execute(&mut *conn)
+    .await?;
+
+  let swap = async {
+    sqlx::query("BEGIN IMMEDIATE").execute(&mut *conn).await?;
+    let tables: Vec<String> = sqlx::query_scalar(
+      "SELECT name FROM main.sqlite_master \
+      WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for table in tables {
+      let columns: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM pragma_table_info(?, 'main')")
+          .bind(&table)
+          .fetch_all(&mut *conn)
+          .await?;
+      let columns = columns
+        .iter()
+        .map(|c| format!("\"{}\"", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+      sqlx::query(&format!("DELETE FROM main.\"{}\"", table))
+        .execute(&mut *conn)
+        .await?;
+      sqlx::query(&format!(
+        "INSERT INTO main.\"{table}\" ({columns}) \
+        SELECT {columns} FROM backup.\"{table}\""
+      ))
+      .execute(&mut *conn)
+      .await?;
+    }
+    sqlx::query("COMMIT").execute(&mut *conn).await?;
+    anyhow::Ok(())
+  }
+  .await;
+
+  if swap.is_err() {
+    let _ = sqlx::query("ROLLBACK").execute(&mut *conn).await;
+  }
+  let _ = sqlx::query("DETACH DATABASE backup").execute(&mut *conn).await;
+  let _ = sqlx::query(&format!("PRAGMA foreign_keys = {}", foreign_keys))
+    .execute(&mut *conn)
+    .await;
+  swap
+}
+
+#[cfg(test)]
+mod tests {
+  use age::secrecy::ExposeSecret;
+
+  use super::*;
+  use crate::{
+    config::Config,
+    entity::{LicenseType, license},
+  };
+
+  fn at(s: &str) -> DateTime {
+    DateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+  }
+
+  #[test]
+  fn test_key_roundtrip() {
+    let identity = x25519::Identity::generate();
+    let key: Key = identity.to_string().expose_secret().parse().unwrap();
+
+    let encrypted = key.encrypt(b"SQLite format 3").unwrap();
+    assert_ne!(encrypted, b"SQLite format 3");
+    assert_eq!(key.decrypt(&encrypted).unwrap(), b"SQLite format 3");
+
+    let other: Key =
+      x25519::Identity::generate().to_string().expose_secret().parse().unwrap();
+    assert!(other.decrypt(&encrypted).is_err());
+  }
+
+  #[test]
+  fn test_retention() {
+    let times = [
+      at("2025-12-01 10:00"),
+      at("2025-12-08 10:00"),
+      at("2025-12-14 09:00"),
+      at("2025-12-14 10:00"),
+      at("2025-12-15 08:00"),
+      at("2025-12-15 09:00"),
+      at("2025-12-15 09:30"),
+      at("2025-12-15 10:00"),
+    ];
+    let retention = Retention { hourly: 2, daily: 2, weekly: 2 };
+
+    let mut keep: Vec<_> = retention.retained(&times).into_iter().collect();
+    keep.sort();
+    assert_eq!(
+      keep,
+      vec![
+        // newest of the previous day and ISO week
+        at("2025-12-14 10:00"),
+        at("2025-12-15 09:30"),
+        at("2025-12-15 10:00"),
+      ]
+    );
+
+    assert_eq!(
+      Retention { hourly: 0, daily: 0, weekly: 0 }.retained(&times),
+      HashSet::from([at("2025-12-15 10:00")])
+    );
+  }
+
+  #[tokio::test]
+  async fn test_create_and_prune() {
+    let dir = tempfile::tempdir().unwrap();
+    // VACUUM INTO writes through the VFS of the source, so no :memory:
+    let url = format!("sqlite:{}?mode=rwc", dir.path().join("db").display());
+    let db = Database::connect(url).await.unwrap();
+    db.execute_unprepared(
+      "CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('x')",
+    )
+    .await
+    .unwrap();
+    let backups = dir.path().join("backups");
+    let key = Arc::new(Key::Identity(x25519::Identity::generate()));
+
+    let tables = ["t".to_string()];
+    let (path, manifest) =
+      create(&db, key.clone(), &backups, &tables).await.unwrap();
+    let encrypted = std::fs::read(&path).unwrap();
+    assert_eq!(manifest.sha256, sha256(&encrypted));
+
+    let plain = key.decrypt(&encrypted).unwrap();
+    assert!(plain.starts_with(b"SQLite format 3"));
+    assert_eq!(manifest.db_sha256, sha256(&plain));
+
+    let written: Manifest = json::from_slice(
+      &std::fs::read(Manifest::path(&backups, manifest.created_at)).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(written, manifest);
+
+    // an older backup beyond every period is deleted with its manifest
+    let old = at("2020-01-01 00:00");
+    std::fs::write(self::path(&backups, old), b"").unwrap();
+    std::fs::write(Manifest::path(&backups, old), b"{}").unwrap();
+    let retention = Retention { hourly: 1, daily: 1, weekly: 1 };
+    assert_eq!(prune(&backups, retention).await.unwrap(), 1);
+    assert_eq!(std::fs::read_dir(&backups).unwrap().count(), 2);
+  }
+
+  #[tokio::test]
+  async fn test_data_hash() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    db.execute_unprepared(
+      "CREATE TABLE a (v TEXT); CREATE TABLE b (v TEXT); \
+      INSERT INTO a VALUES (NULL)",
+    )
+    .await
+    .unwrap();
+    let tables = ["a".to_string(), "missing".to_string()];
+
+    let hash = data_hash(&db, &tables).await.unwrap();
+    db.execute_unprepared("INSERT INTO b VALUES ('x')").await.unwrap();
+    assert_eq!(data_hash(&db, &tables).await.unwrap(), hash);
+
+    db.execute_unprepared("UPDATE a SET v = 'NULL'").await.unwrap();
+    assert_ne!(data_hash(&db, &tables).await.unwrap(), hash);
+  }
+
+  #[tokio::test]
+  async fn test_prepare_and_restore() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite:{}?mode=rwc", dir.path().join("db").display());
+    let db = Database::connect(url).await.unwrap();
+    Migrator::up(&db, None).await.unwrap();
+    let backups = dir.path().join("backups");
+    let key = Arc::new(Key::Identity(x25519::Identity::generate()));
+
+    let licenses = crate::sv::License::new(&db);
+    let kept = licenses.create(1, LicenseType::Pro, 30).await.unwrap();
+    let tables = Config::default().backup_tables;
+    let (path, _) = create(&db, key.clone(), &backups, &tables).await.unwrap();
+    licenses.create(2, LicenseType::Pro, 30).await.unwrap();
+
+    let data = std::fs::read(&path).unwrap();
+    let prepared = prepare(&db, key.clone(), data, &backups).await.unwrap();
+    assert_eq!(prepared.migrated, 0);
+    assert_eq!(prepared.counts[0], ("Licenses", 2, 1));
+
+    let snapshot =
+      restore(&db, key.clone(), &prepared, &backups, &tables).await.unwrap();
+    assert!(snapshot.exists());
+    let Staged::File(staged) = &prepared.staged else { unreachable!() };
+    assert!(!staged.exists());
+    let all = license::Entity::find().all(&db).await.unwrap();
+    assert_eq!(all, vec![kept]);
+
+    let garbage = b"not a backup".to_vec();
+    assert!(prepare(&db, key, garbage, &backups).await.is_err());
+  }
+
+  #[test]
+  fn test_parse_time() {
+    let time = at("2025-12-15 10:00");
+    let path = path(Path::new("."), time);
+    let name = path.file_name().unwrap().to_str().unwrap();
+    assert_eq!(parse_time(name), Some(time));
+    assert_eq!(parse_time("backup_2025-12-15_10-00-00.json"), None);
+  }
+}
@@ -0,0 +1,427 @@
+//! Postgres backups are a JSON dump of every table. Restores load the dump
+//! into a staging schema, migrate it there and copy it over the live
+//! tables in one transaction.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use migration::Migrator;
+use sea_orm::{DatabaseBackend, SqlxPostgresConnector, Statement, Value, sqlx};
+use serde::{Deserialize, Serialize};
+
+use super::{Checked, SQLITE_HEADER, Staged, counts, latest_migration};
+use crate::prelude::*;
+
+const FORMAT: &str = "license-dump/1";
+/// Managed by the migrator of each schema, never copied
+const MIGRATIONS: &str = "seaql_migrations";
+
+#[derive(Serialize, Deserialize)]
+struct Dump {
+  format: String,
+  migration: Option<String>,
+  /// Rows of each table as a JSON array
+  tables: BTreeMap<String, json::Value>,
+}
+
+/// First column of every row as text
+async fn strings(
+  db: &impl ConnectionTrait,
+  sql: &str,
+  values: Vec<Value>,
+) -> anyhow::Result<Vec<String>> {
+  let query =
+    Statement::from_sql_and_values(DatabaseBackend::Postgres, sql, values);
+  let rows = db.query_all(query).await?;
+  Ok(
+    rows
+      .iter()
+      .map(|row| row.try_get_by_index(0))
+      .collect::<std::result::Result<_, _>>()?,
+  )
+}
+
+async fn execute(db: &impl ConnectionTrait, sql: String) -> Result<()> {
+  db.execute(Statement::from_string(DatabaseBackend::Postgres, sql)).await?;
+  Ok(())
+}
+
+async fn truncate(
+  db: &impl ConnectionTrait,
+  tables: &[String],
+) -> anyhow::Result<()> {
+  if tables.is_empty() {
+    return Ok(());
+  }
+  let all = tables
+    .iter()
+    .map(|table| format!("\"{}\"", table))
+    .collect::<Vec<_>>()
+    .join(", ");
+  Ok(execute(db, format!("TRUNCATE {}", all)).await?)
+}
+
+/// Tables of the current schema, referenced tables before the ones
+/// referencing them
+async fn tables(db: &impl ConnectionTrait) -> anyhow::Result<Vec<String>> {
+  let mut left = strings(
+    db,
+    "SELECT table_name::text FROM information_schema.tables \
+    WHERE table_schema = current_schema() AND table_type = 'BASE TABLE' \
+    ORDER BY table_name",
+    vec![],
+  )
+  .await?;
+
+  let query = Statement::from_string(
+    DatabaseBackend::Postgres,
+    "SELECT child.relname::text, parent.relname::text FROM pg_constraint c \
+    JOIN pg_class child ON child.oid = c.conrelid \
+    JOIN pg_class parent ON parent.oid = c.confrelid \
+    WHERE c.contype = 'f' AND c.connamespace = current_schema()::regnamespace",
+  );
+  let mut parents: HashMap<String, HashSet<String>> = HashMap::new();
+  for row in db.query_all(query).await? {
+    let (child, parent): (String, String) = row.try_get_many_by_index()?;
+    if child != parent {
+      parents.entry(child).or_default().insert(parent);
+    }
+  }
+
+  let mut ordered: Vec<String> = Vec::with_capacity(left.len());
+  while !left.is_empty() {
+    let (ready, rest): (Vec<_>, Vec<_>) = left.into_iter().partition(|table| {
+      parents
+        .get(table)
+        .is_none_or(|parents| parents.iter().all(|p| ordered.contains(p)))
+    });
+    // a reference cycle, the inserts report it if it matters
+    if ready.is_empty() {
+      ordered.extend(rest);
+      break;
+    }
+    ordered.extend(ready);
+    left = rest;
+  }
+  Ok(ordered)
+}
+
+/// Rows of `table` as text in a stable order, `None` if it does not exist
+pub(super) async fn rows(
+  db: &DatabaseConnection,
+  table: &str,
+) -> anyhow::Result<Option<Vec<String>>> {
+  let exists = strings(
+    db,
+    "SELECT table_name::text FROM information_schema.tables \
+    WHERE table_schema = current_schema() AND table_name = $1",
+    vec![table.into()],
+  )
+  .await?;
+  if exists.is_empty() {
+    return Ok(None);
+  }
+
+  let sql = format!("SELECT t::text FROM \"{}\" t ORDER BY 1", table);
+  Ok(Some(strings(db, &sql, vec![]).await?))
+}
+
+pub(super) async fn dump(db: &DatabaseConnection) -> anyhow::Result<Vec<u8>> {
+  // one snapshot for all tables
+  let txn = db.begin().await?;
+  execute(&txn, "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ".into())
+    .await?;
+
+  let mut tables = BTreeMap::new();
+  for table in self::tables(&txn).await? {
+    let sql =
+      format!("SELECT COALESCE(json_agg(t), '[]')::text FROM \"{}\" t", table);
+    let rows = strings(&txn, &sql, vec![]).await?;
+    let rows = rows.first().context("Empty table dump")?;
+    tables.insert(table, json::from_str(rows)?);
+  }
+  let migration = latest_migration(&txn).await;
+  txn.commit().await?;
+
+  let dump = Dump { format: FORMAT.into(), migration, tables };
+  Ok(json::to_vec(&dump)?)
+}
+
+/// Load `plain` into a new schema, migrated to the current version
+pub(super) async fn stage(
+  db: &DatabaseConnection,
+  plain: Vec<u8>,
+) -> anyhow::Result<Checked> {
+  anyhow::ensure!(
+    !plain.starts_with(SQLITE_HEADER),
+    "SQLite backups cannot be restored into Postgres"
+  );
+  let dump: Dump = json::from_slice(&plain).context("Not a database dump")?;
+  anyhow::ensure!(dump.format == FORMAT, "Unknown dump format {}", dump.format);
+
+  let migrations: Vec<String> =
+    Migrator::migrations().iter().map(|m| m.name().to_string()).collect();
+  let migration = dump.migration.clone().context("Backup has no migrations")?;
+  let applied = migrations
+    .iter()
+    .position(|m| *m == migration)
+    .context("Backup is from a newer version")?
+    + 1;
+
+  let schema = format!("restore_{}", Utc::now().format("%Y%m%d_%H%M%S"));
+  execute(db, format!("CREATE SCHEMA \"{}\"", schema)).await?;
+
+  match load(db, &schema, dump, applied).await {
+    Ok(counts) => Ok((
+      Staged::Schema(schema),
+      Some(migration),
+      migrations.len() - applied,
+      counts,
+    )),
+    Err(e) => {
+      drop_schema(db, &schema).await;
+      Err(e)
+    }
+  }
+}
+
+/// Connection to the same database that only sees `schema`
+async fn connect(
+  db: &DatabaseConnection,
+  schema: &str,
+) -> anyhow::Result<DatabaseConnection> {
+  let options = db
+    .get_postgres_connection_pool()
+    .connect_options()
+    .as_ref()
+    .clone()
+    .options([("search_path", schema)]);
+  let pool = sqlx::postgres::PgPoolOptions::new()
+    .max_connections(1)
+    .connect_with(options)
+    .await?;
+  Ok(SqlxPostgresConnector::from_sqlx_postgres_pool(pool))
+}
+
+/// Recreate the schema of the dump in `schema`, fill it and migrate it up,
+/// returns its [`super::COUNTS`]
+async fn load(
+  db: &DatabaseConnection,
+  schema: &str,
+  mut dump: Dump,
+  applied: usize,
+) -> anyhow::Result<Vec<i64>> {
+  let staging = connect(db, schema).await?;
+
+  let result = async {
+    Migrator::up(&staging, Some(applied as u32)).await?;
+
+    // rows seeded by the migrations are in the dump as well
+    dump.tables.remove(MIGRATIONS);
+    let order: Vec<String> = tables(&staging)
+      .await?
+      .into_iter()
+      .filter(|table| table != MIGRATIONS)
+      .collect();
+    truncate(&staging, &order).await?;
+
+    for table in order {
+      let Some(rows) = dump.tables.remove(&table) else { continue };
+      let sql = format!(
+        "INSERT INTO \"{table}\" \
+        SELECT * FROM json_populate_recordset(NULL::\"{table}\", $1::json)"
+      );
+      staging
+        .execute(Statement::from_sql_and_values(
+          DatabaseBackend::Postgres,
+          sql,
+          [rows.to_string().into()],
+        ))
+        .await
+        .with_context(|| format!("Failed to load {}", table))?;
+    }
+    if let Some(table) = dump.tables.keys().next() {
+      anyhow::bail!("Backup has unknown table {}", table);
+    }
+
+    Migrator::up(&staging, None).await?;
+    counts(&staging).await
+  }
+  .await;
+
+  let _ = staging.close().await;
+  result
+}
+
+pub(super) async fn drop_schema(db: &DatabaseConnection, schema: &str) {
+  let sql = format!("DROP SCHEMA IF EXISTS \"{}\" CASCADE", schema);
+  if let Err(e) = execute(db, sql).await {
+    warn!("Failed to drop staging schema {}: {}", schema, e);
+  }
+}
+
+/// Replace every live table with its copy in `schema` in one transaction,
+/// then drop `schema`
+pub(super) async fn swap(
+  db: &DatabaseConnection,
+  schema: &str,
+) -> anyhow::Result<()> {
+  let txn = db.begin().await?;
+  let tables: Vec<String> = tables(&txn)
+    .await?
+    .into_iter()
+    .filter(|table| table != MIGRATIONS)
+    .collect();
+
+  truncate(&txn, &tables).await?;
+
+  for table in &tables {
+    let columns = strings(
+      &txn,
+      "SELECT column_name::text FROM information_schema.columns \
+      WHERE table_schema = current_schema() AND table_name = $1 \
+      ORDER BY ordinal_position",
+      vec![table.into()],
+    )
+    .await?
+    .iter()
+    .map(|column| format!("\"{}\"", column))
+    .collect::<Vec<_>>()
+    .join(", ");
+    execute(
+      &txn,
+      format!(
+        "INSERT INTO \"{table}\" ({columns}) \
+        SELECT {columns} FROM \"{schema}\".\"{table}\""
+      ),
+    )
+    .await?;
+
+    // serial ids continue after the restored rows
+    let serials = strings(
+      &txn,
+      "SELECT column_name::text FROM information_schema.columns \
+      WHERE table_schema = current_schema() AND table_name = $1 \
+      AND column_default LIKE 'nextval(%'",
+      vec![table.into()],
+    )
+    .await?;
+    for column in serials {
+      execute(
+        &txn,
+        format!(
+          "SELECT setval(pg_get_serial_sequence('\"{table}\"', '{column}'), \
+          COALESCE(MAX(\"{column}\"), 0) + 1, false) FROM \"{table}\""
+        ),
+      )
+      .await?;
+    }
+  }
+  txn.commit().await?;
+
+  drop_schema(db, schema).await;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use age::x25519;
+  use sea_orm::ConnectOptions;
+
+  use super::*;
+  use crate::{
+    backup::{self, Key},
+    config::Config,
+    entity::{LicenseType, license},
+    sv,
+  };
+
+  /// Connection to a fresh `schema` of `TEST_POSTGRES_URL`, the tests are
+  /// skipped without it
+  async fn setup(schema: &str) -> Option<DatabaseConnection> {
+    let Ok(url) = std::env::var("TEST_POSTGRES_URL") else {
+      eprintln!("TEST_POSTGRES_URL is not set, skipping");
+      return None;
+    };
+
+    let admin = Database::connect(&url).await.unwrap();
+    drop_schema(&admin, schema).await;
+    execute(&admin, format!("CREATE SCHEMA \"{}\"", schema)).await.unwrap();
+    let _ = admin.close().await;
+
+    let mut options = ConnectOptions::new(url);
+    options.set_schema_search_path(schema);
+    Some(Database::connect(options).await.unwrap())
+  }
+
+  #[tokio::test]
+  async fn test_migrations() {
+    let Some(db) = setup("test_migrations").await else { return };
+
+    Migrator::up(&db, None).await.unwrap();
+    Migrator::down(&db, None).await.unwrap();
+    Migrator::up(&db, None).await.unwrap();
+    assert!(Migrator::get_pending_migrations(&db).await.unwrap().is_empty());
+    assert!(tables(&db).await.unwrap().contains(&"licenses".to_string()));
+  }
+
+  #[tokio::test]
+  async fn test_backup_and_restore() {
+    let Some(db) = setup("test_backup").await else { return };
+    Migrator::up(&db, None).await.unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let key = Arc::new(Key::Identity(x25519::Identity::generate()));
+    let tables = Config::default().backup_tables;
+
+    // users are referenced by licenses, so they load first
+    let order = self::tables(&db).await.unwrap();
+    let position = |t: &str| order.iter().position(|o| o == t).unwrap();
+    assert!(position("users") < position("licenses"));
+
+    let licenses = sv::License::new(&db);
+    let builds = sv::Build::new(&db);
+    let kept = licenses.create(1, LicenseType::Pro, 30).await.unwrap();
+    builds.create("1.0".into(), "builds/a".into(), None).await.unwrap();
+    let hash = backup::data_hash(&db, &tables).await.unwrap();
+    let (path, manifest) =
+      backup::create(&db, key.clone(), dir.path(), &tables).await.unwrap();
+    assert_eq!(manifest.data_hash.as_deref(), Some(hash.as_str()));
+
+    licenses.create(2, LicenseType::Trial, 7).await.unwrap();
+    assert_ne!(backup::data_hash(&db, &tables).await.unwrap(), hash);
+
+    let data = std::fs::read(&path).unwrap();
+    let prepared =
+      backup::prepare(&db, key.clone(), data, dir.path()).await.unwrap();
+    assert_eq!(prepared.migrated, 0);
+    assert_eq!(prepared.counts[0], ("Licenses", 2, 1));
+    assert_eq!(prepared.counts[1], ("Active licenses", 2, 1));
+
+    backup::restore(&db, key.clone(), &prepared, dir.path(), &tables)
+      .await
+      .unwrap();
+    let all = license::Entity::find().all(&db).await.unwrap();
+    assert_eq!(all, vec![kept]);
+    assert_eq!(backup::data_hash(&db, &tables).await.unwrap(), hash);
+
+    // the build id sequence continues after the restored rows
+    builds.create("1.1".into(), "builds/b".into(), None).await.unwrap();
+    assert_eq!(builds.all().await.unwrap().len(), 2);
+    assert_eq!(builds.total_downloads().await.unwrap(), 0);
+    sv::Stats::new(&db).aggregate().await.unwrap();
+
+    let staged = strings(
+      &db,
+      "SELECT schema_name::text FROM information_schema.schemata \
+      WHERE schema_name LIKE 'restore_%'",
+      vec![],
+    )
+    .await
+    .unwrap();
+    assert!(staged.is_empty());
+
+    let sqlite = b"SQLite format 3\0".to_vec();
+    assert!(backup::prepare(&db, key, sqlite, dir.path()).await.is_err());
+  }
+}
@@ -0,0 +1,404 @@
+//! Admin CLI for scripting and emergencies, works on the same database as
+//! the server. Data goes to stdout in the chosen `--format`, notes to
+//! stderr.
+
+use std::{env, io, path::PathBuf, sync::Arc};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use license::{
+  backup,
+  config::Config,
+  entity::{self, LicenseType},
+  prelude::*,
+  sv::{self, audit::Actor},
+  utils,
+};
+use migration::Migrator;
+use serde::Serialize;
+
+#[derive(Parser)]
+#[command(name = "license-admin", version, about)]
+struct Cli {
+  /// Database of the server, SQLite or Postgres
+  #[arg(
+    long,
+    env = "DATABASE_URL",
+    default_value = "sqlite:licenses.db?mode=rwc",
+    global = true
+  )]
+  database_url: String,
+  /// Config file, for the backup and builds settings
+  #[arg(
+    long,
+    env = "CONFIG_PATH",
+    default_value = "config.toml",
+    global = true
+  )]
+  config: PathBuf,
+  #[arg(long, short, value_enum, default_value_t = Format::Text, global = true)]
+  format: Format,
+  #[command(subcommand)]
+  command: Command,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+  Text,
+  Json,
+  Csv,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  #[command(subcommand)]
+  License(LicenseCommand),
+  /// Show a user with license counts
+  User { user_id: i64 },
+  #[command(subcommand)]
+  Build(BuildCommand),
+  /// Write an encrypted backup to the backup directory
+  Backup,
+  /// Check a backup and restore it, only a dry run without `--yes`
+  Restore {
+    file: PathBuf,
+    #[arg(long)]
+    yes: bool,
+  },
+  /// Dump a whole table
+  Export { table: Table },
+}
+
+#[derive(Subcommand)]
+enum LicenseCommand {
+  /// Create a license, its user is created if needed
+  Gen {
+    user_id: i64,
+    #[arg(long, default_value_t = 30)]
+    days: u64,
+    #[arg(long, value_enum, default_value_t = Tier::Pro)]
+    tier: Tier,
+  },
+  /// Extend a license, e.g. `30d`, `2w` or `1h30m`
+  Extend {
+    key: String,
+    #[arg(value_parser = humantime::parse_duration)]
+    duration: Duration,
+  },
+  Block {
+    key: String,
+  },
+  Unblock {
+    key: String,
+  },
+  List {
+    /// Only licenses of this user
+    #[arg(long)]
+    user: Option<i64>,
+  },
+}
+
+#[derive(Subcommand)]
+enum BuildCommand {
+  /// Publish a build, files outside the builds directory are copied in
+  Publish {
+    file: PathBuf,
+    version: String,
+    #[arg(long)]
+    changelog: Option<String>,
+  },
+  /// Remove a build from downloads
+  Yank {
+    version: String,
+  },
+  Unyank {
+    version: String,
+  },
+  List,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Tier {
+  Trial,
+  Pro,
+}
+
+impl From<Tier> for LicenseType {
+  fn from(tier: Tier) -> Self {
+    match tier {
+      Tier::Trial => LicenseType::Trial,
+      Tier::Pro => LicenseType::Pro,
+    }
+  }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Table {
+  Licenses,
+  Users,
+  Builds,
+}
+
+/// Record printed by the CLI, serialized as is for JSON and CSV
+trait Row: Serialize {
+  fn text(&self) -> String;
+}
+
+impl Row for entity::license::Model {
+  fn text(&self) -> String {
+    format!(
+      "{}  user {}  {:?}  expires {}{}",
+      self.key,
+      self.tg_user_id,
+      self.license_type,
+      utils::format_date(self.expires_at),
+      if self.is_blocked { "  BLOCKED" } else { "" }
+    )
+  }
+}
+
+impl Row for entity::build::Model {
+  fn text(&self) -> String {
+    format!(
+      "{}  {}  {} downloads{}",
+      self.version,
+      self.file_path,
+      self.downloads,
+      if self.is_active { "" } else { "  YANKED" }
+    )
+  }
+}
+
+#[derive(Serialize)]
+struct UserRow {
+  tg_user_id: i64,
+  username: Option<String>,
+  first_name: Option<String>,
+  display_name: Option<String>,
+  language: Option<String>,
+  reg_date: DateTime,
+  licenses: usize,
+  active_licenses: usize,
+}
+
+impl Row for UserRow {
+  fn text(&self) -> String {
+    format!(
+      "{}  @{}  {}  registered {}  {} licenses ({} active)",
+      self.tg_user_id,
+      self.username.as_deref().unwrap_or("-"),
+      self.first_name.as_deref().unwrap_or("-"),
+      utils::format_date(self.reg_date),
+      self.licenses,
+      self.active_licenses
+    )
+  }
+}
+
+impl Row for backup::Manifest {
+  fn text(&self) -> String {
+    format!("{}  {} bytes  sha256 {}", self.file, self.size, self.sha256)
+  }
+}
+
+#[derive(Serialize)]
+struct CountRow {
+  what: &'static str,
+  live: i64,
+  backup: i64,
+}
+
+impl Row for CountRow {
+  fn text(&self) -> String {
+    format!("{}: {} -> {}", self.what, self.live, self.backup)
+  }
+}
+
+fn print<T: Row>(format: Format, rows: &[T]) -> anyhow::Result<()> {
+  match format {
+    Format::Text => rows.iter().for_each(|row| println!("{}", row.text())),
+    Format::Json => println!("{}", json::to_string_pretty(rows)?),
+    Format::Csv => {
+      let mut writer = csv::Writer::from_writer(io::stdout());
+      for row in rows {
+        writer.serialize(row)?;
+      }
+      writer.flush()?;
+    }
+  }
+  Ok(())
+}
+
+/// Like [`print`], but a single JSON object instead of an array
+fn print_one<T: Row>(format: Format, row: T) -> anyhow::Result<()> {
+  match format {
+    Format::Json => {
+      println!("{}", json::to_string_pretty(&row)?);
+      Ok(())
+    }
+    _ => print(format, &[row]),
+  }
+}
+
+fn backup_key() -> anyhow::Result<Arc<backup::Key>> {
+  let key = env::var("BACKUP_KEY").context("BACKUP_KEY is not set")?;
+  let key = key.parse().map_err(|e| anyhow::anyhow!("BACKUP_KEY: {e}"))?;
+  Ok(Arc::new(key))
+}
+
+async fn user_row(db: &DatabaseConnection, user_id: i64) -> Result<UserRow> {
+  let user =
+    sv::User::new(db).by_id(user_id).await?.ok_or(Error::UserNotFound)?;
+  let licenses = sv::License::new(db).by_user(user_id, true).await?;
+  let now = Utc::now().naive_utc();
+  Ok(UserRow {
+    tg_user_id: user.tg_user_id,
+    username: user.username,
+    first_name: user.first_name,
+    display_name: user.display_name,
+    language: user.language,
+    reg_date: user.reg_date,
+    licenses: licenses.len(),
+    active_licenses: licenses
+      .iter()
+      .filter(|l| !l.is_blocked && l.expires_at > now)
+      .count(),
+  })
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
+  let config = Config::load(&cli.config).map_err(anyhow::Error::msg)?;
+  let db = Database::connect(&cli.database_url).await?;
+  Migrator::up(&db, None).await?;
+
+  let format = cli.format;
+  let actor = Actor::System("cli");
+  let licenses = sv::License::new(&db).by(actor.clone());
+  let builds = sv::Build::new(&db).by(actor);
+
+  match cli.command {
+    Command::License(LicenseCommand::Gen { user_id, days, tier }) => {
+      print_one(format, licenses.create(user_id, tier.into(), days).await?)
+    }
+    Command::License(LicenseCommand::Extend { key, duration }) => {
+      licenses.expires(&key, duration).await?;
+      let license =
+        licenses.by_key(&key).await?.ok_or(Error::LicenseNotFound)?;
+      print_one(format, license)
+    }
+    Command::License(LicenseCommand::Block { key }) => {
+      licenses.set_blocked(&key, true).await?;
+      eprintln!("Blocked, live sessions end on their next heartbeat");
+      Ok(())
+    }
+    Command::License(LicenseCommand::Unblock { key }) => {
+      licenses.set_blocked(&key, false).await?;
+      Ok(())
+    }
+    Command::License(LicenseCommand::List { user: Some(user_id) }) => {
+      print(format, &licenses.by_user(user_id, true).await?)
+    }
+    Command::License(LicenseCommand::List { user: None }) => {
+      print(format, &licenses.all().await?)
+    }
+    Command::User { user_id } => {
+      print_one(format, user_row(&db, user_id).await?)
+    }
+    Command::Build(BuildCommand::Publish { file, version, changelog }) => {
+      let dir = PathBuf::from(&config.builds_directory);
+      let name = file.file_name().context("Not a file")?;
+      let target = dir.join(name);
+      if target.exists() {
+        // a same-named file may still be served by an older version
+        let source = tokio::fs::canonicalize(&file).await?;
+        if source != tokio::fs::canonicalize(&target).await? {
+          anyhow::bail!(
+            "{} already exists, rename the file to publish it",
+            target.display()
+          );
+        }
+      } else {
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::copy(&file, &target)
+          .await
+          .with_context(|| format!("Failed to copy {}", file.display()))?;
+      }
+
+      let path =
+        format!("{}/{}", config.builds_directory, name.to_string_lossy());
+      print_one(format, builds.create(version, path, changelog).await?)
+    }
+    Command::Build(BuildCommand::Yank { version }) => {
+      builds.by_version(&version).await?.ok_or(Error::BuildNotFound)?;
+      builds.deactivate(&version).await?;
+      Ok(())
+    }
+    Command::Build(BuildCommand::Unyank { version }) => {
+      builds.by_version(&version).await?.ok_or(Error::BuildNotFound)?;
+      builds.activate(&version).await?;
+      Ok(())
+    }
+    Command::Build(BuildCommand::List) => print(format, &builds.all().await?),
+    Command::Backup => {
+      let dir = PathBuf::from(&config.backup_directory);
+      let (_, manifest) =
+        backup::create(&db, backup_key()?, &dir, &config.backup_tables).await?;
+      backup::prune(&dir, config.backup_retention()).await?;
+      print_one(format, manifest)
+    }
+    Command::Restore { file, yes } => {
+      let key = backup_key()?;
+      let dir = PathBuf::from(&config.backup_directory);
+      let data = tokio::fs::read(&file)
+        .await
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+
+      let prepared = backup::prepare(&db, key.clone(), data, &dir).await?;
+      eprintln!(
+        "Integrity check passed, migration {} (+{} on restore)",
+        prepared.migration.as_deref().unwrap_or("none"),
+        prepared.migrated
+      );
+      let counts: Vec<_> = prepared
+        .counts
+        .iter()
+        .map(|&(what, live, backup)| CountRow { what, live, backup })
+        .collect();
+      print(format, &counts)?;
+
+      if !yes {
+        backup::discard(&db, &prepared).await;
+        eprintln!("Dry run, pass --yes to restore");
+        return Ok(());
+      }
+      let snapshot =
+        backup::restore(&db, key, &prepared, &dir, &config.backup_tables)
+          .await?;
+      eprintln!("Restored, pre-restore snapshot: {}", snapshot.display());
+      eprintln!("Restart the server to drop sessions of removed licenses");
+      Ok(())
+    }
+    Command::Export { table: Table::Licenses } => {
+      print(format, &licenses.all().await?)
+    }
+    Command::Export { table: Table::Users } => {
+      let mut rows = Vec::new();
+      for user in sv::User::new(&db).all().await? {
+        rows.push(user_row(&db, user.tg_user_id).await?);
+      }
+      print(format, &rows)
+    }
+    Command::Export { table: Table::Builds } => {
+      print(format, &builds.all().await?)
+    }
+  }
+}
+
+#[tokio::main]
+async fn main() {
+  dotenvy::dotenv().ok();
+
+  if let Err(e) = run(Cli::parse()).await {
+    eprintln!("Error: {:#}", e);
+    std::process::exit(1);
+  }
+}
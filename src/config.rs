@@ -0,0 +1,374 @@
+use std::{
+  env, fmt::Display, net::IpAddr, path::Path, str::FromStr, time::Duration,
+};
+
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::{backup::Retention, sv::anomaly::Policy};
+
+/// Runtime configuration, layered as defaults < TOML file < env variables
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  pub builds_directory: String,
+  pub session_lifetime: i64,
+  pub backup_hours: u64,
+  /// Directory encrypted backups and their manifests are written to
+  pub backup_directory: String,
+  /// Backups kept of the latest hours, days and ISO weeks, see [`Retention`]
+  pub backup_keep_hourly: u64,
+  pub backup_keep_daily: u64,
+  pub backup_keep_weekly: u64,
+  /// Also send automatic backups to the owners in Telegram
+  pub backup_telegram: bool,
+  /// Tables whose changes trigger an automatic backup, stats and caches
+  /// are left out by default
+  pub backup_tables: Vec<String>,
+  pub download_token_lifetime: i64,
+  pub base_url: String,
+  /// Minimum free disk space in bytes before triggering yanked builds GC.
+  /// Default: 500MB (enough for ~2 releases at ~230MB each)
+  pub gc_min_free_space: u64,
+  /// Interval in seconds for checking disk space and triggering GC.
+  /// Default: 60 seconds
+  pub gc_check_interval_secs: u64,
+  /// Number of users shown on the weekly leaderboard
+  pub leaderboard_size: u64,
+  /// Pro days granted to a referrer for a referee's first paid order,
+  /// 0 disables rewards. Default: 7 days
+  pub referral_reward_days: u64,
+  /// Chat that receives every audit log entry, 0 disables mirroring
+  pub audit_chat_id: i64,
+  /// Initial delay before restarting a stopped plugin, doubled on each
+  /// consecutive restart. Default: 5 seconds
+  pub plugin_backoff_secs: u64,
+  /// Upper bound for the plugin restart delay. Default: 5 minutes
+  pub plugin_backoff_max_secs: u64,
+  /// How long plugins may take to stop gracefully. Default: 30 seconds
+  pub shutdown_timeout_secs: u64,
+  /// Minutes of session history scored for key sharing. Default: 1 hour
+  pub anomaly_window_mins: u64,
+  /// Limits of the key sharing signals within the window, see [`Policy`]
+  pub anomaly_machines: u64,
+  pub anomaly_ips: u64,
+  pub anomaly_opens: u64,
+  pub anomaly_rejections: u64,
+  /// Risk score (0-100) from which keys are reported to the admins,
+  /// 0 disables reports. Default: 60
+  pub anomaly_alert_score: u32,
+  /// Risk score from which keys are blocked, 0 disables it. Default: 0
+  pub anomaly_suspend_score: u32,
+  /// Reverse proxies whose `Forwarded` and `X-Forwarded-For` headers name
+  /// the heartbeat client, the TCP peer is used otherwise
+  pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Self {
+      builds_directory: String::from("./builds"),
+      session_lifetime: 120,
+      backup_hours: 1,
+      backup_directory: String::from("./backups"),
+      backup_keep_hourly: 24,
+      backup_keep_daily: 7,
+      backup_keep_weekly: 4,
+      backup_telegram: true,
+      backup_tables: [
+        "licenses",
+        "users",
+        "builds",
+        "promos",
+        "claimed_promos",
+        "codes",
+        "code_redemptions",
+        "referrals",
+        "staff",
+        "broadcasts",
+      ]
+      .map(String::from)
+      .into(),
+      download_token_lifetime: 600, // 10 minutes
+      base_url: String::from("http://localhost:3000"),
+      gc_min_free_space: 500 * 1024 * 1024, // 500MB
+      gc_check_interval_secs: 60,
+      leaderboard_size: 10,
+      referral_reward_days: 7,
+      audit_chat_id: 0,
+      plugin_backoff_secs: 5,
+      plugin_backoff_max_secs: 300,
+      shutdown_timeout_secs: 30,
+      anomaly_window_mins: 60,
+      anomaly_machines: 2,
+      anomaly_ips: 4,
+      anomaly_opens: 30,
+      anomaly_rejections: 20,
+      anomaly_alert_score: 60,
+      anomaly_suspend_score: 0,
+      trusted_proxies: Vec::new(),
+    }
+  }
+}
+
+fn env_override<T>(field: &mut T, var: &str, invalid: &mut Vec<String>)
+where
+  T: FromStr,
+  T::Err: Display,
+{
+  if let Ok(value) = env::var(var) {
+    match value.trim().parse() {
+      Ok(value) => *field = value,
+      Err(err) => invalid.push(format!("{var}: {err} ('{value}')")),
+    }
+  }
+}
+
+impl Config {
+  /// Load the config file at `path` (if it exists), apply env overrides
+  /// and validate the result. All problems are reported at once.
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let mut config = if path.exists() {
+      let raw = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+      toml::from_str(&raw)
+        .map_err(|e| format!("Failed to parse {}:\n  {e}", path.display()))?
+    } else {
+      Config::default()
+    };
+
+    let mut invalid = Vec::new();
+    config.apply_env(&mut invalid);
+    config.validate(&mut invalid);
+
+    if !invalid.is_empty() {
+      return Err(format!(
+        "Invalid configuration ({}):\n  {}\n",
+        path.display(),
+        invalid.join("\n  ")
+      ));
+    }
+
+    Ok(config)
+  }
+
+  fn apply_env(&mut self, invalid: &mut Vec<String>) {
+    env_override(&mut self.builds_directory, "BUILDS_DIRECTORY", invalid);
+    env_override(&mut self.session_lifetime, "SESSION_LIFETIME", invalid);
+    env_override(&mut self.backup_hours, "BACKUP_HOURS", invalid);
+    env_override(&mut self.backup_directory, "BACKUP_DIRECTORY", invalid);
+    env_override(&mut self.backup_keep_hourly, "BACKUP_KEEP_HOURLY", invalid);
+    env_override(&mut self.backup_keep_daily, "BACKUP_KEEP_DAILY", invalid);
+    env_override(&mut self.backup_keep_weekly, "BACKUP_KEEP_WEEKLY", invalid);
+    env_override(&mut self.backup_telegram, "BACKUP_TELEGRAM", invalid);
+    if let Ok(tables) = env::var("BACKUP_TABLES") {
+      self.backup_tables = tables
+        .split(',')
+        .map(|table| table.trim().to_string())
+        .filter(|table| !table.is_empty())
+        .collect();
+    }
+    env_override(
+      &mut self.download_token_lifetime,
+      "DOWNLOAD_TOKEN_LIFETIME",
+      invalid,
+    );
+    env_override(&mut self.base_url, "BASE_URL", invalid);
+    env_override(&mut self.gc_min_free_space, "GC_MIN_FREE_SPACE", invalid);
+    env_override(
+      &mut self.gc_check_interval_secs,
+      "GC_CHECK_INTERVAL_SECS",
+      invalid,
+    );
+    env_override(&mut self.leaderboard_size, "LEADERBOARD_SIZE", invalid);
+    env_override(
+      &mut self.referral_reward_days,
+      "REFERRAL_REWARD_DAYS",
+      invalid,
+    );
+    env_override(&mut self.audit_chat_id, "AUDIT_CHAT_ID", invalid);
+    env_override(&mut self.plugin_backoff_secs, "PLUGIN_BACKOFF_SECS", invalid);
+    env_override(
+      &mut self.plugin_backoff_max_secs,
+      "PLUGIN_BACKOFF_MAX_SECS",
+      invalid,
+    );
+    env_override(
+      &mut self.shutdown_timeout_secs,
+      "SHUTDOWN_TIMEOUT_SECS",
+      invalid,
+    );
+    env_override(&mut self.anomaly_window_mins, "ANOMALY_WINDOW_MINS", invalid);
+    env_override(&mut self.anomaly_machines, "ANOMALY_MACHINES", invalid);
+    env_override(&mut self.anomaly_ips, "ANOMALY_IPS", invalid);
+    env_override(&mut self.anomaly_opens, "ANOMALY_OPENS", invalid);
+    env_override(&mut self.anomaly_rejections, "ANOMALY_REJECTIONS", invalid);
+    env_override(&mut self.anomaly_alert_score, "ANOMALY_ALERT_SCORE", invalid);
+    env_override(
+      &mut self.anomaly_suspend_score,
+      "ANOMALY_SUSPEND_SCORE",
+      invalid,
+    );
+    if let Ok(proxies) = env::var("TRUSTED_PROXIES") {
+      self.trusted_proxies.clear();
+      for proxy in proxies.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match proxy.parse() {
+          Ok(ip) => self.trusted_proxies.push(ip),
+          Err(err) => {
+            invalid.push(format!("TRUSTED_PROXIES: {err} ('{proxy}')"))
+          }
+        }
+      }
+    }
+  }
+
+  fn validate(&self, invalid: &mut Vec<String>) {
+    if self.builds_directory.trim().is_empty() {
+      invalid.push("builds_directory: cannot be empty".into());
+    }
+    if self.backup_directory.trim().is_empty() {
+      invalid.push("backup_directory: cannot be empty".into());
+    }
+    if self.backup_tables.is_empty() {
+      invalid.push("backup_tables: cannot be empty".into());
+    }
+    if self.session_lifetime <= 0 {
+      invalid.push("session_lifetime: must be positive".into());
+    }
+    if self.download_token_lifetime <= 0 {
+      invalid.push("download_token_lifetime: must be positive".into());
+    }
+    if let Err(err) = Url::parse(&self.base_url) {
+      invalid.push(format!("base_url: {err} ('{}')", self.base_url));
+    }
+    if !(1..=50).contains(&self.leaderboard_size) {
+      invalid.push("leaderboard_size: must be between 1 and 50".into());
+    }
+    if self.plugin_backoff_secs == 0 {
+      invalid.push("plugin_backoff_secs: must be positive".into());
+    }
+    if self.plugin_backoff_max_secs < self.plugin_backoff_secs {
+      invalid.push(
+        "plugin_backoff_max_secs: must not be less than plugin_backoff_secs"
+          .into(),
+      );
+    }
+    if self.shutdown_timeout_secs == 0 {
+      invalid.push("shutdown_timeout_secs: must be positive".into());
+    }
+    if self.anomaly_window_mins == 0 {
+      invalid.push("anomaly_window_mins: must be positive".into());
+    }
+    if self.anomaly_alert_score > 100 {
+      invalid.push("anomaly_alert_score: must not exceed 100".into());
+    }
+    if self.anomaly_suspend_score > 100 {
+      invalid.push("anomaly_suspend_score: must not exceed 100".into());
+    }
+  }
+
+  pub fn backup_retention(&self) -> Retention {
+    Retention {
+      hourly: self.backup_keep_hourly,
+      daily: self.backup_keep_daily,
+      weekly: self.backup_keep_weekly,
+    }
+  }
+
+  pub fn anomaly_policy(&self) -> Policy {
+    Policy {
+      window: Duration::from_secs(self.anomaly_window_mins * 60),
+      machines: self.anomaly_machines,
+      ips: self.anomaly_ips,
+      opens: self.anomaly_opens,
+      rejections: self.anomaly_rejections,
+    }
+  }
+
+  /// Copy fields that are safe to change at runtime from `new`.
+  /// Returns names of changed fields that only apply after a restart.
+  pub fn reload(&mut self, new: Config) -> Vec<&'static str> {
+    let mut restart = Vec::new();
+    if self.builds_directory != new.builds_directory {
+      restart.push("builds_directory");
+    }
+    if self.backup_hours != new.backup_hours {
+      restart.push("backup_hours");
+    }
+    if self.gc_check_interval_secs != new.gc_check_interval_secs {
+      restart.push("gc_check_interval_secs");
+    }
+    if self.plugin_backoff_secs != new.plugin_backoff_secs {
+      restart.push("plugin_backoff_secs");
+    }
+    if self.plugin_backoff_max_secs != new.plugin_backoff_max_secs {
+      restart.push("plugin_backoff_max_secs");
+    }
+
+    self.backup_directory = new.backup_directory;
+    self.backup_keep_hourly = new.backup_keep_hourly;
+    self.backup_keep_daily = new.backup_keep_daily;
+    self.backup_keep_weekly = new.backup_keep_weekly;
+    self.backup_telegram = new.backup_telegram;
+    self.backup_tables = new.backup_tables;
+    self.session_lifetime = new.session_lifetime;
+    self.download_token_lifetime = new.download_token_lifetime;
+    self.base_url = new.base_url;
+    self.gc_min_free_space = new.gc_min_free_space;
+    self.leaderboard_size = new.leaderboard_size;
+    self.referral_reward_days = new.referral_reward_days;
+    self.audit_chat_id = new.audit_chat_id;
+    self.shutdown_timeout_secs = new.shutdown_timeout_secs;
+    self.anomaly_window_mins = new.anomaly_window_mins;
+    self.anomaly_machines = new.anomaly_machines;
+    self.anomaly_ips = new.anomaly_ips;
+    self.anomaly_opens = new.anomaly_opens;
+    self.anomaly_rejections = new.anomaly_rejections;
+    self.anomaly_alert_score = new.anomaly_alert_score;
+    self.anomaly_suspend_score = new.anomaly_suspend_score;
+    self.trusted_proxies = new.trusted_proxies;
+
+    restart
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_partial_file() {
+    let config: Config = toml::from_str("session_lifetime = 60").unwrap();
+    assert_eq!(config.session_lifetime, 60);
+    assert_eq!(config.backup_hours, Config::default().backup_hours);
+  }
+
+  #[test]
+  fn test_unknown_field_rejected() {
+    assert!(toml::from_str::<Config>("session_lifetim = 60").is_err());
+  }
+
+  #[test]
+  fn test_validate() {
+    let config = Config {
+      session_lifetime: 0,
+      base_url: "not a url".into(),
+      ..Default::default()
+    };
+
+    let mut invalid = Vec::new();
+    config.validate(&mut invalid);
+    assert_eq!(invalid.len(), 2);
+  }
+
+  #[test]
+  fn test_reload_keeps_restart_only_fields() {
+    let mut config = Config::default();
+    let new =
+      Config { session_lifetime: 30, backup_hours: 6, ..Default::default() };
+
+    assert_eq!(config.reload(new), vec!["backup_hours"]);
+    assert_eq!(config.session_lifetime, 30);
+    assert_eq!(config.backup_hours, 1);
+  }
+}
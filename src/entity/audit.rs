@@ -0,0 +1,28 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A single administrative or license mutation
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "audit_log")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub id: i64,
+  /// Who did it, e.g. `admin:123`, `user:456` or `system:gc`
+  pub actor: String,
+  /// Dotted action name like `license.extend`
+  pub action: String,
+  /// License key or build version
+  pub target: String,
+  /// Owner of the target license, if any
+  pub user_id: Option<i64>,
+  /// JSON snapshot before the mutation, `None` for creations
+  pub before: Option<String>,
+  /// JSON snapshot after the mutation, `None` for deletions
+  pub after: Option<String>,
+  pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,68 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Users a broadcast is delivered to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Text")]
+pub enum Audience {
+  #[sea_orm(string_value = "all")]
+  All,
+  /// Users with at least one valid license
+  #[sea_orm(string_value = "active")]
+  Active,
+  /// Users that had licenses, but none is valid anymore
+  #[sea_orm(string_value = "expired")]
+  Expired,
+  /// Users with a valid trial license
+  #[sea_orm(string_value = "trial")]
+  Trial,
+  /// Users with a valid pro license
+  #[sea_orm(string_value = "pro")]
+  Pro,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Text")]
+pub enum Status {
+  /// Previewed, waiting for confirmation
+  #[sea_orm(string_value = "pending")]
+  Pending,
+  #[sea_orm(string_value = "sending")]
+  Sending,
+  #[sea_orm(string_value = "done")]
+  Done,
+  #[sea_orm(string_value = "cancelled")]
+  Cancelled,
+}
+
+/// Announcement sent to many users, with its delivery stats
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "broadcasts")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub id: i64,
+  /// HTML message text
+  pub text: String,
+  pub audience: Audience,
+  /// Build offered with a download button
+  pub build_version: Option<String>,
+  pub status: Status,
+  pub total: i32,
+  pub sent: i32,
+  /// Users that blocked the bot or deleted their account
+  pub blocked: i32,
+  pub failed: i32,
+  /// Last user delivered to, recipients are sent to in id order, so an
+  /// interrupted broadcast resumes after it
+  pub last_recipient: Option<i64>,
+  pub created_by: i64,
+  pub created_at: DateTime,
+  pub finished_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
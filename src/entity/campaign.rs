@@ -0,0 +1,50 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{LicenseType, promo};
+
+/// Who may claim a promo
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[derive(EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Text")]
+pub enum Eligibility {
+  #[sea_orm(string_value = "all")]
+  #[default]
+  All,
+  /// Only users that never had a license
+  #[sea_orm(string_value = "new_users")]
+  NewUsers,
+}
+
+/// Promo campaign, claims are recorded in `claimed_promos`
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "promos")]
+pub struct Model {
+  #[sea_orm(primary_key, auto_increment = false)]
+  pub name: String,
+  /// Button label in the main menu
+  pub title: String,
+  pub starts_at: DateTime,
+  pub ends_at: DateTime,
+  pub license_type: LicenseType,
+  pub days: i32,
+  /// Total claims allowed, `None` for unlimited
+  pub max_claims: Option<i32>,
+  pub eligibility: Eligibility,
+  pub is_paused: bool,
+  pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(has_many = "promo::Entity")]
+  Claims,
+}
+
+impl Related<promo::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Claims.def()
+  }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
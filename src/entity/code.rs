@@ -0,0 +1,35 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{LicenseType, redemption};
+
+/// Gift or coupon code, redeemed by users into a license
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "codes")]
+pub struct Model {
+  #[sea_orm(primary_key, auto_increment = false)]
+  pub code: String,
+  pub license_type: LicenseType,
+  pub days: i32,
+  pub max_redemptions: i32,
+  pub redemptions: i32,
+  /// Code cannot be redeemed after this date, `None` for never
+  pub expires_at: Option<DateTime>,
+  /// Generation batch, codes are exported per batch
+  pub batch: String,
+  pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(has_many = "redemption::Entity")]
+  Redemptions,
+}
+
+impl Related<redemption::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Redemptions.def()
+  }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
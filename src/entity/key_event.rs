@@ -0,0 +1,22 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A session a heartbeat opened, or was refused by the session limit
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "key_events")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub id: i64,
+  pub key: String,
+  pub machine_id: String,
+  /// Peer address of the client, if known
+  pub ip: Option<String>,
+  /// Refused with `Session limit reached`
+  pub rejected: bool,
+  pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,38 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::user;
+
+/// Snapshot of a single user's weekly XP taken right before the weekly reset
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "weekly_leaderboards")]
+pub struct Model {
+  #[sea_orm(primary_key)]
+  pub id: i64,
+  /// Monday 00:00 UTC of the snapshotted week
+  pub week_start: DateTime,
+  pub tg_user_id: i64,
+  pub rank: i32,
+  pub weekly_xp: i64,
+  /// Opt-in display name at the moment of the snapshot
+  pub display_name: Option<String>,
+  pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(
+    belongs_to = "user::Entity",
+    from = "Column::TgUserId",
+    to = "user::Column::TgUserId"
+  )]
+  User,
+}
+
+impl Related<user::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::User.def()
+  }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
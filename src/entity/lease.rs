@@ -0,0 +1,18 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Exclusive right of one server replica to run a job until `expires_at`
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "leases")]
+pub struct Model {
+  #[sea_orm(primary_key, auto_increment = false)]
+  pub name: String,
+  /// Instance id of the replica holding it
+  pub holder: String,
+  pub expires_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,9 +1,20 @@
+pub mod audit;
+pub mod broadcast;
 pub mod build;
+pub mod campaign;
+pub mod code;
 pub mod free_game;
 pub mod free_item;
+pub mod key_event;
+pub mod leaderboard;
+pub mod lease;
 pub mod license;
 pub mod promo;
+pub mod redemption;
+pub mod referral;
+pub mod staff;
 pub mod stats;
 pub mod user;
+pub mod week_close;
 
 pub use license::LicenseType;
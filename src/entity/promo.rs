@@ -1,7 +1,7 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use super::user;
+use super::{campaign, user};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "claimed_promos")]
@@ -21,6 +21,18 @@ pub enum Relation {
     to = "user::Column::TgUserId"
   )]
   User,
+  #[sea_orm(
+    belongs_to = "campaign::Entity",
+    from = "Column::PromoName",
+    to = "campaign::Column::Name"
+  )]
+  Campaign,
+}
+
+impl Related<campaign::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Campaign.def()
+  }
 }
 
 impl Related<user::Entity> for Entity {
@@ -0,0 +1,44 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{code, user};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "code_redemptions")]
+pub struct Model {
+  #[sea_orm(primary_key, auto_increment = false)]
+  pub code: String,
+  #[sea_orm(primary_key, auto_increment = false)]
+  pub tg_user_id: i64,
+  pub redeemed_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(
+    belongs_to = "code::Entity",
+    from = "Column::Code",
+    to = "code::Column::Code"
+  )]
+  Code,
+  #[sea_orm(
+    belongs_to = "user::Entity",
+    from = "Column::TgUserId",
+    to = "user::Column::TgUserId"
+  )]
+  User,
+}
+
+impl Related<code::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::Code.def()
+  }
+}
+
+impl Related<user::Entity> for Entity {
+  fn to() -> RelationDef {
+    Relation::User.def()
+  }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
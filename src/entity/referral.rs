@@ -0,0 +1,34 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Referee invited by a referrer, one row per referee
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "referrals")]
+pub struct Model {
+  #[sea_orm(primary_key, auto_increment = false)]
+  pub referee_id: i64,
+  pub referrer_id: i64,
+  /// Days granted to the referrer, 0 until rewarded
+  pub reward_days: i32,
+  pub created_at: DateTime,
+  /// Set once the referee's first paid order completed
+  pub rewarded_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+  #[sea_orm(
+    belongs_to = "super::user::Entity",
+    from = "Column::RefereeId",
+    to = "super::user::Column::TgUserId"
+  )]
+  Referee,
+  #[sea_orm(
+    belongs_to = "super::user::Entity",
+    from = "Column::ReferrerId",
+    to = "super::user::Column::TgUserId"
+  )]
+  Referrer,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,77 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "Text")]
+pub enum Role {
+  /// Everything, including backups and managing roles
+  #[sea_orm(string_value = "owner")]
+  Owner,
+  /// Everything except backups and roles
+  #[sea_orm(string_value = "admin")]
+  Admin,
+  /// Read-only views plus extending licenses
+  #[sea_orm(string_value = "support")]
+  Support,
+  /// Builds only
+  #[sea_orm(string_value = "release")]
+  Release,
+}
+
+/// What a bot command or admin callback needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+  /// `/info`, `/users` and the user and license cards
+  ViewUsers,
+  ExtendLicense,
+  /// Generate, block, kick sessions and change tiers
+  ManageLicenses,
+  ManageBuilds,
+  /// Promos, gift codes and broadcasts
+  ManageMarketing,
+  /// Stats, referrals and the audit log
+  ViewReports,
+  ManagePlugins,
+  Backup,
+  ManageRoles,
+}
+
+impl Role {
+  pub fn parse(input: &str) -> Option<Self> {
+    match input.to_ascii_lowercase().as_str() {
+      "owner" => Some(Role::Owner),
+      "admin" => Some(Role::Admin),
+      "support" => Some(Role::Support),
+      "release" => Some(Role::Release),
+      _ => None,
+    }
+  }
+
+  pub fn can(self, permission: Permission) -> bool {
+    use Permission::*;
+
+    match self {
+      Role::Owner => true,
+      Role::Admin => !matches!(permission, Backup | ManageRoles),
+      Role::Support => matches!(permission, ViewUsers | ExtendLicense),
+      Role::Release => permission == ManageBuilds,
+    }
+  }
+}
+
+/// Bot operator with a role, owners from `ADMIN_IDS` are not stored
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "staff")]
+pub struct Model {
+  #[sea_orm(primary_key, auto_increment = false)]
+  pub tg_user_id: i64,
+  pub role: Role,
+  pub granted_by: Option<i64>,
+  pub granted_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
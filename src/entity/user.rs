@@ -9,6 +9,16 @@ pub struct Model {
   #[sea_orm(primary_key, auto_increment = false)]
   pub tg_user_id: i64,
   pub reg_date: DateTime,
+  /// Name shown on the public leaderboard, `None` means anonymous
+  pub display_name: Option<String>,
+  /// Bot language code the user chose, `None` follows `language_code`
+  pub language: Option<String>,
+  /// Telegram `@username`, cached from updates and [`crate::sv::User`] sync
+  pub username: Option<String>,
+  pub first_name: Option<String>,
+  /// Language reported by Telegram, kept apart from the chosen `language`
+  pub language_code: Option<String>,
+  pub profile_synced_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,17 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Marks a week whose XP was snapshotted and reset, even without winners
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "weekly_closes")]
+pub struct Model {
+  /// Monday 00:00 UTC of the closed week
+  #[sea_orm(primary_key, auto_increment = false)]
+  pub week_start: DateTime,
+  pub closed_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -7,8 +7,11 @@ use axum::{
 
 #[derive(Debug)]
 pub enum Promo {
+  NotFound,
   Inactive,
   Claimed,
+  Exhausted,
+  NotEligible,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -22,14 +25,28 @@ pub enum Error {
   LicenseInvalid,
   #[error("Session limit reached")]
   SessionLimitReached,
+  #[error("Session not found or expired")]
+  SessionInvalid,
+  #[error("Invalid signature")]
+  InvalidSignature,
+  #[error("Unauthorized")]
+  Unauthorized,
   #[error("Promo is {0:?}")]
   Promo(Promo),
+  #[error("Code is invalid or expired")]
+  CodeInvalid,
+  #[error("Code has already been used")]
+  CodeUsed,
+  #[error("Too many attempts")]
+  TooManyAttempts,
   #[error("Build not found")]
   BuildNotFound,
   #[error("Build already yanked")]
   BuildInactive,
   #[error("Build already active")]
   BuildAlreadyActive,
+  #[error("Plugin not found")]
+  PluginNotFound,
   #[error("Invalid arguments: {0}")]
   InvalidArgs(String),
   #[error("DB error: {0}")]
@@ -48,13 +65,29 @@ impl Error {
       Error::UserNotFound => "User not found".into(),
       Error::LicenseInvalid => "License expired or blocked".into(),
       Error::SessionLimitReached => "Session limit reached".into(),
+      Error::SessionInvalid => "Session not found or expired".into(),
+      Error::InvalidSignature => "Invalid signature".into(),
+      Error::Unauthorized => "Unauthorized".into(),
+      Error::Promo(Promo::NotFound) => "Promo not found".into(),
       Error::Promo(Promo::Inactive) => "Promo is not active right now".into(),
       Error::Promo(Promo::Claimed) => {
         "You have already claimed this promo".into()
       }
+      Error::Promo(Promo::Exhausted) => {
+        "All rewards of this promo have been claimed".into()
+      }
+      Error::Promo(Promo::NotEligible) => {
+        "This promo is only available to new users".into()
+      }
+      Error::CodeInvalid => "Code is invalid or expired".into(),
+      Error::CodeUsed => "Code has already been used".into(),
+      Error::TooManyAttempts => {
+        "Too many attempts, please try again later".into()
+      }
       Error::BuildNotFound => "Build not found".into(),
       Error::BuildInactive => "Build is already yanked".into(),
       Error::BuildAlreadyActive => "Build is already active".into(),
+      Error::PluginNotFound => "Plugin not found".into(),
       Error::InvalidArgs(msg) => msg.clone(),
       Error::Database(e) => format!("Database error: {}", e),
       Error::Io(e) => format!("IO error: {}", e),
@@ -77,17 +110,39 @@ impl IntoResponse for Error {
       Error::SessionLimitReached => {
         (StatusCode::CONFLICT, "Session limit reached")
       }
+      Error::SessionInvalid => {
+        (StatusCode::UNAUTHORIZED, "Session not found or expired")
+      }
+      Error::InvalidSignature => {
+        (StatusCode::UNAUTHORIZED, "Invalid signature")
+      }
+      Error::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+      Error::Promo(Promo::NotFound) => {
+        (StatusCode::NOT_FOUND, "Promo not found")
+      }
       Error::Promo(Promo::Inactive) => {
         (StatusCode::BAD_REQUEST, "Promo is not active")
       }
       Error::Promo(Promo::Claimed) => {
         (StatusCode::CONFLICT, "Promo already claimed")
       }
+      Error::Promo(Promo::Exhausted) => {
+        (StatusCode::CONFLICT, "Promo claim limit reached")
+      }
+      Error::Promo(Promo::NotEligible) => {
+        (StatusCode::FORBIDDEN, "Not eligible for promo")
+      }
+      Error::CodeInvalid => (StatusCode::NOT_FOUND, "Code invalid or expired"),
+      Error::CodeUsed => (StatusCode::CONFLICT, "Code already used"),
+      Error::TooManyAttempts => {
+        (StatusCode::TOO_MANY_REQUESTS, "Too many attempts")
+      }
       Error::BuildNotFound => (StatusCode::NOT_FOUND, "Build not found"),
       Error::BuildInactive => (StatusCode::BAD_REQUEST, "Build already yanked"),
       Error::BuildAlreadyActive => {
         (StatusCode::BAD_REQUEST, "Build already active")
       }
+      Error::PluginNotFound => (StatusCode::NOT_FOUND, "Plugin not found"),
       Error::InvalidArgs(msg) => (StatusCode::BAD_REQUEST, msg.as_str()),
       Error::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "IO error"),
       Error::Internal(_) => {
@@ -0,0 +1,166 @@
+//! Message catalog of user facing bot texts.
+//!
+//! Catalogs are TOML files compiled into the binary, `[section] key` becomes
+//! `section.key`. Keys missing in a translation fall back to English.
+
+use std::{collections::HashMap, fmt::Display, sync::LazyLock};
+
+use crate::prelude::*;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+  #[default]
+  En,
+  Ru,
+}
+
+impl Lang {
+  /// Parse a stored code or Telegram's `language_code` like `ru-RU`,
+  /// unsupported languages are English
+  pub fn from_code(code: &str) -> Self {
+    match code.split(['-', '_']).next().unwrap_or_default() {
+      "ru" => Lang::Ru,
+      _ => Lang::En,
+    }
+  }
+
+  pub fn code(self) -> &'static str {
+    match self {
+      Lang::En => "en",
+      Lang::Ru => "ru",
+    }
+  }
+
+  /// Native name, shown in the language switcher
+  pub fn name(self) -> &'static str {
+    match self {
+      Lang::En => "English",
+      Lang::Ru => "Русский",
+    }
+  }
+
+  /// The language offered by the profile switcher
+  pub fn next(self) -> Self {
+    match self {
+      Lang::En => Lang::Ru,
+      Lang::Ru => Lang::En,
+    }
+  }
+}
+
+type Catalog = HashMap<String, String>;
+
+fn flatten(prefix: &str, table: toml::Table, catalog: &mut Catalog) {
+  for (key, value) in table {
+    let key = if prefix.is_empty() { key } else { format!("{prefix}.{key}") };
+    match value {
+      toml::Value::String(text) => {
+        catalog.insert(key, text);
+      }
+      toml::Value::Table(table) => flatten(&key, table, catalog),
+      other => panic!("i18n: `{key}` must be a string, got {other}"),
+    }
+  }
+}
+
+fn parse(src: &str) -> Catalog {
+  let table: toml::Table = toml::from_str(src).expect("valid i18n catalog");
+  let mut catalog = Catalog::new();
+  flatten("", table, &mut catalog);
+  catalog
+}
+
+static EN: LazyLock<Catalog> = LazyLock::new(|| parse(include_str!("en.toml")));
+static RU: LazyLock<Catalog> = LazyLock::new(|| parse(include_str!("ru.toml")));
+
+fn catalog(lang: Lang) -> &'static Catalog {
+  match lang {
+    Lang::En => &EN,
+    Lang::Ru => &RU,
+  }
+}
+
+/// Text of `key`, falling back to English and then to the key itself
+pub fn get(lang: Lang, key: &'static str) -> &'static str {
+  match catalog(lang).get(key).or_else(|| EN.get(key)) {
+    Some(text) => text,
+    None => {
+      warn!("i18n: missing key `{}`", key);
+      key
+    }
+  }
+}
+
+/// Replace `{name}` placeholders of `template`
+pub fn format(template: &str, args: &[(&str, &dyn Display)]) -> String {
+  let mut text = template.to_string();
+  for (name, value) in args {
+    text = text.replace(&format!("{{{name}}}"), &value.to_string());
+  }
+  text
+}
+
+/// Localized text of user facing errors, others use [`Error::user_message`]
+pub fn error(lang: Lang, err: &Error) -> String {
+  let key = match err {
+    Error::Promo(Promo::NotFound) => "error.promo_not_found",
+    Error::Promo(Promo::Inactive) => "error.promo_inactive",
+    Error::Promo(Promo::Claimed) => "error.promo_claimed",
+    Error::Promo(Promo::Exhausted) => "error.promo_exhausted",
+    Error::Promo(Promo::NotEligible) => "error.promo_not_eligible",
+    Error::CodeInvalid => "error.code_invalid",
+    Error::CodeUsed => "error.code_used",
+    Error::TooManyAttempts => "error.too_many_attempts",
+    _ => return err.user_message(),
+  };
+  get(lang, key).to_string()
+}
+
+/// `tr!(lang, "section.key", name = value, ...)` - localized text with
+/// `{name}` placeholders replaced
+macro_rules! tr {
+  ($lang:expr, $key:literal) => {
+    $crate::i18n::get($lang, $key).to_string()
+  };
+  ($lang:expr, $key:literal, $($name:ident = $value:expr),+ $(,)?) => {
+    $crate::i18n::format(
+      $crate::i18n::get($lang, $key),
+      &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+],
+    )
+  };
+}
+
+pub(crate) use tr;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn placeholders(text: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = text
+      .split('{')
+      .skip(1)
+      .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+      .collect();
+    names.sort_unstable();
+    names
+  }
+
+  #[test]
+  fn test_translations_match_english() {
+    for (key, text) in RU.iter() {
+      let english = EN.get(key).unwrap_or_else(|| panic!("unknown key {key}"));
+      assert_eq!(placeholders(text), placeholders(english), "{key}");
+    }
+  }
+
+  #[test]
+  fn test_fallback_and_format() {
+    assert_eq!(Lang::from_code("ru-RU"), Lang::Ru);
+    assert_eq!(Lang::from_code("de"), Lang::En);
+    assert_eq!(
+      tr!(Lang::Ru, "nick.too_long", max = 32),
+      format(RU["nick.too_long"].as_str(), &[("max", &32)])
+    );
+  }
+}
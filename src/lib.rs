@@ -0,0 +1,14 @@
+#![allow(irrefutable_let_patterns)]
+
+pub mod backup;
+pub mod config;
+pub mod entity;
+pub mod error;
+pub mod i18n;
+pub mod metrics;
+pub mod plugins;
+pub mod prelude;
+pub mod state;
+pub mod store;
+pub mod sv;
+pub mod utils;
@@ -1,21 +1,18 @@
-#![allow(irrefutable_let_patterns)]
-
-mod entity;
-mod error;
-mod plugins;
-mod prelude;
-mod state;
-mod sv;
-mod utils;
-
-use std::{collections::HashSet, env, sync::Arc};
-
+use std::{collections::HashSet, env, path::PathBuf, sync::Arc};
+
+use futures::future;
+use license::{
+  backup,
+  plugins::*,
+  prelude::*,
+  state::{self, AppState},
+  store::Store,
+};
+use tokio_util::sync::CancellationToken;
 use tracing_subscriber::{
   EnvFilter, layer::SubscriberExt, util::SubscriberInitExt,
 };
 
-use crate::{plugins::*, prelude::*, state::AppState};
-
 /// Validate required environment variables and return detailed error messages
 fn validate_env() -> Result<(), String> {
   let mut missing: Vec<&str> = Vec::new();
@@ -50,6 +47,26 @@ fn validate_env() -> Result<(), String> {
     missing.push("SERVER_SECRET");
   }
 
+  if let Ok(key) = env::var("BACKUP_KEY")
+    && let Err(e) = key.parse::<backup::Key>()
+  {
+    invalid.push(format!("BACKUP_KEY: {}", e));
+  }
+
+  // Telegram's own limits for the secret token
+  if let Ok(secret) = env::var("TELEGRAM_WEBHOOK_SECRET")
+    && !secret.is_empty()
+    && (secret.len() > 256
+      || !secret
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+  {
+    invalid.push(
+      "TELEGRAM_WEBHOOK_SECRET: up to 256 of A-Z, a-z, 0-9, _ and -"
+        .to_string(),
+    );
+  }
+
   if !missing.is_empty() || !invalid.is_empty() {
     let mut msg = String::new();
     if !missing.is_empty() {
@@ -71,10 +88,28 @@ fn validate_env() -> Result<(), String> {
     msg.push_str("  TELOXIDE_TOKEN - Telegram Bot API token\n");
     msg.push_str("  SERVER_SECRET  - Secret key for server authentication\n");
     msg.push_str("\nOptional environment variables:\n");
-    msg.push_str("  DATABASE_URL   - SQLite database URL (default: sqlite:licenses.db?mode=rwc)\n");
+    msg.push_str("  DATABASE_URL   - SQLite or, built with the postgres feature, Postgres URL (default: sqlite:licenses.db?mode=rwc)\n");
     msg.push_str(
       "  BASE_URL       - Server base URL (default: http://localhost:3000)\n",
     );
+    msg.push_str(
+      "  CONFIG_PATH    - TOML config file, env variables named after its fields override it (default: config.toml)\n",
+    );
+    msg.push_str(
+      "  BACKUP_KEY     - age identity (AGE-SECRET-KEY-1...) or passphrase backups are encrypted with, backups are disabled without it\n",
+    );
+    msg.push_str(
+      "  ADMIN_API_TOKEN - Bearer token of the /api/admin import and export routes, they are disabled without it\n",
+    );
+    msg.push_str(
+      "  METRICS_TOKEN  - Bearer token Prometheus scrapes /metrics with, the exporter is disabled without it\n",
+    );
+    msg.push_str(
+      "  REDIS_URL      - Redis shared by replicas for sessions and download tokens, built with the redis feature (default: in memory)\n",
+    );
+    msg.push_str(
+      "  TELEGRAM_WEBHOOK_SECRET - Receive Telegram updates at BASE_URL/api/telegram with this secret token instead of polling (default: polling)\n",
+    );
     return Err(msg);
   }
 
@@ -109,34 +144,78 @@ async fn main() {
     .unwrap_or_else(|_| "sqlite:licenses.db?mode=rwc".into());
   let token = env::var("TELOXIDE_TOKEN").expect("TELOXIDE_TOKEN not set");
   let secret = env::var("SERVER_SECRET").expect("SERVER_SECRET not set");
-  let base_url =
-    env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:3000".into());
+  let backup_key = env::var("BACKUP_KEY")
+    .ok()
+    .map(|key| key.parse().expect("Invalid BACKUP_KEY"));
+  let config_path = PathBuf::from(
+    env::var("CONFIG_PATH").unwrap_or_else(|_| "config.toml".into()),
+  );
+
+  let config = match state::Config::load(&config_path) {
+    Ok(config) => config,
+    Err(msg) => {
+      eprintln!("Configuration error:\n\n{}", msg);
+      std::process::exit(1);
+    }
+  };
 
   info!("Starting License Server v{}", env!("CARGO_PKG_VERSION"));
 
-  let config = state::Config { base_url, ..Default::default() };
+  let redis_url = env::var("REDIS_URL").ok();
+  let store = match Store::connect(redis_url.as_deref()).await {
+    Ok(store) => store,
+    Err(e) => {
+      eprintln!("Configuration error:\n\n{:#}", e);
+      std::process::exit(1);
+    }
+  };
 
-  let app_state = Arc::new(
-    AppState::with_config(&db_url, &token, admins, secret, config).await,
-  );
+  let mut app_state = AppState::with_config(
+    &db_url, &token, admins, secret, backup_key, config, store,
+  )
+  .await;
+  if let Ok(secret) = env::var("TELEGRAM_WEBHOOK_SECRET")
+    && !secret.is_empty()
+  {
+    app_state = app_state.with_webhook(secret);
+  }
+  let app_state = Arc::new(app_state);
 
-  App::new()
+  let shutdown = CancellationToken::new();
+  let plugins = App::new()
     // TODO: maybe its better to use single plugin
     .register(cron::GC)
+    .register(cron::Leader)
     .register(cron::Sync)
     .register(cron::Backup)
     .register(cron::StatsClean)
     .register(cron::YankedBuildsGC)
+    .register(cron::ProfileSync)
+    .register(cron::AuditMirror)
+    .register(cron::Anomalies)
     //
     .register(steam::FreeGames)
     .register(steam::FreeRewards)
     //
     .register(telegram::Plugin)
+    .register(telegram::Broadcasts)
     .register(server::Plugin)
-    .run(app_state)
+    .register(watch::Config { path: config_path })
+    .run(app_state.clone(), shutdown.clone())
     .await;
 
   wait_for_shutdown().await;
+
+  info!("Shutdown requested, stopping plugins...");
+  shutdown.cancel();
+
+  let timeout = Duration::from_secs(app_state.config().shutdown_timeout_secs);
+  if time::timeout(timeout, future::join_all(plugins)).await.is_err() {
+    warn!("Plugins did not stop within {}s", timeout.as_secs());
+  }
+
+  app_state.close().await;
+  info!("Shutdown complete");
 }
 
 async fn wait_for_shutdown() {
@@ -0,0 +1,146 @@
+use prometheus::{
+  Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts,
+  Registry, TextEncoder,
+};
+
+/// Prometheus collectors exposed via `/metrics`.
+/// Cloning is cheap, clones share the same underlying collectors.
+#[derive(Clone)]
+pub struct Metrics {
+  registry: Registry,
+  pub active_sessions: IntGaugeVec,
+  pub heartbeats: IntCounterVec,
+  pub downloads: IntCounterVec,
+  pub download_bytes: IntCounterVec,
+  pub ingest_errors: IntCounterVec,
+  pub plugin_restarts: IntCounterVec,
+  pub db_query_duration: HistogramVec,
+  pub steam_scrapes: IntCounterVec,
+}
+
+impl Metrics {
+  pub fn new() -> Self {
+    let registry = Registry::new_custom(Some("license".into()), None)
+      .expect("valid registry prefix");
+
+    let active_sessions = IntGaugeVec::new(
+      Opts::new("active_sessions", "Active sessions per license type"),
+      &["license_type"],
+    )
+    .unwrap();
+    let heartbeats = IntCounterVec::new(
+      Opts::new("heartbeats_total", "Heartbeat requests by response status"),
+      &["status"],
+    )
+    .unwrap();
+    let downloads = IntCounterVec::new(
+      Opts::new("downloads_total", "Build downloads started"),
+      &["version"],
+    )
+    .unwrap();
+    let download_bytes = IntCounterVec::new(
+      Opts::new("download_bytes_total", "Build bytes streamed to clients"),
+      &["version"],
+    )
+    .unwrap();
+    let ingest_errors = IntCounterVec::new(
+      Opts::new("metrics_ingest_errors_total", "Rejected metrics submissions"),
+      &["reason"],
+    )
+    .unwrap();
+    let plugin_restarts = IntCounterVec::new(
+      Opts::new("plugin_restarts_total", "Plugin restarts by `App::run`"),
+      &["plugin"],
+    )
+    .unwrap();
+    let db_query_duration = HistogramVec::new(
+      HistogramOpts::new("db_query_duration_seconds", "Database query latency")
+        .buckets(vec![
+          0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+        ]),
+      &["kind", "failed"],
+    )
+    .unwrap();
+    let steam_scrapes = IntCounterVec::new(
+      Opts::new("steam_scrapes_total", "Steam scrapes by source and outcome"),
+      &["source", "outcome"],
+    )
+    .unwrap();
+
+    for collector in [
+      Box::new(active_sessions.clone()) as Box<dyn prometheus::core::Collector>,
+      Box::new(heartbeats.clone()),
+      Box::new(downloads.clone()),
+      Box::new(download_bytes.clone()),
+      Box::new(ingest_errors.clone()),
+      Box::new(plugin_restarts.clone()),
+      Box::new(db_query_duration.clone()),
+      Box::new(steam_scrapes.clone()),
+    ] {
+      registry.register(collector).expect("unique metric names");
+    }
+
+    Self {
+      registry,
+      active_sessions,
+      heartbeats,
+      downloads,
+      download_bytes,
+      ingest_errors,
+      plugin_restarts,
+      db_query_duration,
+      steam_scrapes,
+    }
+  }
+
+  /// Observe a single query, labelled by its leading SQL keyword
+  pub fn observe_query(&self, sql: &str, elapsed: f64, failed: bool) {
+    let kind = sql
+      .split_whitespace()
+      .next()
+      .map(|word| word.to_ascii_lowercase())
+      .unwrap_or_default();
+    let kind = match kind.as_str() {
+      "select" | "insert" | "update" | "delete" => kind.as_str(),
+      _ => "other",
+    };
+
+    self
+      .db_query_duration
+      .with_label_values(&[kind, if failed { "true" } else { "false" }])
+      .observe(elapsed);
+  }
+
+  /// Encode all collectors in Prometheus text format
+  pub fn encode(&self) -> String {
+    let mut buf = Vec::new();
+    TextEncoder::new()
+      .encode(&self.registry.gather(), &mut buf)
+      .expect("text encoding never fails");
+    String::from_utf8(buf).expect("text encoding is utf-8")
+  }
+}
+
+impl Default for Metrics {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_encode() {
+    let metrics = Metrics::new();
+    metrics.heartbeats.with_label_values(&["200"]).inc();
+    metrics.observe_query("SELECT * FROM licenses", 0.002, false);
+    metrics.observe_query("VACUUM INTO 'x.db'", 0.5, true);
+
+    let text = metrics.encode();
+    assert!(text.contains("license_heartbeats_total{status=\"200\"} 1"));
+    assert!(text.contains("kind=\"select\""));
+    assert!(text.contains("kind=\"other\""));
+  }
+}
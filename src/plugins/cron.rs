@@ -2,21 +2,89 @@ use std::{path::Path, sync::Arc};
 
 use async_trait::async_trait;
 use teloxide::{prelude::*, types::ParseMode};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
-use crate::{plugins::Plugin, prelude::*, state::AppState, sv};
+use crate::{
+  plugins::Plugin,
+  prelude::*,
+  state::AppState,
+  sv::{self, audit::Actor},
+};
 
 pub struct GC;
 
 #[async_trait]
 impl Plugin for GC {
-  async fn start(&self, app: Arc<AppState>) -> anyhow::Result<()> {
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
     let mut interval = tokio::time::interval(Duration::from_secs(60));
-    loop {
-      interval.tick().await;
-      app.gc_sessions();
-      app.gc_download_tokens();
+    while shutdown.run_until_cancelled(interval.tick()).await.is_some() {
+      let result = async {
+        app.gc_sessions().await?;
+        app.gc_download_tokens().await
+      }
+      .await;
+      app.gc_redeem_attempts();
+      app.gc_metrics_rejections();
+      app.gc_restores().await;
+      match result {
+        Ok(()) => app.plugins.report_success(self.name()),
+        Err(e) => error!("Failed to collect sessions and tokens: {}", e),
+      }
     }
+    Ok(())
+  }
+}
+
+/// Elects the replica that runs the singleton jobs, such as backups and
+/// Steam scraping, through a lease in the shared database. Replicas that
+/// lose it skip those jobs until they get it back.
+pub struct Leader;
+
+const LEADER_LEASE: &str = "leader";
+/// Renewed every third of it, so a replica that dies is replaced in time
+const LEADER_TTL: Duration = Duration::from_secs(30);
+
+#[async_trait]
+impl Plugin for Leader {
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    let lease = app.sv().lease;
+    let mut interval = time::interval(LEADER_TTL / 3);
+    while shutdown.run_until_cancelled(interval.tick()).await.is_some() {
+      let leader =
+        match lease.acquire(LEADER_LEASE, &app.instance, LEADER_TTL).await {
+          Ok(leader) => {
+            app.plugins.report_success(self.name());
+            leader
+          }
+          Err(e) => {
+            error!("Failed to renew the leader lease: {}", e);
+            false
+          }
+        };
+      if app.set_leader(leader) != leader {
+        info!(
+          "{} the leader, singleton jobs {} here",
+          if leader { "Became" } else { "No longer" },
+          if leader { "run" } else { "are skipped" }
+        );
+      }
+    }
+
+    // let another replica take over right away
+    app.set_leader(false);
+    if let Err(e) = lease.release(LEADER_LEASE, &app.instance).await {
+      warn!("Failed to release the leader lease: {}", e);
+    }
+    Ok(())
   }
 }
 
@@ -24,15 +92,21 @@ pub struct Backup;
 
 #[async_trait]
 impl Plugin for Backup {
-  async fn start(&self, app: Arc<AppState>) -> anyhow::Result<()> {
-    if app.admins.is_empty() {
-      warn!("No admins configured, auto-backups disabled");
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    if app.backup_key.is_none() {
+      warn!("BACKUP_KEY not set, auto-backups disabled");
+      shutdown.cancelled().await;
       return Ok(());
     }
 
-    let interval_hours = app.config.backup_hours;
+    let interval_hours = app.config().backup_hours;
     if interval_hours == 0 {
       info!("Auto-backups disabled via config (0 hours)");
+      shutdown.cancelled().await;
       return Ok(());
     }
 
@@ -43,14 +117,19 @@ impl Plugin for Backup {
     // skip at the moment backup
     interval.tick().await;
 
-    loop {
-      interval.tick().await;
-
+    // a started backup is never interrupted, only the wait for the next one
+    while shutdown.run_until_cancelled(interval.tick()).await.is_some() {
+      if !app.is_leader() {
+        debug!("Not the leader, skipping scheduled backup");
+        continue;
+      }
       info!("Starting scheduled backup...");
-      if let Err(err) = app.perform_smart_backup().await {
-        error!("Auto-backup failed: {}", err);
+      match app.perform_smart_backup().await {
+        Ok(()) => app.plugins.report_success(self.name()),
+        Err(err) => error!("Auto-backup failed: {}", err),
       }
     }
+    Ok(())
   }
 }
 
@@ -58,57 +137,313 @@ pub struct StatsClean;
 
 #[async_trait]
 impl Plugin for StatsClean {
-  async fn start(&self, app: Arc<AppState>) -> anyhow::Result<()> {
-    loop {
-      let now = Utc::now();
-
-      // Calculate days until next Monday
-      // num_days_from_monday() returns 0 for Monday, 1 for Tuesday, etc.
-      let days_from_monday = now.weekday().num_days_from_monday();
-      let days_until_next_monday = if days_from_monday == 0 {
-        7 // It's Monday, schedule for next Monday
-      } else {
-        7 - days_from_monday
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    // Every leader tick closes the previous week if nobody did yet, so a
+    // Monday missed during a restart or leader change is caught up
+    let mut interval = time::interval(Duration::from_secs(60));
+    while shutdown.run_until_cancelled(interval.tick()).await.is_some() {
+      if !app.is_leader() {
+        continue;
+      }
+      match close_due_week(&app).await {
+        Ok(Some((week_start, winners))) => {
+          info!(
+            "Weekly XP stats reset successfully ({} users snapshotted)",
+            winners.len()
+          );
+          announce_winners(&app, week_start, &winners).await;
+          app.plugins.report_success(self.name());
+        }
+        Ok(None) => app.plugins.report_success(self.name()),
+        Err(e) => error!("Failed to reset weekly stats: {}", e),
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Close the week before the current one unless that already happened
+async fn close_due_week(
+  app: &AppState,
+) -> Result<Option<(DateTime, Vec<sv::leaderboard::Entry>)>> {
+  let today = Utc::now().date_naive();
+  let days = today.weekday().num_days_from_monday() as u64 + 7;
+  let week_start = (today - chrono::Days::new(days))
+    .and_hms_opt(0, 0, 0)
+    .expect("Invalid time");
+
+  let sv = app.sv();
+  match sv.leaderboard.last_closed().await? {
+    Some(last) if last >= week_start => Ok(None),
+    Some(_) => {
+      let winners = sv.leaderboard.close_week(week_start).await?;
+      Ok(winners.map(|winners| (week_start, winners)))
+    }
+    // Nothing to catch up on before the first close, the XP earned so far
+    // belongs to the current week
+    None => {
+      sv.leaderboard.mark_closed(week_start).await?;
+      info!("Weekly stats reset starts after the week of {}", week_start);
+      Ok(None)
+    }
+  }
+}
+
+async fn announce_winners(
+  app: &AppState,
+  week_start: DateTime,
+  winners: &[sv::leaderboard::Entry],
+) {
+  if winners.is_empty() {
+    return;
+  }
+
+  let top =
+    &winners[..winners.len().min(app.config().leaderboard_size as usize)];
+  let message = format!(
+    "🏆 <b>Weekly Leaderboard</b>\n\
+    Week of {}\n\n{}",
+    utils::format_date(week_start),
+    utils::format_leaderboard(top, true, "Anonymous")
+  );
+
+  for &admin_id in &app.admins {
+    let _ = app
+      .bot
+      .send_message(ChatId(admin_id), &message)
+      .parse_mode(ParseMode::Html)
+      .await;
+  }
+}
+
+/// Refreshes cached Telegram profiles of users that have not written to
+/// the bot for a while, so admin views do not go stale
+pub struct ProfileSync;
+
+/// Profiles older than this are refreshed via `get_chat`
+const PROFILE_MAX_AGE: Duration = Duration::from_hours(24 * 7);
+/// Users refreshed per run, paced to stay below API limits
+const PROFILE_BATCH: u64 = 50;
+const PROFILE_PACE: Duration = Duration::from_millis(500);
+
+#[async_trait]
+impl Plugin for ProfileSync {
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    let mut interval = time::interval(Duration::from_hours(1));
+    while shutdown.run_until_cancelled(interval.tick()).await.is_some() {
+      if !app.is_leader() {
+        continue;
+      }
+      match sync_profiles(&app, &shutdown).await {
+        Ok(synced) => {
+          if synced > 0 {
+            debug!("Refreshed {} user profile(s)", synced);
+          }
+          app.plugins.report_success(self.name());
+        }
+        Err(e) => error!("Profile sync failed: {}", e),
+      }
+    }
+    Ok(())
+  }
+}
+
+async fn sync_profiles(
+  app: &AppState,
+  shutdown: &CancellationToken,
+) -> Result<usize> {
+  let sv = app.sv();
+  let users = sv.user.stale_profiles(PROFILE_MAX_AGE, PROFILE_BATCH).await?;
+
+  let mut synced = 0;
+  for user in users {
+    if shutdown.is_cancelled() {
+      break;
+    }
+
+    let profile = match app.bot.get_chat(ChatId(user.tg_user_id)).await {
+      Ok(chat) => sv::user::Profile {
+        username: chat.username().map(Into::into),
+        first_name: chat.first_name().map(Into::into),
+        language_code: user.language_code.clone(),
+      },
+      // the user blocked the bot, keep what we have and retry later
+      Err(e) => {
+        debug!("get_chat for {} failed: {}", user.tg_user_id, e);
+        sv::user::Profile {
+          username: user.username.clone(),
+          first_name: user.first_name.clone(),
+          language_code: user.language_code.clone(),
+        }
+      }
+    };
+    sv.user.update_profile(user.tg_user_id, profile).await?;
+    synced += 1;
+
+    time::sleep(PROFILE_PACE).await;
+  }
+  Ok(synced)
+}
+
+/// Mirrors new audit log entries to `audit_chat_id`
+pub struct AuditMirror;
+
+#[async_trait]
+impl Plugin for AuditMirror {
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    // entries written before startup are not mirrored again
+    let mut last_id = app.sv().audit.last_id().await?;
+
+    let mut interval = time::interval(Duration::from_secs(5));
+    while shutdown.run_until_cancelled(interval.tick()).await.is_some() {
+      let entries = match app.sv().audit.since(last_id, 20).await {
+        Ok(entries) => entries,
+        Err(e) => {
+          error!("Failed to read audit log: {}", e);
+          continue;
+        }
+      };
+      let Some(last) = entries.last() else {
+        app.plugins.report_success(self.name());
+        continue;
       };
+      last_id = last.id;
+
+      // followers keep up, so they mirror no entry twice once they lead
+      let chat_id = app.config().audit_chat_id;
+      if chat_id != 0 && app.is_leader() {
+        let text: String = entries.iter().map(utils::format_audit).collect();
+        for chunk in utils::chunk_message(&text, 0) {
+          if let Err(e) = app
+            .bot
+            .send_message(ChatId(chat_id), chunk)
+            .parse_mode(ParseMode::Html)
+            .await
+          {
+            warn!("Failed to mirror audit log: {}", e);
+          }
+        }
+      }
+      app.plugins.report_success(self.name());
+    }
+    Ok(())
+  }
+}
 
-      // TODO: check this out
-      let next_monday = now
-        .date_naive()
-        .checked_add_days(chrono::Days::new(days_until_next_monday as u64))
-        .expect("Date overflow")
-        .and_hms_opt(0, 0, 0)
-        .expect("Invalid time");
-
-      let sleep_duration = (next_monday - now.naive_utc())
-        .to_std()
-        .unwrap_or(Duration::from_secs(3600));
-
-      info!(
-        "Weekly stats reset scheduled in {} hours",
-        sleep_duration.as_secs() / 3600
-      );
-      tokio::time::sleep(sleep_duration).await;
-
-      match sv::Stats::reset_weekly_xp(&app.db).await {
-        Ok(_) => info!("Weekly XP stats reset successfully"),
-        Err(e) => error!("Failed to reset weekly stats: {}", e),
+/// Reports keys that look shared or resold to the admins, and blocks them
+/// from `anomaly_suspend_score` on
+pub struct Anomalies;
+
+const ANOMALY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[async_trait]
+impl Plugin for Anomalies {
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    // score each key was last reported with, reported again once it grows
+    let mut reported = HashMap::new();
+
+    let mut interval = time::interval(ANOMALY_INTERVAL);
+    while shutdown.run_until_cancelled(interval.tick()).await.is_some() {
+      if !app.is_leader() {
+        continue;
+      }
+      match detect_anomalies(&app, &mut reported).await {
+        Ok(()) => app.plugins.report_success(self.name()),
+        Err(e) => error!("Anomaly detection failed: {}", e),
       }
     }
+    Ok(())
+  }
+}
+
+async fn detect_anomalies(
+  app: &AppState,
+  reported: &mut HashMap<String, u32>,
+) -> Result<()> {
+  let config = app.config();
+  let policy = config.anomaly_policy();
+  let sv = app.sv_as(Actor::System("anomaly"));
+  sv.anomaly.prune(policy.window).await?;
+
+  let (alert, suspend) =
+    (config.anomaly_alert_score, config.anomaly_suspend_score);
+  let Some(min_score) = [alert, suspend].into_iter().filter(|&s| s > 0).min()
+  else {
+    return Ok(());
+  };
+
+  let suspects = sv.anomaly.suspects(&policy, min_score).await?;
+  reported.retain(|key, _| suspects.iter().any(|s| &s.license.key == key));
+
+  for suspect in suspects {
+    let key = &suspect.license.key;
+    if reported.get(key).is_some_and(|&score| score >= suspect.score) {
+      continue;
+    }
+    reported.insert(key.clone(), suspect.score);
+
+    let suspended = suspend > 0 && suspect.score >= suspend;
+    if suspended {
+      sv.license.set_blocked(key, true).await?;
+      app.drop_sessions(key).await?;
+      warn!("Suspended `{}` for key sharing (score {})", key, suspect.score);
+    } else if alert == 0 || suspect.score < alert {
+      continue;
+    }
+
+    let message = suspect.summary(policy.window, suspended);
+    for &admin_id in &app.admins {
+      let _ = app
+        .bot
+        .send_message(ChatId(admin_id), &message)
+        .parse_mode(ParseMode::Html)
+        .await;
+    }
   }
+  Ok(())
 }
 
 pub struct Sync;
 
 #[async_trait]
 impl Plugin for Sync {
-  async fn start(&self, app: Arc<AppState>) -> anyhow::Result<()> {
-    time::sleep(Duration::from_secs(10)).await;
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    let delay = time::sleep(Duration::from_secs(10));
+    if shutdown.run_until_cancelled(delay).await.is_none() {
+      return Ok(());
+    }
     loop {
-      info!("Starting external sync...");
-      if let Err(e) = run_sync(&app).await {
-        error!("external sync failed: {}", e);
+      if app.is_leader() {
+        info!("Starting external sync...");
+        match run_sync(&app).await {
+          Ok(()) => app.plugins.report_success(self.name()),
+          Err(e) => error!("external sync failed: {}", e),
+        }
+      }
+      let delay = time::sleep(Duration::from_hours(24));
+      if shutdown.run_until_cancelled(delay).await.is_none() {
+        return Ok(());
       }
-      time::sleep(Duration::from_hours(24)).await;
     }
   }
 }
@@ -130,28 +465,36 @@ pub struct YankedBuildsGC;
 
 #[async_trait]
 impl Plugin for YankedBuildsGC {
-  async fn start(&self, app: Arc<AppState>) -> anyhow::Result<()> {
-    let interval_secs = app.config.gc_check_interval_secs;
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    let interval_secs = app.config().gc_check_interval_secs;
     if interval_secs == 0 {
       info!("YankedBuildsGC disabled via config (0 interval)");
+      shutdown.cancelled().await;
       return Ok(());
     }
 
     info!(
       "YankedBuildsGC started (check interval: {}s, min free space: {}MB)",
       interval_secs,
-      app.config.gc_min_free_space / (1024 * 1024)
+      app.config().gc_min_free_space / (1024 * 1024)
     );
 
     let mut interval = time::interval(Duration::from_secs(interval_secs));
 
-    loop {
-      interval.tick().await;
-
-      if let Err(e) = run_yanked_builds_gc(&app).await {
-        error!("YankedBuildsGC failed: {}", e);
+    while shutdown.run_until_cancelled(interval.tick()).await.is_some() {
+      if !app.is_leader() {
+        continue;
+      }
+      match run_yanked_builds_gc(&app).await {
+        Ok(()) => app.plugins.report_success(self.name()),
+        Err(e) => error!("YankedBuildsGC failed: {}", e),
       }
     }
+    Ok(())
   }
 }
 
@@ -187,9 +530,7 @@ struct StatvfsResult {
 
 #[cfg(unix)]
 fn nix_statvfs(path: &Path) -> Option<StatvfsResult> {
-  use std::ffi::CString;
-  use std::mem::MaybeUninit;
-  use std::os::unix::ffi::OsStrExt;
+  use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
 
   let path_cstr = CString::new(path.as_os_str().as_bytes()).ok()?;
 
@@ -207,8 +548,9 @@ fn nix_statvfs(path: &Path) -> Option<StatvfsResult> {
 }
 
 async fn run_yanked_builds_gc(app: &Arc<AppState>) -> anyhow::Result<()> {
-  let min_free_space = app.config.gc_min_free_space;
-  let builds_dir = &app.config.builds_directory;
+  let config = app.config();
+  let min_free_space = config.gc_min_free_space;
+  let builds_dir = &config.builds_directory;
 
   // Check current free space
   let free_space = match get_available_space(builds_dir) {
@@ -234,7 +576,7 @@ async fn run_yanked_builds_gc(app: &Arc<AppState>) -> anyhow::Result<()> {
     min_free_space / (1024 * 1024)
   );
 
-  let sv = app.sv();
+  let sv = app.sv_as(Actor::System("yanked_builds_gc"));
   let yanked_builds = sv.build.yanked_oldest_first().await?;
 
   if yanked_builds.is_empty() {
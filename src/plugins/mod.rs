@@ -1,14 +1,25 @@
 pub mod cron;
+pub mod registry;
 pub mod server;
 pub mod steam;
 pub mod telegram;
+pub mod watch;
 
-use std::{sync::Arc, time::Duration};
+use std::{
+  sync::Arc,
+  time::{Duration, Instant},
+};
 
-use tokio::time::sleep;
+pub use registry::{Control, PluginState, Registry};
+use tokio::{
+  sync::mpsc,
+  task::{JoinError, JoinHandle},
+  time::{self, sleep},
+};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
-use crate::state::AppState;
+use crate::{prelude::Utc, state::AppState};
 
 #[async_trait::async_trait]
 pub trait Plugin: Send + Sync {
@@ -16,9 +27,17 @@ pub trait Plugin: Send + Sync {
     std::any::type_name::<Self>()
   }
 
-  async fn start(&self, app: Arc<AppState>) -> anyhow::Result<()>;
+  /// Run the plugin until it fails or `shutdown` is cancelled.
+  /// Plugins should finish the current unit of work and return `Ok(())`
+  /// once cancelled.
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()>;
 }
 
+#[derive(Default)]
 pub struct App {
   plugins: Vec<Arc<dyn Plugin>>,
 }
@@ -33,41 +52,177 @@ impl App {
     self
   }
 
-  pub async fn run(self, app: Arc<AppState>) {
-    for plugin in self.plugins {
-      let app = app.clone();
+  /// Spawn a supervisor per plugin. The returned handles complete once
+  /// `shutdown` is cancelled and the plugins have stopped.
+  pub async fn run(
+    self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> Vec<JoinHandle<()>> {
+    self
+      .plugins
+      .into_iter()
+      .map(|plugin| {
+        let control = app.plugins.register(plugin.name());
+        tokio::spawn(supervise(plugin, app.clone(), control, shutdown.clone()))
+      })
+      .collect()
+  }
+}
 
-      tokio::spawn(async move {
-        let name = plugin.name();
-        info!("SYSTEM: Service `{}` initialized", name);
+enum Exit {
+  Done(Result<anyhow::Result<()>, JoinError>),
+  Control(Control),
+}
 
-        loop {
-          let app = app.clone();
-          let plugin = plugin.clone();
+/// Wait until the plugin is started again, `false` on shutdown
+async fn wait_for_start(
+  control: &mut mpsc::UnboundedReceiver<Control>,
+  shutdown: &CancellationToken,
+) -> bool {
+  loop {
+    let cmd = tokio::select! {
+      cmd = control.recv() => cmd,
+      _ = shutdown.cancelled() => return false,
+    };
+    match cmd {
+      Some(Control::Start | Control::Restart) => return true,
+      Some(Control::Stop) => continue,
+      None => return false,
+    }
+  }
+}
 
-          let handle = tokio::spawn(async move { plugin.start(app).await });
+/// Run a plugin forever, restarting it with exponential backoff on exit
+async fn supervise(
+  plugin: Arc<dyn Plugin>,
+  app: Arc<AppState>,
+  mut control: mpsc::UnboundedReceiver<Control>,
+  shutdown: CancellationToken,
+) {
+  let name = plugin.name();
+  info!("SYSTEM: Service `{}` initialized", name);
 
-          match handle.await {
-            Ok(Ok(())) => {
-              warn!("Service `{name}` stopped unexpectedly (Ok).",);
-            }
-            Ok(Err(err)) => {
-              error!("Service `{name}` crashed with error: {err:#}.",);
-            }
-            Err(join_err) => {
-              if join_err.is_cancelled() {
-                info!("Service `{}` shutdown.", name);
-                break;
-              } else {
-                error!("Service `{}` PANICKED!", name);
-              }
+  let base = Duration::from_secs(app.config().plugin_backoff_secs);
+  let cap = Duration::from_secs(app.config().plugin_backoff_max_secs);
+  let mut backoff = base;
+  let mut restart = false;
+  let mut stopped = false;
+
+  loop {
+    if stopped {
+      app.plugins.update(name, |s| {
+        s.state = PluginState::Stopped;
+        s.started_at = None;
+      });
+      if !wait_for_start(&mut control, &shutdown).await {
+        break;
+      }
+      info!("SYSTEM: Starting service `{}`...", name);
+      stopped = false;
+      backoff = base;
+    } else if restart {
+      app.metrics.plugin_restarts.with_label_values(&[name]).inc();
+      app.plugins.update(name, |s| s.restart_count += 1);
+    }
+    restart = true;
+
+    app.plugins.update(name, |s| {
+      s.state = PluginState::Running;
+      s.started_at = Some(Utc::now().naive_utc());
+    });
+
+    let started = Instant::now();
+    let token = shutdown.child_token();
+    let mut handle = tokio::spawn({
+      let app = app.clone();
+      let plugin = plugin.clone();
+      let token = token.clone();
+      async move { plugin.start(app, token).await }
+    });
+
+    let exit = loop {
+      tokio::select! {
+        result = &mut handle => break Exit::Done(result),
+        Some(cmd) = control.recv() => {
+          if cmd != Control::Start {
+            // same grace period as for the process shutdown, read now as
+            // it may have been reloaded since the start
+            let grace =
+              Duration::from_secs(app.config().shutdown_timeout_secs);
+            token.cancel();
+            if time::timeout(grace, &mut handle).await.is_err() {
+              handle.abort();
             }
+            break Exit::Control(cmd);
           }
+        }
+      }
+    };
+
+    if shutdown.is_cancelled() {
+      info!("Service `{}` shutdown.", name);
+      break;
+    }
 
-          sleep(Duration::from_secs(5)).await;
-          info!("SYSTEM: Restarting service `{}`...", name);
+    let error = match exit {
+      Exit::Control(Control::Stop) => {
+        info!("SYSTEM: Service `{}` stopped by admin.", name);
+        stopped = true;
+        continue;
+      }
+      Exit::Control(_) => {
+        info!("SYSTEM: Restarting service `{}` by admin...", name);
+        backoff = base;
+        continue;
+      }
+      Exit::Done(Ok(Ok(()))) => {
+        warn!("Service `{name}` stopped unexpectedly (Ok).",);
+        None
+      }
+      Exit::Done(Ok(Err(err))) => {
+        error!("Service `{name}` crashed with error: {err:#}.",);
+        Some(format!("{err:#}"))
+      }
+      Exit::Done(Err(join_err)) => {
+        if join_err.is_cancelled() {
+          info!("Service `{}` shutdown.", name);
+          break;
         }
-      });
+        error!("Service `{}` PANICKED!", name);
+        Some("panicked".to_string())
+      }
+    };
+
+    // a plugin that stayed up long enough is considered healthy again
+    if started.elapsed() >= cap {
+      backoff = base;
+    }
+    let delay = backoff;
+    backoff = (backoff * 2).min(cap);
+
+    app.plugins.update(name, |s| {
+      s.started_at = None;
+      s.state = if error.is_some() {
+        PluginState::Crashed
+      } else {
+        PluginState::Restarting
+      };
+      if error.is_some() {
+        s.last_error = error;
+      }
+    });
+
+    info!("SYSTEM: Restarting service `{}` in {}s...", name, delay.as_secs());
+    tokio::select! {
+      _ = sleep(delay) => {}
+      Some(cmd) = control.recv() => stopped = cmd == Control::Stop,
+      _ = shutdown.cancelled() => break,
     }
   }
+
+  app.plugins.update(name, |s| {
+    s.state = PluginState::Stopped;
+    s.started_at = None;
+  });
 }
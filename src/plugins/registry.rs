@@ -0,0 +1,138 @@
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginState {
+  Running,
+  Crashed,
+  Restarting,
+  Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginStatus {
+  pub name: &'static str,
+  pub state: PluginState,
+  pub last_error: Option<String>,
+  pub restart_count: u32,
+  pub last_success: Option<DateTime>,
+  pub started_at: Option<DateTime>,
+}
+
+impl PluginStatus {
+  /// Short name without the crate path, e.g. `cron::GC`
+  pub fn short_name(&self) -> &'static str {
+    short_name(self.name)
+  }
+}
+
+/// Runtime commands accepted by a plugin supervisor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+  Start,
+  Stop,
+  Restart,
+}
+
+struct Entry {
+  status: Mutex<PluginStatus>,
+  control: mpsc::UnboundedSender<Control>,
+}
+
+/// Shared view of all supervised plugins, filled by `App::run`
+#[derive(Default)]
+pub struct Registry {
+  entries: DashMap<&'static str, Entry>,
+}
+
+fn short_name(name: &'static str) -> &'static str {
+  name
+    .strip_prefix(concat!(env!("CARGO_CRATE_NAME"), "::plugins::"))
+    .unwrap_or(name)
+}
+
+impl Registry {
+  pub fn register(
+    &self,
+    name: &'static str,
+  ) -> mpsc::UnboundedReceiver<Control> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let status = PluginStatus {
+      name,
+      state: PluginState::Restarting,
+      last_error: None,
+      restart_count: 0,
+      last_success: None,
+      started_at: None,
+    };
+    self
+      .entries
+      .insert(name, Entry { status: Mutex::new(status), control: tx });
+    rx
+  }
+
+  pub fn update(&self, name: &str, f: impl FnOnce(&mut PluginStatus)) {
+    if let Some(entry) = self.entries.get(name) {
+      f(&mut entry.status.lock().unwrap());
+    }
+  }
+
+  /// Called by plugins after a successful unit of work
+  pub fn report_success(&self, name: &str) {
+    self.update(name, |s| s.last_success = Some(Utc::now().naive_utc()));
+  }
+
+  /// Statuses of all plugins ordered by name
+  pub fn snapshot(&self) -> Vec<PluginStatus> {
+    let mut all: Vec<_> = self
+      .entries
+      .iter()
+      .map(|entry| entry.status.lock().unwrap().clone())
+      .collect();
+    all.sort_by_key(|s| s.name);
+    all
+  }
+
+  /// Find a plugin by full or short name (case-insensitive)
+  pub fn resolve(&self, query: &str) -> Result<&'static str> {
+    self
+      .entries
+      .iter()
+      .map(|entry| *entry.key())
+      .find(|name| {
+        name.eq_ignore_ascii_case(query)
+          || short_name(name).eq_ignore_ascii_case(query)
+      })
+      .ok_or(Error::PluginNotFound)
+  }
+
+  pub fn send(&self, name: &str, control: Control) -> Result<()> {
+    let entry = self.entries.get(name).ok_or(Error::PluginNotFound)?;
+    entry
+      .control
+      .send(control)
+      .map_err(|_| Error::Internal("plugin supervisor is gone".into()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_resolve_short_name() {
+    let registry = Registry::default();
+    let _rx = registry.register("license::plugins::cron::GC");
+
+    assert_eq!(
+      registry.resolve("cron::gc").unwrap(),
+      "license::plugins::cron::GC"
+    );
+    assert!(matches!(registry.resolve("GC"), Err(Error::PluginNotFound)));
+  }
+}
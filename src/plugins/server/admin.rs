@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use axum::{
+  Json, Router,
+  body::Bytes,
+  extract::{Path, Query, State},
+  http::{HeaderMap, StatusCode, header},
+  middleware,
+  response::IntoResponse,
+  routing::{get, post},
+};
+use serde::Deserialize;
+
+use crate::{
+  prelude::*,
+  state::AppState,
+  sv::{
+    audit::Actor,
+    transfer::{Format, Report, Strategy, Table},
+  },
+};
+
+/// Routes behind `Authorization: Bearer <token>`
+pub fn router(token: String) -> Router<Arc<AppState>> {
+  Router::new()
+    .route("/export/{table}", get(export))
+    .route("/import", post(import))
+    .layer(middleware::from_fn_with_state(Arc::<str>::from(token), super::auth))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+  pub format: Option<String>,
+}
+
+pub async fn export(
+  State(app): State<Arc<AppState>>,
+  Path(table): Path<String>,
+  Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse> {
+  let table: Table = table.parse()?;
+  let format: Format = query.format.as_deref().unwrap_or("json").parse()?;
+  let data = app.sv().transfer.export(table, format).await?;
+
+  let headers = [
+    (header::CONTENT_TYPE, format.content_type().to_string()),
+    (
+      header::CONTENT_DISPOSITION,
+      format!(
+        "attachment; filename=\"{}.{}\"",
+        table.name(),
+        format.extension()
+      ),
+    ),
+  ];
+  Ok((headers, data))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+  /// Taken from `Content-Type` if not given
+  pub format: Option<String>,
+  pub strategy: Option<String>,
+  #[serde(default)]
+  pub dry_run: bool,
+}
+
+/// Import licenses, 422 with the row errors if any row is invalid
+pub async fn import(
+  State(app): State<Arc<AppState>>,
+  Query(query): Query<ImportQuery>,
+  headers: HeaderMap,
+  body: Bytes,
+) -> Result<(StatusCode, Json<Report>)> {
+  let format: Format = match query.format {
+    Some(format) => format.parse()?,
+    None => {
+      let csv = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/csv"));
+      if csv { Format::Csv } else { Format::Json }
+    }
+  };
+  let strategy: Strategy =
+    query.strategy.as_deref().unwrap_or("skip").parse()?;
+
+  let report = app
+    .sv_as(Actor::System("api"))
+    .transfer
+    .import(format, &body, strategy, query.dry_run)
+    .await?;
+
+  let status = if report.errors.is_empty() {
+    StatusCode::OK
+  } else {
+    StatusCode::UNPROCESSABLE_ENTITY
+  };
+  Ok((status, Json(report)))
+}
@@ -1,16 +1,23 @@
-use std::{path::Path, sync::Arc};
+use std::{
+  net::{IpAddr, Ipv4Addr, SocketAddr},
+  path::Path,
+  sync::Arc,
+};
 
 use axum::{
   Json,
   body::Body,
-  extract::{Query, State},
-  http::{StatusCode, header},
+  extract::{ConnectInfo, Query, State},
+  http::{HeaderMap, StatusCode, header},
   response::IntoResponse,
 };
+use futures::TryStreamExt;
 use serde::{Deserialize, Serialize};
 use tokio_util::io::ReaderStream;
+use tracing::debug;
 
 use crate::{
+  plugins::{PluginState, registry::PluginStatus},
   prelude::*,
   state::{AppState, Session},
 };
@@ -53,71 +60,149 @@ fn generate_magic(session_id: &str, secret: &str) -> i64 {
 
 pub async fn heartbeat(
   State(app): State<Arc<AppState>>,
+  ConnectInfo(peer): ConnectInfo<SocketAddr>,
+  headers: HeaderMap,
   Json(req): Json<HeartbeatReq>,
+) -> (StatusCode, Json<HeartbeatRes>) {
+  let ip = client_ip(&headers, peer.ip(), &app.config().trusted_proxies);
+  let (status, res) = process_heartbeat(&app, req, ip).await;
+  app.metrics.heartbeats.with_label_values(&[status.as_str()]).inc();
+  (status, res)
+}
+
+/// Address of the client behind `peer`. Hops of `Forwarded` (or else
+/// `X-Forwarded-For`) are only believed while they were added by one of
+/// the `trusted` proxies, the first other address is the client.
+fn client_ip(headers: &HeaderMap, peer: IpAddr, trusted: &[IpAddr]) -> IpAddr {
+  let values =
+    |name| headers.get_all(name).iter().filter_map(|value| value.to_str().ok());
+  let mut hops: Vec<&str> = values(header::FORWARDED)
+    .flat_map(|value| value.split(','))
+    .filter_map(|element| {
+      element.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        key.trim().eq_ignore_ascii_case("for").then_some(value)
+      })
+    })
+    .collect();
+  if hops.is_empty() {
+    hops = values("x-forwarded-for").flat_map(|v| v.split(',')).collect();
+  }
+
+  let mut ip = peer;
+  for hop in hops.iter().rev() {
+    if !trusted.contains(&ip) {
+      break;
+    }
+    match parse_hop(hop) {
+      Some(hop) => ip = hop,
+      None => break,
+    }
+  }
+  ip
+}
+
+/// Parse a forwarded address, optionally quoted and with a port
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+  let hop = hop.trim().trim_matches('"');
+  if let Some(rest) = hop.strip_prefix('[') {
+    return rest.split(']').next()?.parse().ok();
+  }
+  if let Ok(ip) = hop.parse() {
+    return Some(ip);
+  }
+  // only IPv4 addresses carry a port unbracketed
+  let (ip, _port) = hop.rsplit_once(':')?;
+  ip.parse::<Ipv4Addr>().ok().map(IpAddr::V4)
+}
+
+async fn process_heartbeat(
+  app: &AppState,
+  req: HeartbeatReq,
+  ip: IpAddr,
 ) -> (StatusCode, Json<HeartbeatRes>) {
   let now = Utc::now().naive_utc();
   let magic = generate_magic(&req.session_id, &app.secret);
+  let lifetime = app.config().session_lifetime;
+  let internal = || {
+    (
+      StatusCode::INTERNAL_SERVER_ERROR,
+      Json(HeartbeatRes::invalid("Internal error")),
+    )
+  };
 
-  if let Some(mut sessions) = app.sessions.get_mut(&req.key)
-    && let Some(sess) =
-      sessions.iter_mut().find(|s| s.session_id == req.session_id)
-  {
-    sess.last_seen = now;
-    return (StatusCode::OK, Json(HeartbeatRes::ok(magic)));
+  match app.sessions.touch(&req.key, &req.session_id, lifetime).await {
+    Ok(true) => return (StatusCode::OK, Json(HeartbeatRes::ok(magic))),
+    Ok(false) => {}
+    Err(e) => {
+      error!("Failed to refresh session of `{}`: {}", req.key, e);
+      return internal();
+    }
   }
 
   let license = match app.sv().license.validate(&req.key).await {
     Ok(license) => license,
     Err(Error::LicenseNotFound) => {
-      app.drop_sessions(&req.key);
+      let _ = app.drop_sessions(&req.key).await;
       return (
         StatusCode::UNAUTHORIZED,
         Json(HeartbeatRes::invalid("Invalid license")),
       );
     }
     Err(Error::LicenseInvalid) => {
-      app.drop_sessions(&req.key);
+      let _ = app.drop_sessions(&req.key).await;
       return (
         StatusCode::FORBIDDEN,
         Json(HeartbeatRes::invalid("License expired or blocked")),
       );
     }
-    Err(_) => {
-      return (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(HeartbeatRes::invalid("Internal error")),
-      );
-    }
+    Err(_) => return internal(),
   };
 
-  let mut entry = app.sessions.entry(req.key.clone()).or_insert_with(Vec::new);
-  entry.retain(|s| {
-    (now - s.last_seen).num_seconds() < app.config.session_lifetime
-  });
-
+  let session = Session {
+    session_id: req.session_id,
+    hwid_hash: Some(req.machine_id.clone()),
+    license_type: license.license_type,
+    last_seen: now,
+  };
   let max_sessions = license.max_sessions as usize;
-  if entry.len() >= max_sessions {
-    return (
+  let opened =
+    match app.sessions.open(&req.key, session, max_sessions, lifetime).await {
+      Ok(opened) => opened,
+      Err(e) => {
+        error!("Failed to open session of `{}`: {}", req.key, e);
+        return internal();
+      }
+    };
+
+  // only opened and refused sessions are kept for anomaly detection,
+  // refreshes of live ones return above
+  let ip = Some(ip.to_string());
+  let rejected = opened.is_some();
+  if let Err(e) =
+    app.sv().anomaly.record(&req.key, &req.machine_id, ip, rejected).await
+  {
+    warn!("Failed to record heartbeat of `{}`: {}", req.key, e);
+  }
+
+  match opened {
+    None => (StatusCode::OK, Json(HeartbeatRes::ok(magic))),
+    Some(live) => (
       StatusCode::CONFLICT,
       Json(HeartbeatRes::invalid(format!(
         "Session limit reached ({}/{})",
-        entry.len(),
-        max_sessions
+        live, max_sessions
       ))),
-    );
+    ),
   }
-
-  entry.push(Session {
-    session_id: req.session_id,
-    hwid_hash: Some(req.machine_id),
-    last_seen: now,
-  });
-
-  (StatusCode::OK, Json(HeartbeatRes::ok(magic)))
 }
 
 #[derive(Debug, Deserialize)]
 pub struct MetricsReq {
+  pub key: String,
+  pub session_id: String,
+  /// `generate_magic(session_id + stats, secret)`
+  pub signature: i64,
   pub stats: String,
 }
 
@@ -125,14 +210,66 @@ pub async fn submit_metrics(
   State(app): State<Arc<AppState>>,
   Json(req): Json<MetricsReq>,
 ) -> Result<()> {
-  app.sv().stats.process_metric(&req.stats).await?;
-  Ok(())
+  let result = async {
+    if !app.has_session(&req.key, &req.session_id).await? {
+      return Err(Error::SessionInvalid);
+    }
+
+    let signed = format!("{}{}", req.session_id, req.stats);
+    if generate_magic(&signed, &app.secret) != req.signature {
+      return Err(Error::InvalidSignature);
+    }
+
+    app.sv().stats.process_metric(&req.key, &req.stats).await
+  }
+  .await;
+
+  if let Err(err) = &result {
+    let reason = match err {
+      Error::SessionInvalid => "session",
+      Error::InvalidSignature => "signature",
+      Error::Database(_) | Error::Io(_) | Error::Internal(_) => "internal",
+      _ => "payload",
+    };
+    app.metrics.ingest_errors.with_label_values(&[reason]).inc();
+
+    if reason != "internal" {
+      match app.reject_metrics(&req.key).await {
+        Ok(Some(count)) => {
+          warn!("Rejected metrics for key `{}` ({count} total): {err}", req.key)
+        }
+        Ok(None) => debug!("Rejected metrics for unknown key: {err}"),
+        Err(e) => warn!("Failed to count rejected metrics: {e}"),
+      }
+    }
+  }
+
+  result
 }
 
 pub async fn health() -> &'static str {
   "OK"
 }
 
+/// Supervisor state of every plugin, 503 if any of them is not running
+pub async fn plugins_health(
+  State(app): State<Arc<AppState>>,
+) -> (StatusCode, Json<Vec<PluginStatus>>) {
+  let plugins = app.plugins.snapshot();
+  let healthy = plugins
+    .iter()
+    .all(|p| matches!(p.state, PluginState::Running | PluginState::Stopped));
+
+  let status =
+    if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+  (status, Json(plugins))
+}
+
+pub async fn metrics(State(app): State<Arc<AppState>>) -> impl IntoResponse {
+  let metrics = app.render_metrics().await;
+  ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct DownloadQuery {
   pub token: String,
@@ -142,14 +279,17 @@ pub async fn download(
   State(app): State<Arc<AppState>>,
   Query(query): Query<DownloadQuery>,
 ) -> impl IntoResponse {
-  let version = match app.validate_download_token(&query.token) {
-    Some(v) => v,
-    None => {
+  let version = match app.validate_download_token(&query.token).await {
+    Ok(Some(v)) => v,
+    Ok(None) => {
       return Err((
         StatusCode::UNAUTHORIZED,
         "Invalid or expired download token",
       ));
     }
+    Err(_) => {
+      return Err((StatusCode::INTERNAL_SERVER_ERROR, "Internal error"));
+    }
   };
 
   let build = match app.sv().build.by_version(&version).await {
@@ -177,10 +317,13 @@ pub async fn download(
     .unwrap_or("download.bin")
     .to_string();
 
-  let stream = ReaderStream::new(file);
+  let bytes = app.metrics.download_bytes.with_label_values(&[&version]);
+  let stream = ReaderStream::new(file)
+    .inspect_ok(move |chunk| bytes.inc_by(chunk.len() as u64));
   let body = Body::from_stream(stream);
 
   // Increment download counter
+  app.metrics.downloads.with_label_values(&[&version]).inc();
   let _ = app.sv().build.increment_downloads(&version).await;
 
   let headers = [
@@ -193,3 +336,45 @@ pub async fn download(
 
   Ok((headers, body))
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for &(name, value) in pairs {
+      headers.append(name, value.parse().unwrap());
+    }
+    headers
+  }
+
+  #[test]
+  fn test_client_ip() {
+    let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+    let client: IpAddr = "203.0.113.7".parse().unwrap();
+    let xff = headers(&[("x-forwarded-for", "198.51.100.1, 203.0.113.7")]);
+
+    assert_eq!(client_ip(&xff, proxy, &[]), proxy);
+    assert_eq!(client_ip(&xff, client, &[proxy]), client);
+    assert_eq!(client_ip(&xff, proxy, &[proxy]), client);
+    assert_eq!(client_ip(&HeaderMap::new(), proxy, &[proxy]), proxy);
+
+    let chained = headers(&[("x-forwarded-for", "203.0.113.7, 10.0.0.2")]);
+    let trusted = ["10.0.0.2".parse().unwrap(), proxy];
+    assert_eq!(client_ip(&chained, proxy, &trusted), client);
+
+    let forwarded = headers(&[
+      ("forwarded", r#"for="[2001:db8::17]:4711";proto=https"#),
+      ("forwarded", "for=203.0.113.7:80;by=10.0.0.1"),
+      ("x-forwarded-for", "198.51.100.1"),
+    ]);
+    assert_eq!(client_ip(&forwarded, proxy, &[proxy]), client);
+    let trusted = [client, proxy];
+    let ip = client_ip(&forwarded, proxy, &trusted);
+    assert_eq!(ip, "2001:db8::17".parse::<IpAddr>().unwrap());
+
+    let garbage = headers(&[("x-forwarded-for", "unknown")]);
+    assert_eq!(client_ip(&garbage, proxy, &[proxy]), proxy);
+  }
+}
@@ -1,13 +1,21 @@
+mod admin;
 mod handlers;
 mod steam;
+mod webhook;
 
 use std::{net::SocketAddr, sync::Arc};
 
 use async_trait::async_trait;
 use axum::{
   Router,
+  extract::{Request, State},
+  http::header,
+  middleware::{self, Next},
+  response::Response,
   routing::{get, post},
 };
+use sha2::{Digest, Sha256};
+use tokio_util::sync::CancellationToken;
 use tower::ServiceBuilder;
 use tower_governor::{GovernorLayer, governor::GovernorConfigBuilder};
 use tower_http::{
@@ -15,13 +23,42 @@ use tower_http::{
   trace::TraceLayer,
 };
 
-use crate::{prelude::*, state::AppState};
+use crate::{plugins::telegram, prelude::*, state::AppState};
+
+/// Compare secrets through their digests, so the time taken does not depend
+/// on how much of `given` is right
+fn secret_matches(given: &str, secret: &str) -> bool {
+  Sha256::digest(given) == Sha256::digest(secret)
+}
+
+/// Let through requests with `Authorization: Bearer <token>`
+async fn auth(
+  State(token): State<Arc<str>>,
+  request: Request,
+  next: Next,
+) -> Result<Response> {
+  let given = request
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "))
+    .unwrap_or_default();
+
+  if !secret_matches(given, &token) {
+    return Err(Error::Unauthorized);
+  }
+  Ok(next.run(request).await)
+}
 
 pub struct Plugin;
 
 #[async_trait]
 impl super::Plugin for Plugin {
-  async fn start(&self, app: Arc<AppState>) -> anyhow::Result<()> {
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
     let governor_conf = Arc::new(
       GovernorConfigBuilder::default()
         .per_second(2)
@@ -32,26 +69,58 @@ impl super::Plugin for Plugin {
 
     let limiter = governor_conf.limiter().clone();
 
-    let router = Router::new()
+    let mut router = Router::new()
       .route("/health", get(handlers::health))
+      .route("/health/plugins", get(handlers::plugins_health))
       .route("/api/download", get(handlers::download))
       .route("/api/heartbeat", post(handlers::heartbeat))
       .route("/api/metrics", post(handlers::submit_metrics))
       // TODO: split configuration
       .route("/api/cache/steam/free-games", get(steam::free_games))
-      .route("/api/cache/steam/free-items", get(steam::free_items))
-      .layer(
-        ServiceBuilder::new()
-          .layer(TraceLayer::new_for_http())
-          .layer(GovernorLayer::new(governor_conf))
-          .layer(
-            CorsLayer::new()
-              .allow_origin(Any)
-              .allow_methods(Any)
-              .allow_headers(Any),
-          ),
-      )
-      .with_state(app)
+      .route("/api/cache/steam/free-items", get(steam::free_items));
+
+    // admin API is only served with a token
+    if let Ok(token) = std::env::var("ADMIN_API_TOKEN")
+      && !token.is_empty()
+    {
+      router = router.nest("/api/admin", admin::router(token));
+    }
+
+    // Prometheus exporter is only served with a token, it exposes license
+    // counts and traffic
+    if let Ok(token) = std::env::var("METRICS_TOKEN")
+      && !token.is_empty()
+    {
+      let token = Arc::<str>::from(token);
+      router = router.route(
+        "/metrics",
+        get(handlers::metrics)
+          .route_layer(middleware::from_fn_with_state(token, auth)),
+      );
+    }
+
+    let mut router = router.layer(
+      ServiceBuilder::new()
+        .layer(TraceLayer::new_for_http())
+        .layer(GovernorLayer::new(governor_conf))
+        .layer(
+          CorsLayer::new()
+            .allow_origin(Any)
+            .allow_methods(Any)
+            .allow_headers(Any),
+        ),
+    );
+
+    // Telegram is not rate limited, it retries rejected updates anyway
+    if app.webhook.is_some() {
+      router = router.route(
+        telegram::WEBHOOK_PATH,
+        post(webhook::update).layer(TraceLayer::new_for_http()),
+      );
+    }
+
+    let router = router
+      .with_state(app.clone())
       .into_make_service_with_connect_info::<SocketAddr>();
 
     let port: u16 =
@@ -60,6 +129,7 @@ impl super::Plugin for Plugin {
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     tracing::info!("HTTP Server listening on {addr}");
+    app.plugins.report_success(super::Plugin::name(self));
 
     let limiter = async {
       loop {
@@ -68,8 +138,13 @@ impl super::Plugin for Plugin {
       }
     };
 
+    // stop accepting connections on shutdown, in-flight requests
+    // (e.g. downloads) are allowed to complete
     let server = async {
-      axum::serve(listener, router).await.context("Axum server error")
+      axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown.clone().cancelled_owned())
+        .await
+        .context("Axum server error")
     };
 
     tokio::select! {
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use axum::{
+  body::Bytes,
+  extract::State,
+  http::{HeaderMap, StatusCode},
+};
+use teloxide::types::Update;
+
+use crate::{prelude::*, state::AppState};
+
+const SECRET_HEADER: &str = "x-telegram-bot-api-secret-token";
+
+/// Updates pushed by Telegram in webhook mode, anything but 200 makes
+/// Telegram deliver the update again
+pub async fn update(
+  State(app): State<Arc<AppState>>,
+  headers: HeaderMap,
+  body: Bytes,
+) -> StatusCode {
+  let Some(webhook) = &app.webhook else {
+    return StatusCode::NOT_FOUND;
+  };
+
+  let given = headers
+    .get(SECRET_HEADER)
+    .and_then(|value| value.to_str().ok())
+    .unwrap_or_default();
+  if !super::secret_matches(given, &webhook.secret) {
+    return StatusCode::UNAUTHORIZED;
+  }
+
+  // retrying an update we can't read won't help
+  let update: Update = match json::from_slice(&body) {
+    Ok(update) => update,
+    Err(err) => {
+      warn!("Dropping unreadable Telegram update: {err}");
+      return StatusCode::OK;
+    }
+  };
+
+  if webhook.push(update) {
+    StatusCode::OK
+  } else {
+    StatusCode::SERVICE_UNAVAILABLE
+  }
+}
@@ -3,6 +3,7 @@ use std::sync::Arc;
 use reqwest::Client;
 use scraper::{Html, Selector};
 use serde::Deserialize;
+use tokio_util::sync::CancellationToken;
 
 use crate::{entity::free_item, plugins::Plugin, prelude::*, state::AppState};
 
@@ -38,20 +39,43 @@ struct PackageSub {
   price_in_cents_with_discount: u32,
 }
 
+/// Wait until this replica leads, scraping is done by one of them only.
+/// `false` on shutdown.
+async fn follow(app: &AppState, shutdown: &CancellationToken) -> bool {
+  while !app.is_leader() {
+    let delay = time::sleep(Duration::from_secs(60));
+    if shutdown.run_until_cancelled(delay).await.is_none() {
+      return false;
+    }
+  }
+  true
+}
+
 pub struct FreeGames;
 
 #[async_trait]
 impl Plugin for FreeGames {
-  async fn start(&self, app: Arc<AppState>) -> anyhow::Result<()> {
-    time::sleep(Duration::from_secs(10)).await;
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    let delay = time::sleep(Duration::from_secs(10));
+    if shutdown.run_until_cancelled(delay).await.is_none() {
+      return Ok(());
+    }
 
     let client = Client::builder().user_agent(USER_AGENT).build()?;
 
     loop {
+      if !follow(&app, &shutdown).await {
+        return Ok(());
+      }
       info!("Scanning Steam for free games...");
 
       match scrape_games(&client).await {
         Ok(games) => {
+          app.metrics.steam_scrapes.with_label_values(&["games", "ok"]).inc();
           let count = games.len();
           info!("Found {} free packages. Updating DB...", count);
 
@@ -59,14 +83,23 @@ impl Plugin for FreeGames {
             error!("Failed to update DB cache: {}", e);
           } else {
             info!("DB cache updated successfully.");
+            app.plugins.report_success(self.name());
           }
         }
         Err(e) => {
+          app
+            .metrics
+            .steam_scrapes
+            .with_label_values(&["games", "error"])
+            .inc();
           error!("Steam scrape failed: {}", e);
         }
       }
 
-      time::sleep(Duration::from_secs(12 * 3600)).await;
+      let delay = time::sleep(Duration::from_secs(12 * 3600));
+      if shutdown.run_until_cancelled(delay).await.is_none() {
+        return Ok(());
+      }
     }
   }
 }
@@ -154,14 +187,25 @@ struct Sih {
 
 #[async_trait]
 impl Plugin for FreeRewards {
-  async fn start(&self, app: Arc<AppState>) -> anyhow::Result<()> {
-    time::sleep(Duration::from_secs(10)).await;
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    let delay = time::sleep(Duration::from_secs(10));
+    if shutdown.run_until_cancelled(delay).await.is_none() {
+      return Ok(());
+    }
 
     loop {
+      if !follow(&app, &shutdown).await {
+        return Ok(());
+      }
       info!("Syncing Steam Free Rewards (SIH)...");
 
       match fetch_sih_rewards().await {
         Ok(items) => {
+          app.metrics.steam_scrapes.with_label_values(&["rewards", "ok"]).inc();
           let count = items.len();
           info!("Found {} free items. Updating DB...", count);
 
@@ -169,15 +213,24 @@ impl Plugin for FreeRewards {
             error!("Failed to update DB cache (Items): {}", e);
           } else {
             info!("Items cache updated successfully.");
+            app.plugins.report_success(self.name());
           }
         }
         Err(err) => {
+          app
+            .metrics
+            .steam_scrapes
+            .with_label_values(&["rewards", "error"])
+            .inc();
           error!("SIH sync failed: {err:?}");
         }
       }
 
       // Синхронизация раз в 6 часов
-      time::sleep(Duration::from_secs(6 * 3600)).await;
+      let delay = time::sleep(Duration::from_secs(6 * 3600));
+      if shutdown.run_until_cancelled(delay).await.is_none() {
+        return Ok(());
+      }
     }
   }
 }
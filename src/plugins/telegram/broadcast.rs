@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use teloxide::{
+  ApiError, RequestError,
+  prelude::*,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode},
+};
+use tokio_util::sync::CancellationToken;
+
+use super::callback::{AdminCallback, Callback};
+use crate::{
+  entity::broadcast::{self, Audience},
+  plugins::Plugin,
+  prelude::*,
+  state::AppState,
+  sv::broadcast::Delivery,
+};
+
+/// Pause between messages, keeps well below the ~30 messages/s bot limit
+const SEND_INTERVAL: Duration = Duration::from_millis(40);
+/// Persist progress every N recipients, progress is also saved on
+/// shutdown, so only a crash sends up to this many messages twice
+const PROGRESS_EVERY: usize = 100;
+/// How often the leader looks for confirmed broadcasts
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub fn parse_audience(input: &str) -> Option<Audience> {
+  match input.to_ascii_lowercase().as_str() {
+    "all" => Some(Audience::All),
+    "active" => Some(Audience::Active),
+    "expired" => Some(Audience::Expired),
+    "trial" => Some(Audience::Trial),
+    "pro" => Some(Audience::Pro),
+    _ => None,
+  }
+}
+
+/// Keyboard delivered with the message, a download button if any
+pub fn keyboard(broadcast: &broadcast::Model) -> InlineKeyboardMarkup {
+  let rows = broadcast
+    .build_version
+    .iter()
+    .map(|version| {
+      vec![InlineKeyboardButton::callback(
+        format!("📥 Download v{}", version),
+        Callback::DownloadVersion(version.clone()).to_data(),
+      )]
+    })
+    .collect::<Vec<_>>();
+  InlineKeyboardMarkup::new(rows)
+}
+
+pub fn confirm_keyboard(id: i64) -> InlineKeyboardMarkup {
+  InlineKeyboardMarkup::new(vec![vec![
+    InlineKeyboardButton::callback(
+      "✅ Send",
+      Callback::from(AdminCallback::BroadcastSend(id)).to_data(),
+    ),
+    InlineKeyboardButton::callback(
+      "❌ Cancel",
+      Callback::from(AdminCallback::BroadcastCancel(id)).to_data(),
+    ),
+  ]])
+}
+
+async fn send(
+  bot: &Bot,
+  chat_id: ChatId,
+  broadcast: &broadcast::Model,
+) -> Result<(), RequestError> {
+  loop {
+    let result = bot
+      .send_message(chat_id, &broadcast.text)
+      .parse_mode(ParseMode::Html)
+      .reply_markup(keyboard(broadcast))
+      .await;
+
+    match result {
+      Err(RequestError::RetryAfter(after)) => {
+        time::sleep(after.duration()).await
+      }
+      other => return other.map(|_| ()),
+    }
+  }
+}
+
+/// Delivers confirmed broadcasts on the leader. Progress is saved as it
+/// goes, so a broadcast interrupted by a restart resumes where it stopped.
+pub struct Broadcasts;
+
+#[async_trait]
+impl Plugin for Broadcasts {
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    let mut interval = time::interval(POLL_INTERVAL);
+    while shutdown.run_until_cancelled(interval.tick()).await.is_some() {
+      if !app.is_leader() {
+        continue;
+      }
+
+      let broadcasts = match app.sv().broadcast.sending().await {
+        Ok(broadcasts) => broadcasts,
+        Err(e) => {
+          error!("Failed to load confirmed broadcasts: {}", e);
+          continue;
+        }
+      };
+      for broadcast in broadcasts {
+        if !deliver(&app, broadcast, &shutdown).await {
+          break;
+        }
+      }
+      app.plugins.report_success(self.name());
+    }
+    Ok(())
+  }
+}
+
+/// Deliver a confirmed broadcast from its saved progress and report the
+/// stats to its author. Returns `false` if it was interrupted by shutdown
+/// or a lost leadership, it is resumed later then.
+async fn deliver(
+  app: &AppState,
+  broadcast: broadcast::Model,
+  shutdown: &CancellationToken,
+) -> bool {
+  let sv = app.sv().broadcast;
+  let recipients = match sv.remaining(&broadcast).await {
+    Ok(recipients) => recipients,
+    Err(e) => {
+      error!("Broadcast #{} failed: {}", broadcast.id, e);
+      return false;
+    }
+  };
+
+  let mut delivery = Delivery::from(&broadcast);
+  if delivery.last_recipient.is_some() {
+    info!("Broadcast #{} resumed for {} users", broadcast.id, recipients.len());
+  } else {
+    info!("Broadcast #{} started for {} users", broadcast.id, recipients.len());
+  }
+
+  for (i, &user_id) in recipients.iter().enumerate() {
+    if shutdown.is_cancelled() || !app.is_leader() {
+      if let Err(e) = sv.progress(broadcast.id, delivery, false).await {
+        error!("Failed to save broadcast #{} progress: {}", broadcast.id, e);
+      }
+      info!(
+        "Broadcast #{} interrupted, {} users left",
+        broadcast.id,
+        recipients.len() - i
+      );
+      return false;
+    }
+
+    match send(&app.bot, ChatId(user_id), &broadcast).await {
+      Ok(()) => delivery.sent += 1,
+      Err(RequestError::Api(
+        ApiError::BotBlocked
+        | ApiError::UserDeactivated
+        | ApiError::ChatNotFound,
+      )) => delivery.blocked += 1,
+      Err(e) => {
+        warn!("Broadcast #{} to {} failed: {}", broadcast.id, user_id, e);
+        delivery.failed += 1;
+      }
+    }
+    delivery.last_recipient = Some(user_id);
+
+    if (i + 1) % PROGRESS_EVERY == 0 {
+      let _ = sv.progress(broadcast.id, delivery, false).await;
+    }
+    time::sleep(SEND_INTERVAL).await;
+  }
+
+  if let Err(e) = sv.progress(broadcast.id, delivery, true).await {
+    error!("Failed to save broadcast #{} stats: {}", broadcast.id, e);
+  }
+
+  info!("Broadcast #{} finished: {:?}", broadcast.id, delivery);
+
+  let _ = app
+    .bot
+    .send_message(
+      ChatId(broadcast.created_by),
+      format!(
+        "📣 <b>Broadcast #{} finished</b>\n\n\
+        Sent: {}\n\
+        Blocked: {}\n\
+        Failed: {}",
+        broadcast.id, delivery.sent, delivery.blocked, delivery.failed
+      ),
+    )
+    .parse_mode(ParseMode::Html)
+    .await;
+  true
+}
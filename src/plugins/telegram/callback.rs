@@ -6,10 +6,16 @@ use teloxide::{
   types::{InlineKeyboardButton, InlineKeyboardMarkup},
 };
 
-use super::ReplyBot;
+use super::{ReplyBot, users::Filter};
 use crate::{
+  entity::{
+    LicenseType, campaign,
+    staff::{Permission, Role},
+  },
+  i18n::{self, Lang, tr},
   prelude::*,
   state::{AppState, Services},
+  sv::{self, audit::Actor},
 };
 
 /// Callback data enum - provides type-safe callback handling
@@ -17,9 +23,14 @@ use crate::{
 pub enum Callback {
   Profile,
   License,
-  Trial,
+  ClaimPromo(String),
   Download,
   DownloadVersion(String),
+  Leaderboard,
+  Redeem,
+  Language(String),
+  /// Admin-only actions, checked once in [`handle`]
+  Admin(AdminCallback),
   Buy,
   PayManual,
   Back,
@@ -31,9 +42,13 @@ impl Callback {
     match self {
       Callback::Profile => "profile".to_string(),
       Callback::License => "license".to_string(),
-      Callback::Trial => "trial".to_string(),
+      Callback::ClaimPromo(name) => format!("promo:{}", name),
       Callback::Download => "download".to_string(),
       Callback::DownloadVersion(v) => format!("dl_ver:{}", v),
+      Callback::Leaderboard => "leaderboard".to_string(),
+      Callback::Redeem => "redeem".to_string(),
+      Callback::Language(code) => format!("lang:{}", code),
+      Callback::Admin(admin) => format!("adm:{}", admin.to_data()),
       Callback::Buy => "buy".to_string(),
       Callback::PayManual => "pay_man".to_string(),
       Callback::Back => "back".to_string(),
@@ -45,66 +60,209 @@ impl Callback {
     match data {
       "profile" => Some(Callback::Profile),
       "license" => Some(Callback::License),
-      "trial" => Some(Callback::Trial),
       "download" => Some(Callback::Download),
+      "leaderboard" => Some(Callback::Leaderboard),
+      "redeem" => Some(Callback::Redeem),
       "buy" => Some(Callback::Buy),
       "pay_man" => Some(Callback::PayManual),
       "back" => Some(Callback::Back),
       _ if data.starts_with("dl_ver:") => {
         Some(Callback::DownloadVersion(data[7..].to_string()))
       }
+      _ if data.starts_with("promo:") => {
+        Some(Callback::ClaimPromo(data[6..].to_string()))
+      }
+      _ if data.starts_with("lang:") => {
+        Some(Callback::Language(data[5..].to_string()))
+      }
+      _ if data.starts_with("adm:") => {
+        AdminCallback::from_data(&data[4..]).map(Callback::Admin)
+      }
+      _ => None,
+    }
+  }
+}
+
+/// Callbacks of admin views, serialized under the `adm:` prefix
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCallback {
+  BroadcastSend(i64),
+  BroadcastCancel(i64),
+  Users {
+    filter: Filter,
+    page: usize,
+    query: String,
+  },
+  UserCard(i64),
+  UserKick(i64),
+  LicenseCard(String),
+  /// Add days to a license
+  Extend(String, u64),
+  /// Ask for a custom duration, applied with `/buy`
+  ExtendCustom(String),
+  Block(String, bool),
+  Kick(String),
+  SetTier(String, LicenseType),
+  /// Apply a backup checked by `/restore`
+  RestoreConfirm(u64),
+  RestoreCancel(u64),
+}
+
+impl AdminCallback {
+  pub fn permission(&self) -> Permission {
+    match self {
+      AdminCallback::BroadcastSend(_) | AdminCallback::BroadcastCancel(_) => {
+        Permission::ManageMarketing
+      }
+      AdminCallback::Users { .. }
+      | AdminCallback::UserCard(_)
+      | AdminCallback::LicenseCard(_) => Permission::ViewUsers,
+      AdminCallback::Extend(..) | AdminCallback::ExtendCustom(_) => {
+        Permission::ExtendLicense
+      }
+      AdminCallback::UserKick(_)
+      | AdminCallback::Block(..)
+      | AdminCallback::Kick(_)
+      | AdminCallback::SetTier(..) => Permission::ManageLicenses,
+      AdminCallback::RestoreConfirm(_) | AdminCallback::RestoreCancel(_) => {
+        Permission::Backup
+      }
+    }
+  }
+
+  pub fn to_data(&self) -> String {
+    match self {
+      AdminCallback::BroadcastSend(id) => format!("bc_send:{}", id),
+      AdminCallback::BroadcastCancel(id) => format!("bc_cancel:{}", id),
+      AdminCallback::Users { filter, page, query } => {
+        format!("users:{}:{}:{}", filter.code(), page, query)
+      }
+      AdminCallback::UserCard(id) => format!("ucard:{}", id),
+      AdminCallback::UserKick(id) => format!("ukick:{}", id),
+      AdminCallback::LicenseCard(key) => format!("lcard:{}", key),
+      AdminCallback::Extend(key, days) => format!("lext:{}:{}", days, key),
+      AdminCallback::ExtendCustom(key) => format!("lextc:{}", key),
+      AdminCallback::Block(key, blocked) => {
+        format!("lblock:{}:{}", u8::from(*blocked), key)
+      }
+      AdminCallback::Kick(key) => format!("lkick:{}", key),
+      AdminCallback::SetTier(key, ty) => {
+        let ty = match ty {
+          LicenseType::Trial => 't',
+          LicenseType::Pro => 'p',
+        };
+        format!("ltier:{}:{}", ty, key)
+      }
+      AdminCallback::RestoreConfirm(id) => format!("rst_ok:{}", id),
+      AdminCallback::RestoreCancel(id) => format!("rst_cancel:{}", id),
+    }
+  }
+
+  pub fn from_data(data: &str) -> Option<Self> {
+    let (action, args) = data.split_once(':')?;
+    match action {
+      "bc_send" => args.parse().ok().map(AdminCallback::BroadcastSend),
+      "bc_cancel" => args.parse().ok().map(AdminCallback::BroadcastCancel),
+      "users" => {
+        let mut parts = args.splitn(3, ':');
+        let filter = Filter::from_code(parts.next()?.chars().next()?)?;
+        let page = parts.next()?.parse().ok()?;
+        let query = parts.next().unwrap_or_default().to_string();
+        Some(AdminCallback::Users { filter, page, query })
+      }
+      "ucard" => args.parse().ok().map(AdminCallback::UserCard),
+      "ukick" => args.parse().ok().map(AdminCallback::UserKick),
+      "lcard" => Some(AdminCallback::LicenseCard(args.into())),
+      "lext" => {
+        let (days, key) = args.split_once(':')?;
+        Some(AdminCallback::Extend(key.into(), days.parse().ok()?))
+      }
+      "lextc" => Some(AdminCallback::ExtendCustom(args.into())),
+      "lblock" => {
+        let (blocked, key) = args.split_once(':')?;
+        Some(AdminCallback::Block(key.into(), blocked == "1"))
+      }
+      "lkick" => Some(AdminCallback::Kick(args.into())),
+      "ltier" => {
+        let (ty, key) = args.split_once(':')?;
+        let ty = match ty {
+          "t" => LicenseType::Trial,
+          "p" => LicenseType::Pro,
+          _ => return None,
+        };
+        Some(AdminCallback::SetTier(key.into(), ty))
+      }
+      "rst_ok" => args.parse().ok().map(AdminCallback::RestoreConfirm),
+      "rst_cancel" => args.parse().ok().map(AdminCallback::RestoreCancel),
       _ => None,
     }
   }
 }
 
-pub fn main_menu(is_promo: bool) -> InlineKeyboardMarkup {
+impl From<AdminCallback> for Callback {
+  fn from(admin: AdminCallback) -> Self {
+    Callback::Admin(admin)
+  }
+}
+
+pub fn main_menu(
+  lang: Lang,
+  promos: &[campaign::Model],
+) -> InlineKeyboardMarkup {
   let mut rows = vec![
     vec![InlineKeyboardButton::callback(
-      "👤 My Profile",
+      tr!(lang, "menu.profile"),
       Callback::Profile.to_data(),
     )],
     vec![InlineKeyboardButton::callback(
-      "🔑 My License",
+      tr!(lang, "menu.license"),
       Callback::License.to_data(),
     )],
     vec![InlineKeyboardButton::callback(
-      "💳 Buy License",
+      tr!(lang, "menu.buy"),
       Callback::Buy.to_data(),
     )],
     vec![InlineKeyboardButton::callback(
-      "📥 Download Panel",
+      tr!(lang, "menu.redeem"),
+      Callback::Redeem.to_data(),
+    )],
+    vec![InlineKeyboardButton::callback(
+      tr!(lang, "menu.download"),
       Callback::Download.to_data(),
     )],
+    vec![InlineKeyboardButton::callback(
+      tr!(lang, "menu.leaderboard"),
+      Callback::Leaderboard.to_data(),
+    )],
   ];
 
-  if is_promo {
+  for promo in promos {
     rows.push(vec![InlineKeyboardButton::callback(
-      "🆓 Get Free Trial",
-      Callback::Trial.to_data(),
+      format!("🆓 {}", promo.title),
+      Callback::ClaimPromo(promo.name.clone()).to_data(),
     )]);
   }
 
   InlineKeyboardMarkup::new(rows)
 }
 
-fn payment_method_menu() -> InlineKeyboardMarkup {
+fn payment_method_menu(lang: Lang) -> InlineKeyboardMarkup {
   InlineKeyboardMarkup::new(vec![
     vec![InlineKeyboardButton::callback(
-      "👤 Manual Purchase",
+      tr!(lang, "buy.manual"),
       Callback::PayManual.to_data(),
     )],
     // vec![InlineKeyboardButton::callback("CryptoBot (Auto)", CB_PAY_CRYPTO)],
     vec![InlineKeyboardButton::callback(
-      "« Back to Menu",
+      tr!(lang, "menu.back"),
       Callback::Back.to_data(),
     )],
   ])
 }
 
-fn back_keyboard() -> InlineKeyboardMarkup {
+fn back_keyboard(lang: Lang) -> InlineKeyboardMarkup {
   InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
-    "« Back to Menu",
+    tr!(lang, "menu.back"),
     Callback::Back.to_data(),
   )]])
 }
@@ -120,6 +278,13 @@ pub async fn handle(
     return Ok(());
   };
 
+  let bot = match sv.user.update_profile(bot.user_id, bot.profile.clone()).await
+  {
+    Ok(user) => bot.with_stored_lang(&user),
+    Err(_) => bot,
+  };
+  let lang = bot.lang;
+
   match callback {
     Callback::Profile => {
       handle_profile_view(&sv, &bot).await?;
@@ -127,8 +292,8 @@ pub async fn handle(
     Callback::License => {
       handle_license_edit(&sv, &bot).await?;
     }
-    Callback::Trial => {
-      handle_trial_claim(&sv, &bot).await?;
+    Callback::ClaimPromo(name) => {
+      handle_promo_claim(&sv, &bot, &name).await?;
     }
     Callback::Download => {
       if let Ok(keys) = sv.license.by_user(bot.chat_id.0, false).await
@@ -137,43 +302,192 @@ pub async fn handle(
         handle_download(&sv, &bot, &app).await?;
       } else {
         bot
-          .edit_with_keyboard("You have no active license!", back_keyboard())
+          .edit_with_keyboard(tr!(lang, "license.none"), back_keyboard(lang))
           .await?;
       }
     }
     Callback::Buy => {
-      let text = "💳 <b>Purchase License</b>\n\n\
-        Select a payment method below.";
-      bot.edit_with_keyboard(text, payment_method_menu()).await?;
+      bot
+        .edit_with_keyboard(tr!(lang, "buy.title"), payment_method_menu(lang))
+        .await?;
     }
     Callback::PayManual => {
-      let text = "👤 <b>Manual Purchase</b>\n\n\
-        To purchase a license via USDT or other methods, please contact our support:\n\n\
-        👉 @y_a_c_s_p\n\n\
-        <i>Send a message with \"I want to buy license\"</i>";
-
       let kb = InlineKeyboardMarkup::new(vec![
         vec![InlineKeyboardButton::url(
-          "Open Chat with Support",
+          tr!(lang, "buy.support"),
           Url::parse("https://t.me/y_a_c_s_p").expect("invalid link, what???"),
         )],
-        vec![InlineKeyboardButton::callback("« Back", Callback::Buy.to_data())],
+        vec![InlineKeyboardButton::callback(
+          tr!(lang, "menu.back_short"),
+          Callback::Buy.to_data(),
+        )],
       ]);
 
-      bot.edit_with_keyboard(text, kb).await?;
+      bot.edit_with_keyboard(tr!(lang, "buy.manual_text"), kb).await?;
     }
     Callback::Back => {
-      let text = "<b>Yet Another Counter Strike Panel!</b>\n\n\
-        Use the buttons below to navigate.\n\
-        Read docs: https://yacsp.gitbook.io/yacsp\n\
-        Contact support: @y_a_c_s_p";
       bot
-        .edit_with_keyboard(text, main_menu(sv.license.is_promo_active()))
+        .edit_with_keyboard(
+          tr!(lang, "menu.welcome"),
+          main_menu(lang, &sv.campaign.active().await.unwrap_or_default()),
+        )
         .await?;
     }
     Callback::DownloadVersion(version) => {
-      handle_download_version(&sv, &bot, &app, &version).await?;
+      // also reachable from broadcasts, not only from the download menu
+      if let Ok(keys) = sv.license.by_user(bot.user_id, false).await
+        && !keys.is_empty()
+      {
+        handle_download_version(&sv, &bot, &app, &version).await?;
+      } else {
+        bot
+          .reply_with_keyboard(tr!(lang, "license.none"), back_keyboard(lang))
+          .await?;
+      }
+    }
+    Callback::Admin(admin) => match app.role_of(bot.user_id).await {
+      Some(role) if role.can(admin.permission()) => {
+        handle_admin(&app, &bot, role, admin).await?;
+      }
+      role => {
+        warn!(
+          "{} ({:?}) is not allowed to send admin callback {:?}",
+          bot.user_id, role, admin
+        );
+      }
+    },
+    Callback::Leaderboard => {
+      handle_leaderboard(&sv, &bot, &app).await?;
+    }
+    Callback::Redeem => {
+      bot
+        .edit_with_keyboard(tr!(lang, "redeem.hint"), back_keyboard(lang))
+        .await?;
+    }
+    Callback::Language(code) => {
+      let lang = Lang::from_code(&code);
+      if let Err(e) = sv.user.set_language(bot.user_id, lang).await {
+        warn!("Failed to set language of {}: {}", bot.user_id, e);
+      }
+      handle_profile_view(&sv, &ReplyBot { lang, ..bot }).await?;
+    }
+  }
+
+  Ok(())
+}
+
+async fn handle_admin(
+  app: &Arc<AppState>,
+  bot: &ReplyBot,
+  role: Role,
+  admin: AdminCallback,
+) -> ResponseResult<()> {
+  use super::users;
+
+  let view = match admin {
+    AdminCallback::BroadcastSend(id) => {
+      return handle_broadcast_send(app, bot, id).await;
+    }
+    AdminCallback::BroadcastCancel(id) => {
+      let text = match app.sv().broadcast.cancel(id).await {
+        Ok(true) => format!("❌ Broadcast #{} cancelled", id),
+        Ok(false) => format!("Broadcast #{} is no longer pending", id),
+        Err(e) => format!("❌ {}", e.user_message()),
+      };
+      return bot.edit_html(text).await;
+    }
+    AdminCallback::RestoreConfirm(id) => {
+      bot.edit_html(format!("⏳ Restoring backup #{}...", id)).await?;
+      let text = match app.restore(id, Actor::Admin(bot.user_id)).await {
+        Ok(snapshot) => format!(
+          "✅ Backup #{} restored, sessions dropped.\n\
+          Pre-restore snapshot: <code>{}</code>",
+          id,
+          snapshot.display()
+        ),
+        Err(e) => format!(
+          "❌ Restore failed: {}",
+          teloxide::utils::html::escape(&format!("{:#}", e))
+        ),
+      };
+      return bot.edit_html(text).await;
+    }
+    AdminCallback::RestoreCancel(id) => {
+      let text = if app.cancel_restore(id).await {
+        format!("❌ Restore #{} cancelled", id)
+      } else {
+        format!("Restore #{} is no longer pending", id)
+      };
+      return bot.edit_html(text).await;
     }
+    AdminCallback::Users { filter, page, query } => {
+      users::page(app, filter, &query, page).await
+    }
+    AdminCallback::UserCard(id) => users::card(app, role, id).await,
+    AdminCallback::UserKick(id) => match users::kick(app, id).await {
+      Ok(()) => users::card(app, role, id).await,
+      Err(e) => Err(e),
+    },
+    AdminCallback::LicenseCard(key) => {
+      users::license_card(app, role, &key).await
+    }
+    AdminCallback::ExtendCustom(key) => {
+      let text = format!(
+        "Send the duration with:\n<code>/buy {} 30d</code>\n\n\
+        Examples: 30d, 2w, 1h30m",
+        key
+      );
+      return bot.reply_html(text).await.map(|_| ());
+    }
+    action @ (AdminCallback::Extend(..)
+    | AdminCallback::Block(..)
+    | AdminCallback::Kick(_)
+    | AdminCallback::SetTier(..)) => {
+      users::license_action(app, role, Actor::Admin(bot.user_id), action).await
+    }
+  };
+
+  edit_admin_view(bot, view).await
+}
+
+/// Show an admin view in place of the current message
+async fn edit_admin_view(
+  bot: &ReplyBot,
+  view: Result<(String, InlineKeyboardMarkup)>,
+) -> ResponseResult<()> {
+  match view {
+    Ok((text, keyboard)) => bot.edit_with_keyboard(text, keyboard).await,
+    Err(e) => bot.edit_html(format!("❌ {}", e.user_message())).await,
+  }
+}
+
+async fn handle_broadcast_send(
+  app: &Arc<AppState>,
+  bot: &ReplyBot,
+  id: i64,
+) -> ResponseResult<()> {
+  let sv = app.sv();
+
+  let broadcast = match sv.broadcast.start(id).await {
+    Ok(true) => sv.broadcast.by_id(id).await.ok().flatten(),
+    Ok(false) => {
+      bot.edit_html(format!("Broadcast #{} is no longer pending", id)).await?;
+      return Ok(());
+    }
+    Err(e) => {
+      bot.edit_html(format!("❌ {}", e.user_message())).await?;
+      return Ok(());
+    }
+  };
+
+  if let Some(broadcast) = broadcast {
+    // delivered by the `Broadcasts` plugin, which survives restarts
+    bot
+      .edit_html(format!(
+        "📤 Sending broadcast #{} to {} users...",
+        id, broadcast.total
+      ))
+      .await?;
   }
 
   Ok(())
@@ -185,43 +499,61 @@ async fn handle_profile_view(
 ) -> ResponseResult<()> {
   let user = sv.user.by_id(bot.user_id).await.ok().flatten();
 
+  let lang = bot.lang;
   let reg_date = match user {
     Some(u) => utils::format_date(u.reg_date),
-    None => "Unknown".into(),
+    None => tr!(lang, "profile.unknown"),
   };
 
   let stats = sv.stats.display_stats(bot.user_id).await.ok();
 
-  let mut text = format!(
-    "👤 <b>My Profile</b>\n\n\
-    <b>User ID:</b> <code>{}</code>\n\
-    <b>Registered:</b> {}",
-    bot.user_id, reg_date
-  );
+  let mut text =
+    tr!(lang, "profile.title", id = bot.user_id, registered = reg_date);
+
+  if let Ok(referrals) = sv.referral.summary(bot.user_id).await {
+    text.push_str("\n\n");
+    text.push_str(&tr!(
+      lang,
+      "profile.referrals",
+      count = referrals.referrals,
+      days = referrals.earned_days
+    ));
+    let payload = format!("{}{}", sv::referral::PAYLOAD_PREFIX, bot.user_id);
+    if let Some(link) = bot.deep_link(&payload).await {
+      text.push('\n');
+      text.push_str(&tr!(lang, "profile.referral_link", link = link));
+    }
+  }
 
   if let Some(s) = stats {
     // Базовая стата
-    text.push_str(&format!(
-      "\n\n<b>📊 Farming Stats:</b>\n\
-        Weekly XP: {}\n\
-        Total XP: {}\n\
-        Drops: {}\n\
-        Runtime: {:.1}h",
-      s.weekly_xp, s.total_xp, s.drops_count, s.runtime_hours
+    text.push_str("\n\n");
+    text.push_str(&tr!(
+      lang,
+      "profile.stats",
+      weekly = s.weekly_xp,
+      total = s.total_xp,
+      drops = s.drops_count,
+      runtime = format!("{:.1}", s.runtime_hours)
     ));
 
     if let Some(meta) = s.meta {
       if !meta.network.routes.is_empty() {
-        text.push_str(&format!(
-          "\n🌐 <b>Routes:</b> {}",
-          meta.network.routes.join(", ")
+        text.push('\n');
+        text.push_str(&tr!(
+          lang,
+          "profile.routes",
+          routes = meta.network.routes.join(", ")
         ));
       }
 
       if meta.performance.avg_fps > 0.0 {
-        text.push_str(&format!(
-          "\n🚀 <b>Perf:</b> {:.0} FPS | {} MB",
-          meta.performance.avg_fps, meta.performance.avg_ram_mb
+        text.push('\n');
+        text.push_str(&tr!(
+          lang,
+          "profile.perf",
+          fps = format!("{:.0}", meta.performance.avg_fps),
+          ram = meta.performance.avg_ram_mb
         ));
       }
 
@@ -229,15 +561,69 @@ async fn handle_profile_view(
       states.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
 
       if let Some((top_state, duration)) = states.first() {
-        text.push_str(&format!(
-          "\n⏳ <b>Top State:</b> {top_state} ({:.1}h)",
-          *duration / 3600.0
+        text.push('\n');
+        text.push_str(&tr!(
+          lang,
+          "profile.top_state",
+          state = top_state,
+          hours = format!("{:.1}", *duration / 3600.0)
         ));
       }
     }
   }
 
-  bot.edit_with_keyboard(text, back_keyboard()).await?;
+  let keyboard = InlineKeyboardMarkup::new(vec![
+    vec![InlineKeyboardButton::callback(
+      format!("🌐 {}", lang.next().name()),
+      Callback::Language(lang.next().code().into()).to_data(),
+    )],
+    vec![InlineKeyboardButton::callback(
+      tr!(lang, "menu.back"),
+      Callback::Back.to_data(),
+    )],
+  ]);
+
+  bot.edit_with_keyboard(text, keyboard).await?;
+
+  Ok(())
+}
+
+async fn handle_leaderboard(
+  sv: &Services<'_>,
+  bot: &ReplyBot,
+  app: &AppState,
+) -> ResponseResult<()> {
+  let limit = app.config().leaderboard_size;
+  let current = sv.leaderboard.current(limit).await.unwrap_or_default();
+
+  let lang = bot.lang;
+  let anonymous = tr!(lang, "leaderboard.anonymous");
+
+  let mut text = format!("{}\n\n", tr!(lang, "leaderboard.title"));
+  if current.is_empty() {
+    text.push_str(&format!("{}\n", tr!(lang, "leaderboard.empty")));
+  } else {
+    text.push_str(&utils::format_leaderboard(&current, false, &anonymous));
+  }
+
+  if let Ok(Some(rank)) = sv.leaderboard.rank_of(bot.user_id).await {
+    text
+      .push_str(&format!("\n{}\n", tr!(lang, "leaderboard.rank", rank = rank)));
+  }
+
+  if let Ok(Some((week_start, winners))) = sv.leaderboard.last_week(3).await
+    && !winners.is_empty()
+  {
+    text.push_str(&format!(
+      "\n{}\n{}",
+      tr!(lang, "leaderboard.last_week", date = utils::format_date(week_start)),
+      utils::format_leaderboard(&winners, false, &anonymous)
+    ));
+  }
+
+  text.push_str(&format!("\n{}", tr!(lang, "leaderboard.hint")));
+
+  bot.edit_with_keyboard(text, back_keyboard(bot.lang)).await?;
 
   Ok(())
 }
@@ -250,13 +636,13 @@ async fn handle_license_edit(
 
   match sv.license.by_user(bot.user_id, false).await {
     Ok(licenses) if !licenses.is_empty() => {
-      let mut text = String::from("🔑 <b>Your Licenses:</b>\n");
+      let mut text = format!("{}\n", tr!(bot.lang, "license.title"));
 
       for license in licenses {
         let status = if license.expires_at > now {
           format!("⏳ {}", utils::format_duration(license.expires_at - now))
         } else {
-          "❌ Expired".into()
+          tr!(bot.lang, "license.expired")
         };
 
         text.push_str(&format!(
@@ -265,11 +651,14 @@ async fn handle_license_edit(
         ));
       }
 
-      bot.edit_with_keyboard(text, back_keyboard()).await?;
+      bot.edit_with_keyboard(text, back_keyboard(bot.lang)).await?;
     }
     _ => {
       bot
-        .edit_with_keyboard("You have no active license!", back_keyboard())
+        .edit_with_keyboard(
+          tr!(bot.lang, "license.none"),
+          back_keyboard(bot.lang),
+        )
         .await?;
     }
   }
@@ -277,30 +666,35 @@ async fn handle_license_edit(
   Ok(())
 }
 
-async fn handle_trial_claim(
+async fn handle_promo_claim(
   sv: &Services<'_>,
   bot: &ReplyBot,
+  name: &str,
 ) -> ResponseResult<()> {
-  let promo_name = "first_promo";
-
-  match sv.license.claim_promo(bot.user_id, promo_name).await {
+  match sv.campaign.claim(bot.user_id, name).await {
     Ok(license) => {
-      let text = format!(
-        "🎉 <b>Success!</b>\n\n\
-        Here is your FREE week license:\n\
-        <code>{}</code>\n\n\
-        Download the software using the Download button!",
-        license.key
+      let days = (license.expires_at - license.created_at).num_days();
+      let text = tr!(
+        bot.lang,
+        "promo.claimed",
+        days = days,
+        tier = format!("{:?}", license.license_type),
+        key = license.key
       );
-      bot.reply_with_keyboard(text, back_keyboard()).await?;
+      bot.reply_with_keyboard(text, back_keyboard(bot.lang)).await?;
     }
-    Err(e) => {
-      let msg = match e {
-        Error::Promo(Promo::Inactive) => "Promo is not active right now.",
-        Error::Promo(Promo::Claimed) => "You have already claimed this promo",
-        _ => "An error occurred.",
-      };
-      bot.reply_with_keyboard(msg, back_keyboard()).await?;
+    Err(e @ Error::Promo(_)) => {
+      bot
+        .reply_with_keyboard(i18n::error(bot.lang, &e), back_keyboard(bot.lang))
+        .await?;
+    }
+    Err(_) => {
+      bot
+        .reply_with_keyboard(
+          tr!(bot.lang, "error.generic"),
+          back_keyboard(bot.lang),
+        )
+        .await?;
     }
   }
 
@@ -317,8 +711,8 @@ async fn handle_download(
   if builds.is_empty() {
     bot
       .edit_with_keyboard(
-        "❌ No builds available yet. Contact support.",
-        back_keyboard(),
+        tr!(bot.lang, "download.no_builds"),
+        back_keyboard(bot.lang),
       )
       .await?;
     return Ok(());
@@ -333,7 +727,7 @@ async fn handle_download(
   let mut rows = Vec::new();
   for build in &builds {
     let label = if Some(build.id) == builds.first().map(|b| b.id) {
-      format!("📥 v{} (latest)", build.version)
+      format!("📥 v{} {}", build.version, tr!(bot.lang, "download.latest"))
     } else {
       format!("📥 v{}", build.version)
     };
@@ -343,14 +737,16 @@ async fn handle_download(
     )]);
   }
   rows.push(vec![InlineKeyboardButton::callback(
-    "« Back to Menu",
+    tr!(bot.lang, "menu.back"),
     Callback::Back.to_data(),
   )]);
 
-  let text = "📥 <b>Select Version</b>\n\n\
-    Choose which version to download:";
-
-  bot.edit_with_keyboard(text, InlineKeyboardMarkup::new(rows)).await?;
+  bot
+    .edit_with_keyboard(
+      tr!(bot.lang, "download.select"),
+      InlineKeyboardMarkup::new(rows),
+    )
+    .await?;
 
   Ok(())
 }
@@ -365,26 +761,37 @@ async fn handle_download_version(
     Ok(Some(build)) if build.is_active => {
       let path = Path::new(&build.file_path);
       if path.exists() {
-        let token = app.create_download_token(&build.version);
+        let token = match app.create_download_token(&build.version).await {
+          Ok(token) => token,
+          Err(e) => {
+            error!("Failed to create download token: {}", e);
+            bot
+              .edit_with_keyboard(
+                tr!(bot.lang, "error.generic"),
+                back_keyboard(bot.lang),
+              )
+              .await?;
+            return Ok(());
+          }
+        };
         let download_url =
-          format!("{}/api/download?token={}", app.config.base_url, token);
-
-        let text = format!(
-          "<b>YACS Panel v{}</b>\n\n\
-          {}\n\n\
-          📥 <a href=\"{}\">Click here to download</a>\n\n\
-          <i>⚠️ Link expires in 10 minutes</i>",
-          build.version,
-          build.changelog.as_deref().unwrap_or(""),
-          download_url
+          format!("{}/api/download?token={}", app.config().base_url, token);
+
+        let text = tr!(
+          bot.lang,
+          "download.link",
+          version = build.version,
+          changelog = build.changelog.as_deref().unwrap_or(""),
+          url = download_url,
+          minutes = app.config().download_token_lifetime / 60
         );
 
-        bot.edit_with_keyboard(text, back_keyboard()).await?;
+        bot.edit_with_keyboard(text, back_keyboard(bot.lang)).await?;
       } else {
         bot
           .edit_with_keyboard(
-            "❌ Build file not found. Contact support.",
-            back_keyboard(),
+            tr!(bot.lang, "download.file_missing"),
+            back_keyboard(bot.lang),
           )
           .await?;
       }
@@ -392,8 +799,8 @@ async fn handle_download_version(
     _ => {
       bot
         .edit_with_keyboard(
-          "❌ Build not available. Contact support.",
-          back_keyboard(),
+          tr!(bot.lang, "download.unavailable"),
+          back_keyboard(bot.lang),
         )
         .await?;
     }
@@ -401,3 +808,50 @@ async fn handle_download_version(
 
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_admin_callback_round_trip() {
+    let key = "0b9e4c2a-7f1d-4e8a-9c3b-5d6f7a8b9c0d".to_string();
+    let callbacks = [
+      AdminCallback::BroadcastSend(7),
+      AdminCallback::BroadcastCancel(7),
+      AdminCallback::Users {
+        filter: Filter::Expired,
+        page: 3,
+        query: "@name:with:colons".into(),
+      },
+      AdminCallback::Users { filter: Filter::All, page: 0, query: "".into() },
+      AdminCallback::UserCard(42),
+      AdminCallback::UserKick(42),
+      AdminCallback::LicenseCard(key.clone()),
+      AdminCallback::Extend(key.clone(), 30),
+      AdminCallback::ExtendCustom(key.clone()),
+      AdminCallback::Block(key.clone(), true),
+      AdminCallback::Block(key.clone(), false),
+      AdminCallback::Kick(key.clone()),
+      AdminCallback::SetTier(key.clone(), LicenseType::Pro),
+      AdminCallback::SetTier(key, LicenseType::Trial),
+      AdminCallback::RestoreConfirm(1),
+      AdminCallback::RestoreCancel(1),
+    ];
+
+    for admin in callbacks {
+      let data = Callback::from(admin.clone()).to_data();
+      assert!(data.len() <= 64, "{data} does not fit into callback data");
+      assert_eq!(Callback::from_data(&data), Some(Callback::Admin(admin)));
+    }
+  }
+
+  #[test]
+  fn test_admin_callback_rejects_malformed() {
+    assert_eq!(AdminCallback::from_data("users:x:0:"), None);
+    assert_eq!(AdminCallback::from_data("lext:many:key"), None);
+    assert_eq!(AdminCallback::from_data("ltier:q:key"), None);
+    assert_eq!(AdminCallback::from_data("unknown:1"), None);
+    assert_eq!(AdminCallback::from_data("ucard"), None);
+  }
+}
@@ -1,17 +1,32 @@
 use std::{path::Path, sync::Arc, time::Duration};
 
-use futures::future;
 use teloxide::{
   prelude::*,
-  types::InputFile,
+  types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile, ParseMode},
   utils::command::{BotCommands, ParseError},
 };
 
-use super::ReplyBot;
+use super::{
+  ReplyBot,
+  callback::{AdminCallback, Callback},
+  users::Filter,
+};
 use crate::{
-  entity::license::LicenseType,
+  entity,
+  entity::{
+    campaign::Eligibility,
+    license::LicenseType,
+    staff::{Permission, Role},
+  },
+  i18n::{self, Lang, tr},
+  plugins::{Control, PluginState},
   prelude::*,
   state::{AppState, Services},
+  sv::{
+    self,
+    audit::Actor,
+    transfer::{Format, Strategy, Table},
+  },
 };
 
 fn parse_publish(
@@ -61,10 +76,17 @@ fn parse_buy(
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase")]
 pub enum Command {
-  Start,
+  /// Main menu, the payload of `t.me/<bot>?start=ref_<id>` links is passed
+  /// as the argument
+  Start(String),
+  /// Set or clear the leaderboard display name
+  Nick(String),
+  /// Redeem a gift or coupon code
+  Redeem(String),
   // Admin commands below - users use button interface
   Help,
-  Users,
+  /// Paginated user list: `/users [filter] [id or name]`
+  Users(String),
   Gen(String),
   #[command(parse_with = parse_buy)]
   Buy {
@@ -76,6 +98,9 @@ pub enum Command {
   Info(String),
   Stats,
   Backup,
+  /// Check a backup and restore it after confirmation, given as a file in
+  /// the backup directory or as a reply to an uploaded backup
+  Restore(String),
   Builds,
   #[command(parse_with = parse_publish)]
   Publish {
@@ -92,6 +117,56 @@ pub enum Command {
   Deactivate(String),
   /// Admin stats - show global XP/drops summary
   GlobalStats,
+  /// Plugin supervisor status and stop/start/restart controls
+  Plugins(String),
+  /// Promo campaigns: list, create, pause, resume, end
+  Promo(String),
+  /// Gift codes: list batches, generate, export as CSV
+  Codes(String),
+  /// Referral report of all referrers
+  Referrals,
+  /// Announce to an audience after a preview, or list recent broadcasts
+  Broadcast(String),
+  /// Latest audit log entries, of a license key or user id if given
+  Audit(String),
+  /// Staff roles: list, grant, revoke
+  Roles(String),
+  /// Export licenses or users as JSON or CSV
+  Export(String),
+  /// Import licenses from an uploaded JSON or CSV file, a dry run unless
+  /// `apply` is given
+  Import(String),
+}
+
+/// Permission an admin command requires, `None` for user commands
+fn permission(cmd: &Command) -> Option<Permission> {
+  let permission = match cmd {
+    Command::Start(_) | Command::Nick(_) | Command::Redeem(_) => return None,
+    Command::Help => return None,
+    Command::Users(_) | Command::Info(_) => Permission::ViewUsers,
+    Command::Buy { .. } => Permission::ExtendLicense,
+    Command::Gen(_)
+    | Command::Ban(_)
+    | Command::Unban(_)
+    | Command::Import(_) => Permission::ManageLicenses,
+    Command::Builds
+    | Command::Publish { .. }
+    | Command::Yank(_)
+    | Command::Unyank(_)
+    | Command::Deactivate(_) => Permission::ManageBuilds,
+    Command::Promo(_) | Command::Codes(_) | Command::Broadcast(_) => {
+      Permission::ManageMarketing
+    }
+    Command::Stats
+    | Command::GlobalStats
+    | Command::Referrals
+    | Command::Audit(_)
+    | Command::Export(_) => Permission::ViewReports,
+    Command::Plugins(_) => Permission::ManagePlugins,
+    Command::Backup | Command::Restore(_) => Permission::Backup,
+    Command::Roles(_) => Permission::ManageRoles,
+  };
+  Some(permission)
 }
 
 const ADMIN_HELP: &str = "\
@@ -103,6 +178,18 @@ const ADMIN_HELP: &str = "\
 /ban &lt;key&gt; - Block license and drop sessions
 /unban &lt;key&gt; - Unblock license
 /info &lt;key|user_id&gt; - Show license or user details
+/import [skip|overwrite|extend] [apply] - Import licenses, reply to a JSON or CSV file (dry run without apply)
+/export &lt;licenses|users&gt; [csv|json] - Export a table
+
+<b>Promos:</b>
+/promo - List promos with claim counts
+/promo create &lt;name&gt; &lt;trial|pro&gt; &lt;duration&gt; &lt;start&gt; &lt;end&gt; [cap=N] [new] [title] - Create promo (dates: now, 2025-12-24 or 2025-12-24T18:00 UTC)
+/promo pause|resume|end &lt;name&gt; - Control a promo
+
+<b>Gift Codes:</b>
+/codes - List code batches
+/codes gen &lt;count&gt; &lt;trial|pro&gt; &lt;duration&gt; [max=N] [expires=date] - Generate codes as CSV
+/codes export [batch] - Export codes as CSV
 
 <b>Build Management:</b>
 /builds - List all builds
@@ -111,10 +198,20 @@ const ADMIN_HELP: &str = "\
 /unyank &lt;version&gt; - Reactivate yanked build
 
 <b>System:</b>
-/users - List all registered users
-/stats - Show active sessions count
+/users [online|valid|expired|blocked] [id|name] - Browse users
+/stats - Show active sessions and rejected metrics
 /globalstats - Show global XP/drops summary
+/referrals - Show referral report
+/audit [key|user_id] - Show audit log
 /backup - Manual database backup
+/restore &lt;file&gt; - Restore a backup (or reply to an uploaded one)
+/broadcast - Show recent broadcasts
+/broadcast &lt;all|active|expired|trial|pro&gt; [build=ver] &lt;text&gt; - Preview and send announcement
+/plugins - Show plugin status
+/plugins stop|start|restart &lt;name&gt; - Control a plugin
+/roles - List staff roles
+/roles grant &lt;user_id&gt; &lt;owner|admin|support|release&gt; - Grant a role
+/roles revoke &lt;user_id&gt; - Revoke a role
 /help - Show this message";
 
 pub async fn handle(
@@ -124,45 +221,186 @@ pub async fn handle(
 ) -> ResponseResult<()> {
   let sv = app.sv();
 
-  let _ = sv.user.get_or_create(bot.user_id).await;
+  let is_new = matches!(sv.user.by_id(bot.user_id).await, Ok(None));
+  let bot = match sv.user.update_profile(bot.user_id, bot.profile.clone()).await
+  {
+    Ok(user) => bot.with_stored_lang(&user),
+    Err(_) => bot,
+  };
+
+  let role = app.role_of(bot.user_id).await;
 
   match &cmd {
-    Command::Start => {
-      let text = "<b>Yet Another Counter Strike Panel!</b>\n\n\
-        Use the buttons below to navigate.\n\
-        Read docs: https://yacsp.gitbook.io/yacsp\n\
-        Contact support: @y_a_c_s_p";
+    Command::Start(payload) => {
+      // only users starting the bot for the first time can be referred
+      if is_new
+        && let Some(referrer_id) = sv::referral::parse_payload(payload)
+        && let Err(e) = sv.referral.record(referrer_id, bot.user_id).await
+      {
+        warn!("Failed to record referral of {}: {}", bot.user_id, e);
+      }
+
       bot
         .reply_with_keyboard(
-          text,
-          super::callback::main_menu(sv.license.is_promo_active()),
+          tr!(bot.lang, "menu.welcome"),
+          super::callback::main_menu(
+            bot.lang,
+            &sv.campaign.active().await.unwrap_or_default(),
+          ),
         )
         .await?;
     }
-    Command::Help if app.admins.contains(&bot.user_id) => {
+    Command::Nick(name) => {
+      handle_nick(&sv, &bot, name).await?;
+      return Ok(());
+    }
+    Command::Redeem(code) => {
+      handle_redeem(&app, &bot, code).await?;
+      return Ok(());
+    }
+    Command::Help if role.is_some() => {
       bot.reply_html(ADMIN_HELP).await?;
       return Ok(());
     }
     Command::Help => {
-      bot
-        .reply_html("Use /start to access the main menu with buttons.")
-        .await?;
+      bot.reply_html(tr!(bot.lang, "menu.help")).await?;
       return Ok(());
     }
     _ => {}
   }
 
-  if app.admins.contains(&bot.user_id) {
-    handle_admin_command(app, bot, cmd).await?;
+  if let Some(role) = role {
+    handle_admin_command(app, bot, role, cmd).await?;
   }
 
   Ok(())
 }
 
-async fn process_info_command(
+/// Maximum length of a leaderboard display name
+const MAX_NICK_LEN: usize = 32;
+
+async fn handle_nick(
   sv: &Services<'_>,
+  bot: &ReplyBot,
+  name: &str,
+) -> ResponseResult<()> {
+  let name = name.trim();
+  let lang = bot.lang;
+
+  let text = if name.is_empty() {
+    match sv.user.set_display_name(bot.user_id, None).await {
+      Ok(_) => tr!(lang, "nick.hidden"),
+      Err(e) => format!("❌ {}", i18n::error(lang, &e)),
+    }
+  } else if name.chars().count() > MAX_NICK_LEN {
+    tr!(lang, "nick.too_long", max = MAX_NICK_LEN)
+  } else {
+    match sv.user.set_display_name(bot.user_id, Some(name.into())).await {
+      Ok(_) => {
+        tr!(lang, "nick.set", name = teloxide::utils::html::escape(name))
+      }
+      Err(e) => format!("❌ {}", i18n::error(lang, &e)),
+    }
+  };
+
+  bot.reply_html(text).await?;
+  Ok(())
+}
+
+/// Reward the referrer of `referee_id` after a paid order, notifying them.
+/// Returns a note for the admin reply, empty if nothing was granted.
+async fn reward_referrer(app: &AppState, referee_id: i64) -> String {
+  let days = app.config().referral_reward_days;
+
+  match app.sv().referral.reward(referee_id, days).await {
+    Ok(Some(reward)) => {
+      // the referrer is not part of this conversation, use their chosen
+      // language or the one of their cached Telegram profile
+      let lang = match app.sv().user.by_id(reward.referrer_id).await {
+        Ok(Some(user)) => {
+          user.language.or(user.language_code).as_deref().map(Lang::from_code)
+        }
+        _ => None,
+      };
+      let text = tr!(
+        lang.unwrap_or_default(),
+        "referral.reward",
+        key = reward.license.key,
+        days = days
+      );
+      let _ = app
+        .bot
+        .send_message(ChatId(reward.referrer_id), text)
+        .parse_mode(ParseMode::Html)
+        .await;
+
+      format!(
+        "\n\n🤝 Referrer <code>{}</code> rewarded with {} days",
+        reward.referrer_id, days
+      )
+    }
+    Ok(None) => String::new(),
+    Err(e) => {
+      error!("Failed to reward referrer of {}: {}", referee_id, e);
+      String::new()
+    }
+  }
+}
+
+async fn handle_redeem(
   app: &AppState,
   bot: &ReplyBot,
+  code: &str,
+) -> ResponseResult<()> {
+  let code = code.trim();
+  if code.is_empty() {
+    bot.reply_html(tr!(bot.lang, "redeem.hint")).await?;
+    return Ok(());
+  }
+
+  let text = match app.redeem_code(bot.user_id, code).await {
+    Ok(redeemed) => {
+      let tier = format!("{:?}", redeemed.license.license_type);
+      let key = &redeemed.license.key;
+      let expires = utils::format_date(redeemed.license.expires_at);
+      if redeemed.extended {
+        tr!(
+          bot.lang,
+          "redeem.extended",
+          tier = tier,
+          key = key,
+          expires = expires
+        )
+      } else {
+        tr!(
+          bot.lang,
+          "redeem.activated",
+          tier = tier,
+          key = key,
+          expires = expires
+        )
+      }
+    }
+    Err(e) => format!("❌ {}", i18n::error(bot.lang, &e)),
+  };
+
+  bot.reply_html(text).await?;
+  Ok(())
+}
+
+/// Optional leading filter, the rest is a search query
+fn parse_users_args(args: &str) -> (Filter, String) {
+  let args = args.trim();
+  let (first, rest) = args.split_once(' ').unwrap_or((args, ""));
+  match Filter::parse(first) {
+    Some(filter) => (filter, super::users::clamp_query(rest)),
+    None => (Filter::All, super::users::clamp_query(args)),
+  }
+}
+
+pub(super) async fn process_info_command(
+  sv: &Services<'_>,
+  app: &AppState,
   input: String,
 ) -> Result<String> {
   let input = input.trim();
@@ -174,7 +412,7 @@ async fn process_info_command(
 
   if let Ok(user_id) = input.parse::<i64>() {
     let user = sv.user.by_id(user_id).await?.ok_or(Error::UserNotFound)?;
-    let username = bot.infer_username(ChatId(user_id)).await;
+    let username = utils::format_user(&user);
     let stats = sv.stats.display_stats(user_id).await?;
     let licenses = sv.license.by_user(user_id, true).await?;
 
@@ -182,7 +420,7 @@ async fn process_info_command(
     let mut lic_text = String::new();
 
     for lic in &licenses {
-      let active = app.sessions.get(&lic.key).map(|s| s.len()).unwrap_or(0);
+      let active = app.live_sessions(&lic.key).await?.len();
       total_active_sessions += active;
 
       let status_icon = if lic.is_blocked {
@@ -226,10 +464,13 @@ async fn process_info_command(
 
   let key = input;
   let license = sv.license.by_key(key).await?.ok_or(Error::LicenseNotFound)?;
-  let username = bot.infer_username(ChatId(license.tg_user_id)).await;
+  let username = match sv.user.by_id(license.tg_user_id).await? {
+    Some(user) => utils::format_user(&user),
+    None => format!("<code>{}</code>", license.tg_user_id),
+  };
 
-  let sessions = app.sessions.get(key);
-  let active_count = sessions.as_ref().map(|s| s.len()).unwrap_or(0);
+  let sessions = app.live_sessions(key).await?;
+  let active_count = sessions.len();
   let now = Utc::now().naive_utc();
 
   let status = if license.is_blocked {
@@ -270,8 +511,8 @@ async fn process_info_command(
     license.max_sessions
   );
 
-  if let Some(sess_list) = sessions {
-    for (i, s) in sess_list.iter().enumerate() {
+  if active_count > 0 {
+    for (i, s) in sessions.iter().enumerate() {
       text.push_str(&format!(
         " {}. ID: <code>{}...</code>\n    HWID: <code>{}</code>\n",
         i + 1,
@@ -279,99 +520,754 @@ async fn process_info_command(
         s.hwid_hash.as_deref().unwrap_or("Unknown")
       ));
     }
-  } else if active_count == 0 {
+  } else {
     text.push_str(" <i>No active sessions</i>");
   }
 
   Ok(text)
 }
 
-async fn handle_admin_command(
-  app: Arc<AppState>,
-  bot: ReplyBot,
-  cmd: Command,
-) -> ResponseResult<()> {
-  let sv = app.sv();
+const PROMO_USAGE: &str = "Usage: /promo [create &lt;name&gt; &lt;trial|pro&gt; \
+  &lt;duration&gt; &lt;start&gt; &lt;end&gt; [cap=N] [new] [title] | \
+  pause|resume|end &lt;name&gt;]";
 
-  if let Command::Users = cmd {
-    let users_data = match sv.user.all_with_licenses().await {
-      Ok(u) => u,
-      Err(e) => {
-        bot.reply_html(format!("❌ DB Error: {}", e)).await?;
-        return Ok(());
+/// Parse `now`, `2025-12-24` or `2025-12-24T18:00` as UTC.
+/// Date-only values mean the start of the day, or its end if `end_of_day`.
+fn parse_promo_date(input: &str, end_of_day: bool) -> Result<DateTime> {
+  if input.eq_ignore_ascii_case("now") {
+    return Ok(Utc::now().naive_utc());
+  }
+  if let Ok(date) = DateTime::parse_from_str(input, "%Y-%m-%dT%H:%M") {
+    return Ok(date);
+  }
+  chrono::NaiveDate::parse_from_str(input, "%Y-%m-%d")
+    .ok()
+    .and_then(|date| {
+      if end_of_day {
+        date.and_hms_opt(23, 59, 59)
+      } else {
+        date.and_hms_opt(0, 0, 0)
       }
+    })
+    .ok_or_else(|| Error::InvalidArgs(format!("Invalid date '{}'", input)))
+}
+
+fn parse_license_type(input: &str) -> Result<LicenseType> {
+  match input.to_ascii_lowercase().as_str() {
+    "trial" => Ok(LicenseType::Trial),
+    "pro" => Ok(LicenseType::Pro),
+    _ => Err(Error::InvalidArgs(format!("Unknown license type '{}'", input))),
+  }
+}
+
+/// Whole days of a humantime duration like `30d` or `2w`
+fn parse_days(input: &str) -> Result<u64> {
+  humantime::parse_duration(input)
+    .map(|duration| duration.as_secs() / (24 * 60 * 60))
+    .map_err(|e| {
+      Error::InvalidArgs(format!("Invalid duration '{}': {}", input, e))
+    })
+}
+
+fn parse_new_campaign(args: &[&str]) -> Result<sv::campaign::NewCampaign> {
+  let [name, ty, duration, starts, ends, rest @ ..] = args else {
+    return Err(Error::InvalidArgs(PROMO_USAGE.into()));
+  };
+
+  let license_type = parse_license_type(ty)?;
+  let days = parse_days(duration)?;
+
+  let mut max_claims = None;
+  let mut eligibility = Eligibility::All;
+  let mut title = Vec::new();
+  for arg in rest {
+    if let Some(cap) = arg.strip_prefix("cap=") {
+      max_claims = Some(cap.parse::<u64>().map_err(|_| {
+        Error::InvalidArgs(format!("Invalid claim cap '{}'", cap))
+      })?);
+    } else if *arg == "new" && title.is_empty() {
+      eligibility = Eligibility::NewUsers;
+    } else {
+      title.push(*arg);
+    }
+  }
+
+  Ok(sv::campaign::NewCampaign {
+    name: name.to_ascii_lowercase(),
+    title: if title.is_empty() {
+      "Get Free Trial".into()
+    } else {
+      title.join(" ")
+    },
+    starts_at: parse_promo_date(starts, false)?,
+    ends_at: parse_promo_date(ends, true)?,
+    license_type,
+    days,
+    max_claims,
+    eligibility,
+  })
+}
+
+async fn process_promo_command(
+  sv: &Services<'_>,
+  input: &str,
+) -> Result<String> {
+  let parts: Vec<&str> = input.split_whitespace().collect();
+
+  match parts.as_slice() {
+    [] => {
+      let campaigns = sv.campaign.all().await?;
+      if campaigns.is_empty() {
+        return Ok("📭 No promos yet.".into());
+      }
+
+      let mut text = String::from("🎁 <b>Promos</b>\n");
+      for (campaign, status, claims) in campaigns {
+        let cap =
+          campaign.max_claims.map(|max| max.to_string()).unwrap_or("∞".into());
+        let new_only = if campaign.eligibility == Eligibility::NewUsers {
+          " | new users"
+        } else {
+          ""
+        };
+        text.push_str(&format!(
+          "\n<b>{}</b> ({:?})\n\
+          {} | {}d {:?}{}\n\
+          {} — {}\n\
+          Claims: {}/{}\n",
+          campaign.name,
+          status,
+          teloxide::utils::html::escape(&campaign.title),
+          campaign.days,
+          campaign.license_type,
+          new_only,
+          utils::format_date(campaign.starts_at),
+          utils::format_date(campaign.ends_at),
+          claims,
+          cap
+        ));
+      }
+      Ok(text)
+    }
+    ["create", args @ ..] => {
+      let campaign = sv.campaign.create(parse_new_campaign(args)?).await?;
+      Ok(format!(
+        "✅ Promo <b>{}</b> created.\n\
+        Runs {} — {}",
+        campaign.name,
+        utils::format_date(campaign.starts_at),
+        utils::format_date(campaign.ends_at)
+      ))
+    }
+    ["pause", name] => {
+      sv.campaign.set_paused(name, true).await?;
+      Ok(format!("⏸ Promo <b>{}</b> paused", name))
+    }
+    ["resume", name] => {
+      sv.campaign.set_paused(name, false).await?;
+      Ok(format!("▶️ Promo <b>{}</b> resumed", name))
+    }
+    ["end", name] => {
+      sv.campaign.end(name).await?;
+      Ok(format!("🏁 Promo <b>{}</b> ended", name))
+    }
+    _ => Err(Error::InvalidArgs(PROMO_USAGE.into())),
+  }
+}
+
+const BROADCAST_USAGE: &str = "Usage: /broadcast \
+  &lt;all|active|expired|trial|pro&gt; [build=version] &lt;text&gt;";
+
+fn render_broadcasts(broadcasts: &[entity::broadcast::Model]) -> String {
+  if broadcasts.is_empty() {
+    return "📭 No broadcasts yet.".into();
+  }
+
+  let mut text = String::from("📣 <b>Recent Broadcasts</b>\n");
+  for b in broadcasts {
+    text.push_str(&format!(
+      "\n<b>#{}</b> {:?} → {:?} ({})\n\
+      Sent: {}/{} | Blocked: {} | Failed: {}\n",
+      b.id,
+      b.status,
+      b.audience,
+      utils::format_date(b.created_at),
+      b.sent,
+      b.total,
+      b.blocked,
+      b.failed
+    ));
+  }
+  text
+}
+
+/// Parse `/broadcast` arguments into a pending broadcast
+async fn create_broadcast(
+  sv: &Services<'_>,
+  admin_id: i64,
+  input: &str,
+) -> Result<entity::broadcast::Model> {
+  let usage = || Error::InvalidArgs(BROADCAST_USAGE.into());
+
+  let (audience, rest) =
+    input.split_once(char::is_whitespace).ok_or_else(usage)?;
+  let audience =
+    super::broadcast::parse_audience(audience).ok_or_else(usage)?;
+
+  let rest = rest.trim_start();
+  let (build, text) = match rest.strip_prefix("build=") {
+    Some(rest) => {
+      let (version, text) =
+        rest.split_once(char::is_whitespace).ok_or_else(usage)?;
+      (Some(version.to_string()), text.trim())
+    }
+    None => (None, rest.trim()),
+  };
+  if text.is_empty() {
+    return Err(usage());
+  }
+
+  if let Some(version) = &build {
+    match sv.build.by_version(version).await? {
+      Some(build) if build.is_active => {}
+      Some(_) => return Err(Error::BuildInactive),
+      None => return Err(Error::BuildNotFound),
+    }
+  }
+
+  sv.broadcast.create(text.to_string(), audience, build, admin_id).await
+}
+
+async fn handle_broadcast(
+  sv: &Services<'_>,
+  bot: &ReplyBot,
+  args: &str,
+) -> ResponseResult<()> {
+  if args.trim().is_empty() {
+    let text = match sv.broadcast.recent(10).await {
+      Ok(broadcasts) => render_broadcasts(&broadcasts),
+      Err(e) => format!("❌ {}", e.user_message()),
     };
+    bot.reply_html(text).await?;
+    return Ok(());
+  }
 
-    if users_data.is_empty() {
-      bot.reply_html("📭 Database is empty.").await?;
+  let broadcast = match create_broadcast(sv, bot.user_id, args).await {
+    Ok(broadcast) => broadcast,
+    Err(e) => {
+      bot.reply_html(format!("❌ {}", e.user_message())).await?;
       return Ok(());
     }
+  };
 
+  // preview exactly what users will get, invalid HTML fails right here
+  if let Err(e) = bot
+    .reply_with_keyboard(
+      &broadcast.text,
+      super::broadcast::keyboard(&broadcast),
+    )
+    .await
+  {
+    let _ = sv.broadcast.cancel(broadcast.id).await;
     bot
-      .reply_html(format!("⏳ Loading data for {} users...", users_data.len()))
+      .reply_html(format!(
+        "❌ Preview failed, broadcast cancelled:\n<code>{}</code>",
+        teloxide::utils::html::escape(&e.to_string())
+      ))
       .await?;
+    return Ok(());
+  }
+
+  bot
+    .reply_with_keyboard(
+      format!(
+        "📣 <b>Broadcast #{}</b> preview above.\n\
+        Audience: {:?} ({} users)",
+        broadcast.id, broadcast.audience, broadcast.total
+      ),
+      super::broadcast::confirm_keyboard(broadcast.id),
+    )
+    .await?;
+
+  Ok(())
+}
+
+const CODES_USAGE: &str = "Usage: /codes [gen &lt;count&gt; &lt;trial|pro&gt; \
+  &lt;duration&gt; [max=N] [expires=date] | export [batch]]";
+
+fn parse_new_batch(args: &[&str]) -> Result<sv::code::NewBatch> {
+  let [count, ty, duration, rest @ ..] = args else {
+    return Err(Error::InvalidArgs(CODES_USAGE.into()));
+  };
+
+  let count = count
+    .parse::<u64>()
+    .map_err(|_| Error::InvalidArgs(format!("Invalid count '{}'", count)))?;
+
+  let mut max_redemptions = 1;
+  let mut expires_at = None;
+  for arg in rest {
+    if let Some(max) = arg.strip_prefix("max=") {
+      max_redemptions = max.parse::<u64>().map_err(|_| {
+        Error::InvalidArgs(format!("Invalid max redemptions '{}'", max))
+      })?;
+    } else if let Some(date) = arg.strip_prefix("expires=") {
+      expires_at = Some(parse_promo_date(date, true)?);
+    } else {
+      return Err(Error::InvalidArgs(CODES_USAGE.into()));
+    }
+  }
+
+  Ok(sv::code::NewBatch {
+    count,
+    license_type: parse_license_type(ty)?,
+    days: parse_days(duration)?,
+    max_redemptions,
+    expires_at,
+  })
+}
+
+fn codes_csv(name: String, codes: &[entity::code::Model]) -> InputFile {
+  InputFile::memory(sv::code::to_csv(codes).into_bytes()).file_name(name)
+}
+
+/// Reply text and an optional CSV document for `/codes`
+async fn process_codes_command(
+  sv: &Services<'_>,
+  input: &str,
+) -> Result<(String, Option<InputFile>)> {
+  let parts: Vec<&str> = input.split_whitespace().collect();
+
+  match parts.as_slice() {
+    [] => {
+      let batches = sv.code.batches().await?;
+      if batches.is_empty() {
+        return Ok(("📭 No codes yet.".into(), None));
+      }
 
-    let user_futures = users_data.into_iter().map(|(u, licenses)| {
-      let bot = bot.clone();
-      async move {
-        let username = bot.infer_username(ChatId(u.tg_user_id)).await;
-        (u, username, licenses)
+      let mut text = String::from("🎟 <b>Code Batches</b>\n");
+      for batch in batches {
+        text.push_str(&format!(
+          "\n<code>{}</code> | {}d {:?}\n\
+          Redeemed: {} | Codes: {} | {}\n",
+          batch.batch,
+          batch.days,
+          batch.license_type,
+          batch.redemptions,
+          batch.codes,
+          utils::format_date(batch.created_at)
+        ));
+      }
+      Ok((text, None))
+    }
+    ["gen", args @ ..] => {
+      let codes = sv.code.generate(parse_new_batch(args)?).await?;
+      let batch = &codes[0].batch;
+      let text = format!(
+        "✅ Generated {} code(s), batch <code>{}</code>",
+        codes.len(),
+        batch
+      );
+      Ok((text, Some(codes_csv(format!("codes-{batch}.csv"), &codes))))
+    }
+    ["export", rest @ ..] if rest.len() <= 1 => {
+      let batch = rest.first().copied();
+      let codes = sv.code.export(batch).await?;
+      if codes.is_empty() {
+        return Ok(("📭 No codes to export.".into(), None));
       }
-    });
+      let name = format!("codes-{}.csv", batch.unwrap_or("all"));
+      let text = format!("📤 Exported {} code(s)", codes.len());
+      Ok((text, Some(codes_csv(name, &codes))))
+    }
+    _ => Err(Error::InvalidArgs(CODES_USAGE.into())),
+  }
+}
+
+fn render_plugins(app: &AppState) -> String {
+  let mut text = String::from("🧩 <b>Plugins</b>\n");
 
-    let resolved_users = future::join_all(user_futures).await;
+  for status in app.plugins.snapshot() {
+    let icon = match status.state {
+      PluginState::Running => "🟢",
+      PluginState::Crashed => "🔴",
+      PluginState::Restarting => "🟡",
+      PluginState::Stopped => "⚪",
+    };
+    let last_success =
+      status.last_success.map(utils::format_date).unwrap_or("never".into());
+
+    text.push_str(&format!(
+      "\n{} <b>{}</b> ({:?})\n\
+      Restarts: {} | Last success: {}\n",
+      icon,
+      status.short_name(),
+      status.state,
+      status.restart_count,
+      last_success
+    ));
+    if let Some(err) = &status.last_error {
+      text.push_str(&format!(
+        "Last error: <code>{}</code>\n",
+        teloxide::utils::html::escape(err)
+      ));
+    }
+  }
 
-    let mut text =
-      format!("👥 <b>Users List (Total: {})</b>\n\n", resolved_users.len());
-    let now = Utc::now().naive_utc();
+  text
+}
 
-    for (i, (user, username, licenses)) in resolved_users.iter().enumerate() {
-      let status_icon = if licenses.is_empty() {
-        "📂"
+async fn handle_plugins(
+  app: &AppState,
+  bot: &ReplyBot,
+  args: &str,
+) -> ResponseResult<()> {
+  let parts: Vec<&str> = args.split_whitespace().collect();
+  let (control, target) = match parts.as_slice() {
+    [] => {
+      bot.reply_html(render_plugins(app)).await?;
+      return Ok(());
+    }
+    ["stop", name] => (Control::Stop, *name),
+    ["start", name] => (Control::Start, *name),
+    ["restart", name] => (Control::Restart, *name),
+    _ => {
+      bot
+        .reply_html("Usage: /plugins [stop|start|restart &lt;name&gt;]")
+        .await?;
+      return Ok(());
+    }
+  };
+
+  let name = match app.plugins.resolve(target) {
+    Ok(name) => name,
+    Err(e) => {
+      bot.reply_html(format!("❌ {}", e.user_message())).await?;
+      return Ok(());
+    }
+  };
+
+  // stopping the bot from the bot would leave no way to start it again
+  if control == Control::Stop && name == std::any::type_name::<super::Plugin>()
+  {
+    bot.reply_html("❌ Telegram plugin can only be restarted").await?;
+    return Ok(());
+  }
+
+  // reply first: restarting the telegram plugin aborts this very handler
+  bot
+    .reply_html(format!("✅ {:?} requested for <code>{}</code>", control, name))
+    .await?;
+
+  if let Err(e) = app.plugins.send(name, control) {
+    bot.reply_html(format!("❌ {}", e.user_message())).await?;
+  }
+
+  Ok(())
+}
+
+async fn handle_restore(
+  app: &AppState,
+  bot: &ReplyBot,
+  file: &str,
+) -> ResponseResult<()> {
+  let file = file.trim();
+  let data = match &bot.document {
+    _ if !file.is_empty() => {
+      let path = Path::new(file);
+      let path = if path.exists() {
+        path.to_path_buf()
       } else {
-        let mut has_online = false;
-        let mut has_valid = false;
-        let mut has_blocked = false;
-
-        for lic in licenses {
-          if lic.is_blocked {
-            has_blocked = true;
-            continue;
-          }
+        Path::new(&app.config().backup_directory).join(file)
+      };
+      tokio::fs::read(&path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))
+    }
+    Some(document) => bot.download(document.file.id.clone()).await,
+    None => {
+      bot
+        .reply_html(
+          "Usage: /restore &lt;file&gt;, or reply /restore to an uploaded \
+          backup",
+        )
+        .await?;
+      return Ok(());
+    }
+  };
 
-          if lic.expires_at > now {
-            has_valid = true;
-            if let Some(sessions) = app.sessions.get(&lic.key)
-              && !sessions.is_empty()
-            {
-              has_online = true;
-              break;
-            }
-          }
-        }
+  bot.reply_html("⏳ Checking backup...").await?;
+  let prepared = match data {
+    Ok(data) => app.prepare_restore(data).await,
+    Err(e) => Err(e),
+  };
+  let (id, summary) = match prepared {
+    Ok(prepared) => prepared,
+    Err(e) => {
+      let text =
+        format!("❌ {}", teloxide::utils::html::escape(&format!("{:#}", e)));
+      bot.reply_html(text).await?;
+      return Ok(());
+    }
+  };
 
-        if has_online {
-          "🟢"
-        } else if has_valid {
-          "⚪"
-        } else if has_blocked {
-          "⛔"
-        } else {
-          "❌"
-        }
+  let keyboard = InlineKeyboardMarkup::new([[
+    InlineKeyboardButton::callback(
+      "✅ Restore",
+      Callback::from(AdminCallback::RestoreConfirm(id)).to_data(),
+    ),
+    InlineKeyboardButton::callback(
+      "❌ Cancel",
+      Callback::from(AdminCallback::RestoreCancel(id)).to_data(),
+    ),
+  ]]);
+  let text = format!(
+    "♻️ <b>Restore #{}</b>\nIntegrity check passed.\n\n{}\n\n\
+    The current database is snapshotted before it is replaced.",
+    id, summary
+  );
+  bot.reply_with_keyboard(text, keyboard).await?;
+  Ok(())
+}
+
+async fn handle_export(
+  sv: &Services<'_>,
+  bot: &ReplyBot,
+  args: &str,
+) -> ResponseResult<()> {
+  let result = async {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let (table, format) = match parts.as_slice() {
+      [table] => (table.parse::<Table>()?, Format::Csv),
+      [table, format] => (table.parse::<Table>()?, format.parse()?),
+      _ => {
+        return Err(Error::InvalidArgs(
+          "Usage: /export <licenses|users> [csv|json]".into(),
+        ));
+      }
+    };
+    let data = sv.transfer.export(table, format).await?;
+    let name = format!(
+      "{}_{}.{}",
+      table.name(),
+      Utc::now().format("%Y-%m-%d"),
+      format.extension()
+    );
+    Ok(InputFile::memory(data).file_name(name))
+  }
+  .await;
+
+  match result {
+    Ok(file) => {
+      bot.send_document(file).await?;
+    }
+    Err(e) => {
+      bot.reply_html(format!("❌ {}", e.user_message())).await?;
+    }
+  }
+  Ok(())
+}
+
+async fn handle_import(
+  sv: &Services<'_>,
+  bot: &ReplyBot,
+  args: &str,
+) -> ResponseResult<()> {
+  const USAGE: &str = "Usage: reply /import [skip|overwrite|extend] [apply] \
+    to a JSON or CSV file";
+
+  let result =
+    async {
+      let Some(document) = &bot.document else {
+        return Err(Error::InvalidArgs(USAGE.into()));
       };
+      let format =
+        document.file_name.as_deref().and_then(Format::of_file).ok_or_else(
+          || Error::InvalidArgs("Upload a .json or .csv file".into()),
+        )?;
 
-      text.push_str(&format!(
-        "<b>{}.</b> {} {} <code>{}</code>\n",
-        i + 1,
-        status_icon,
-        username,
-        user.tg_user_id
-      ));
+      let (strategy, apply) =
+        match args.split_whitespace().collect::<Vec<_>>()[..] {
+          [] => (Strategy::Skip, false),
+          ["apply"] => (Strategy::Skip, true),
+          [strategy] => (strategy.parse()?, false),
+          [strategy, "apply"] => (strategy.parse()?, true),
+          _ => return Err(Error::InvalidArgs(USAGE.into())),
+        };
+
+      let data = bot
+        .download(document.file.id.clone())
+        .await
+        .map_err(|e| Error::Internal(e.to_string()))?;
+      sv.transfer.import(format, &data, strategy, !apply).await
+    }
+    .await;
+
+  let text = match result {
+    Ok(report) if report.dry_run && report.errors.is_empty() => format!(
+      "{}\n\nReply <code>/import {} apply</code> to the file to import it",
+      report.summary(),
+      args.split_whitespace().next().unwrap_or("skip")
+    ),
+    Ok(report) => report.summary(),
+    Err(e) => format!("❌ {}", e.user_message()),
+  };
+  bot.reply_html(text).await?;
+  Ok(())
+}
+
+async fn process_roles_command(
+  app: &AppState,
+  bot: &ReplyBot,
+  args: &str,
+) -> Result<String> {
+  let sv = app.sv();
+  let parts: Vec<&str> = args.split_whitespace().collect();
+
+  match parts.as_slice() {
+    [] => {
+      let mut text = String::from("👮 <b>Staff</b>\n");
+      for admin in &app.admins {
+        text.push_str(&format!("\n<code>{}</code>: owner (ADMIN_IDS)", admin));
+      }
+      for member in sv.staff.all().await? {
+        let granted_by = member
+          .granted_by
+          .map(|id| format!(", by {}", id))
+          .unwrap_or_default();
+        text.push_str(&format!(
+          "\n<code>{}</code>: {:?} since {}{}",
+          member.tg_user_id,
+          member.role,
+          utils::format_date(member.granted_at),
+          granted_by
+        ));
+      }
+      Ok(text)
+    }
+    ["grant", user_id, role] => {
+      let user_id = user_id.parse::<i64>().map_err(|_| {
+        Error::InvalidArgs(format!("Invalid user id: {}", user_id))
+      })?;
+      let role = Role::parse(role).ok_or_else(|| {
+        Error::InvalidArgs(format!(
+          "Unknown role '{}', use owner, admin, support or release",
+          role
+        ))
+      })?;
+      if app.admins.contains(&user_id) {
+        return Err(Error::InvalidArgs(format!(
+          "{} is an owner through ADMIN_IDS",
+          user_id
+        )));
+      }
+
+      sv.staff.grant(user_id, role, bot.user_id).await?;
+      Ok(format!("✅ <code>{}</code> is now {:?}", user_id, role))
+    }
+    ["revoke", user_id] => {
+      let user_id = user_id.parse::<i64>().map_err(|_| {
+        Error::InvalidArgs(format!("Invalid user id: {}", user_id))
+      })?;
+      if app.admins.contains(&user_id) {
+        return Err(Error::InvalidArgs(format!(
+          "{} is an owner through ADMIN_IDS",
+          user_id
+        )));
+      }
+
+      if sv.staff.revoke(user_id, bot.user_id).await? {
+        Ok(format!("✅ Role of <code>{}</code> revoked", user_id))
+      } else {
+        Ok(format!("ℹ️ <code>{}</code> has no role", user_id))
+      }
     }
+    _ => Err(Error::InvalidArgs(
+      "Usage: /roles [grant <user_id> <role> | revoke <user_id>]".into(),
+    )),
+  }
+}
+
+async fn handle_admin_command(
+  app: Arc<AppState>,
+  bot: ReplyBot,
+  role: Role,
+  cmd: Command,
+) -> ResponseResult<()> {
+  let sv = app.sv_as(Actor::Admin(bot.user_id));
 
-    // Use chunked reply to handle long user lists
-    bot.reply_html_chunked(text).await?;
+  if let Some(permission) = permission(&cmd)
+    && !role.can(permission)
+  {
+    bot.reply_html("⛔ Not allowed for your role").await?;
+    return Ok(());
+  }
+
+  if let Command::Plugins(args) = &cmd {
+    return handle_plugins(&app, &bot, args).await;
+  }
+
+  if let Command::Broadcast(args) = &cmd {
+    return handle_broadcast(&sv, &bot, args).await;
+  }
+
+  if let Command::Codes(args) = &cmd {
+    match process_codes_command(&sv, args).await {
+      Ok((text, csv)) => {
+        bot.reply_html_chunked(text).await?;
+        if let Some(csv) = csv {
+          bot.send_document(csv).await?;
+        }
+      }
+      Err(e) => {
+        bot.reply_html(format!("❌ {}", e.user_message())).await?;
+      }
+    }
+    return Ok(());
+  }
+
+  if let Command::Restore(file) = &cmd {
+    return handle_restore(&app, &bot, file).await;
+  }
+
+  if let Command::Export(args) = &cmd {
+    return handle_export(&sv, &bot, args).await;
+  }
+
+  if let Command::Import(args) = &cmd {
+    return handle_import(&sv, &bot, args).await;
+  }
+
+  if let Command::Users(args) = &cmd {
+    let (filter, query) = parse_users_args(args);
+    match super::users::page(&app, filter, &query, 0).await {
+      Ok((text, keyboard)) => {
+        bot.reply_with_keyboard(text, keyboard).await?;
+      }
+      Err(e) => {
+        bot.reply_html(format!("❌ {}", e.user_message())).await?;
+      }
+    }
+    return Ok(());
+  }
+
+  if let Command::Info(input) = &cmd {
+    let input = input.trim();
+    let view = if input.is_empty() {
+      Err(Error::InvalidArgs("Usage: /info <license_key | user_id>".into()))
+    } else if let Ok(user_id) = input.parse::<i64>() {
+      super::users::card(&app, role, user_id).await
+    } else {
+      super::users::license_card(&app, role, input).await
+    };
+    match view {
+      Ok((text, keyboard)) => {
+        bot.reply_with_keyboard(text, keyboard).await?;
+      }
+      Err(e) => {
+        bot.reply_html(format!("❌ {}", e.user_message())).await?;
+      }
+    }
     return Ok(());
   }
 
@@ -387,31 +1283,41 @@ async fn handle_admin_command(
       };
 
       match target_user {
-        Some(target_user) => sv
-          .license
-          .create(target_user, LicenseType::Pro, days)
-          .await
-          .map(|l| format!("✅ Key created:\n<code>{}</code>", l.key)),
+        Some(target_user) => {
+          match sv.license.create(target_user, LicenseType::Pro, days).await {
+            Ok(l) => Ok(format!("✅ Key created:\n<code>{}</code>", l.key)),
+            Err(e) => Err(e),
+          }
+        }
         None => Err(Error::InvalidArgs("Usage: /gen <user_id> [days]".into())),
       }
     }
 
     Command::Buy { key, duration } => {
-      sv.license.expires(&key, duration).await.map(|new_exp| {
+      async {
+        let new_exp = sv.license.expires(&key, duration).await?;
+        let owner =
+          sv.license.by_key(&key).await?.ok_or(Error::LicenseNotFound)?;
+        // only `/buy` records a paid order, `/gen` and card extensions do not
+        let note = reward_referrer(&app, owner.tg_user_id).await;
+
         let duration_str = humantime::format_duration(duration);
-        format!(
-          "✅ Key expires after {duration_str}.\nNew expiry: <code>{}</code>",
-          utils::format_date(new_exp)
-        )
-      })
+        Ok(format!(
+          "✅ Key expires after {duration_str}.\nNew expiry: <code>{}</code>{}",
+          utils::format_date(new_exp),
+          note
+        ))
+      }
+      .await
     }
 
     Command::Ban(key) => {
-      let result = sv.license.set_blocked(&key, true).await;
-      if result.is_ok() {
-        app.drop_sessions(&key);
+      async {
+        sv.license.set_blocked(&key, true).await?;
+        app.drop_sessions(&key).await?;
+        Ok("🚫 Key blocked, sessions dropped".into())
       }
-      result.map(|_| "🚫 Key blocked, sessions dropped".into())
+      .await
     }
 
     Command::Unban(key) => sv
@@ -420,13 +1326,11 @@ async fn handle_admin_command(
       .await
       .map(|_| "✅ Key unblocked".into()),
 
-    Command::Info(input) => process_info_command(&sv, &app, &bot, input).await,
-    Command::Backup => {
-      if app.perform_backup(bot.chat_id).await.is_err() {
-        bot.send_document(InputFile::file("licenses.db")).await?;
-      }
-      return Ok(());
-    }
+    Command::Promo(args) => process_promo_command(&sv, &args).await,
+    Command::Backup => match app.perform_backup(bot.chat_id).await {
+      Ok(()) => return Ok(()),
+      Err(e) => Err(Error::Internal(e.to_string())),
+    },
     Command::Builds => match sv.build.all().await {
       Ok(builds) if !builds.is_empty() => {
         let mut text = String::from("<b>All Builds:</b>\n");
@@ -451,13 +1355,14 @@ async fn handle_admin_command(
     },
 
     Command::Publish { filename, version, changelog } => {
-      let file_path = format!("{}/{}", app.config.builds_directory, filename);
+      let file_path = format!("{}/{}", app.config().builds_directory, filename);
       let path = Path::new(&file_path);
 
       if !path.exists() {
         Err(Error::InvalidArgs(format!(
           "File not found: {}\n\nUpload the file to the builds folder using scp:\nscp file.exe server:{}/",
-          file_path, app.config.builds_directory
+          file_path,
+          app.config().builds_directory
         )))
       } else {
         let changelog_opt =
@@ -515,6 +1420,59 @@ async fn handle_admin_command(
       .await
     }
 
+    Command::Audit(input) => {
+      async {
+        let input = input.trim();
+        let entries = match input.parse::<i64>() {
+          _ if input.is_empty() => sv.audit.recent(None, None, 20).await?,
+          Ok(user_id) => sv.audit.recent(None, Some(user_id), 20).await?,
+          Err(_) => sv.audit.recent(Some(input), None, 20).await?,
+        };
+        if entries.is_empty() {
+          return Ok("📭 No audit entries.".into());
+        }
+
+        let mut text = String::from("📜 <b>Audit Log</b>\n");
+        for entry in &entries {
+          text.push('\n');
+          text.push_str(&utils::format_audit(entry));
+        }
+        Ok(text)
+      }
+      .await
+    }
+
+    Command::Roles(args) => process_roles_command(&app, &bot, &args).await,
+
+    Command::Referrals => {
+      async {
+        let report = sv.referral.report().await?;
+        if report.is_empty() {
+          return Ok("📭 No referrals yet.".into());
+        }
+
+        let mut text = format!(
+          "🤝 <b>Referrals</b>\n\
+          Total: {} | Rewarded: {} | Days granted: {}\n",
+          report.iter().map(|s| s.referrals).sum::<u64>(),
+          report.iter().map(|s| s.rewarded).sum::<u64>(),
+          report.iter().map(|s| s.earned_days).sum::<u64>()
+        );
+        for (i, summary) in report.iter().take(20).enumerate() {
+          text.push_str(&format!(
+            "\n<b>{}.</b> <code>{}</code>: {} invited, {} paid, +{}d",
+            i + 1,
+            summary.referrer_id,
+            summary.referrals,
+            summary.rewarded,
+            summary.earned_days
+          ));
+        }
+        Ok(text)
+      }
+      .await
+    }
+
     Command::GlobalStats => {
       async {
         let stats = sv.stats.aggregate().await?;
@@ -536,12 +1494,41 @@ async fn handle_admin_command(
       .await
     }
 
-    Command::Stats => Ok(format!(
-      "Active Keys: {}\n\
-       Active Sessions: {}",
-      app.sessions.iter().map(|kv| kv.value().len()).sum::<usize>(),
-      app.sessions.len()
-    )),
+    Command::Stats => {
+      let sessions = match app.all_sessions().await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+          bot.reply_html(format!("❌ {}", e.user_message())).await?;
+          return Ok(());
+        }
+      };
+      let mut text = format!(
+        "Active Keys: {}\n\
+         Active Sessions: {}",
+        sessions.len(),
+        sessions.iter().map(|(_, s)| s.len()).sum::<usize>()
+      );
+
+      let mut rejected: Vec<_> = app
+        .metrics_rejections
+        .iter()
+        .map(|kv| (kv.key().clone(), kv.value().0))
+        .collect();
+
+      if !rejected.is_empty() {
+        rejected.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+        text.push_str(&format!(
+          "\n\n⚠️ <b>Rejected Metrics:</b> {}",
+          rejected.iter().map(|(_, count)| count).sum::<u64>()
+        ));
+        for (key, count) in rejected.iter().take(5) {
+          text.push_str(&format!("\n<code>{}</code>: {}", key, count));
+        }
+      }
+
+      Ok(text)
+    }
 
     _ => return Ok(()),
   };
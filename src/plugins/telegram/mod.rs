@@ -1,32 +1,49 @@
+mod broadcast;
 mod callback;
 mod command;
+mod users;
+mod webhook;
 
 use std::sync::Arc;
 
+pub use broadcast::Broadcasts;
 use command::Command;
+use reqwest::Url;
 use teloxide::{
   Bot, RequestError,
   dispatching::{Dispatcher, HandlerExt, UpdateFilterExt},
+  net::Download,
   prelude::*,
   types::{
-    CallbackQuery, ChatId, InlineKeyboardMarkup, InputFile, Message, MessageId,
-    ParseMode, Update,
+    CallbackQuery, ChatId, Document, FileId, InlineKeyboardMarkup, InputFile,
+    Message, MessageId, ParseMode, Update,
   },
 };
+use tokio_util::sync::CancellationToken;
+pub use webhook::{PATH as WEBHOOK_PATH, Webhook};
 
-use crate::{prelude::*, state::AppState};
+use crate::{
+  entity::user, i18n::Lang, prelude::*, state::AppState, sv::user::Profile,
+};
 
 pub struct Plugin;
 
 #[async_trait::async_trait]
 impl super::Plugin for Plugin {
-  async fn start(&self, app: Arc<AppState>) -> anyhow::Result<()> {
-    run_bot(app).await;
-    Ok(())
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    app.plugins.report_success(super::Plugin::name(self));
+    run_bot(app, shutdown).await
   }
 }
 
-pub async fn run_bot(app: Arc<AppState>) {
+pub async fn run_bot(
+  app: Arc<AppState>,
+  shutdown: CancellationToken,
+) -> anyhow::Result<()> {
   info!("Starting Telegram bot...");
 
   let bot = app.bot.clone();
@@ -36,7 +53,9 @@ pub async fn run_bot(app: Arc<AppState>) {
       let app = app.clone();
       move |bot: Bot, msg: Message, cmd: Command| {
         let app = app.clone();
-        let bot = ReplyBot::new(bot, msg.chat.id.0, msg.chat.id, msg.id);
+        let bot = ReplyBot::new(bot, msg.chat.id.0, msg.chat.id, msg.id)
+          .with_sender(msg.from.as_ref())
+          .with_reply_to(msg.reply_to_message());
         command::handle(app, bot, cmd)
       }
     }))
@@ -48,7 +67,54 @@ pub async fn run_bot(app: Arc<AppState>) {
       }
     }));
 
-  Dispatcher::builder(bot, handler).build().dispatch().await;
+  // the webhook is kept on shutdown, other replicas may still serve it
+  let listener = match &app.webhook {
+    Some(webhook) => {
+      let url =
+        Url::parse(&format!("{}{}", app.config().base_url, WEBHOOK_PATH))
+          .context("Invalid webhook URL")?;
+      Some(webhook.listen(&app.bot, url).await?)
+    }
+    None => None,
+  };
+
+  let mut dispatcher = Dispatcher::builder(bot, handler).build();
+  let token = dispatcher.shutdown_token();
+  let dispatch = async {
+    match listener {
+      Some(listener) => {
+        let errors =
+          LoggingErrorHandler::with_custom_text("Webhook listener error");
+        dispatcher.dispatch_with_listener(listener, errors).await
+      }
+      None => dispatcher.dispatch().await,
+    }
+  };
+  tokio::pin!(dispatch);
+
+  tokio::select! {
+    () = &mut dispatch => anyhow::bail!("Telegram dispatcher stopped"),
+    () = shutdown.cancelled() => {}
+  }
+
+  // let in-progress updates finish instead of dropping them mid-way. The
+  // token refuses to stop a dispatcher that is still starting, so retry
+  let stop = async {
+    loop {
+      match token.shutdown() {
+        Ok(stopped) => break stopped.await,
+        Err(_) => time::sleep(Duration::from_millis(100)).await,
+      }
+    }
+  };
+  let stopped = tokio::select! {
+    () = &mut dispatch => false,
+    () = stop => true,
+  };
+  if stopped {
+    dispatch.await;
+  }
+  Ok(())
 }
 
 async fn callback_handle(
@@ -60,7 +126,8 @@ async fn callback_handle(
     && let Some(msg) = query.message.as_ref()
   {
     let bot =
-      ReplyBot::new(bot, query.from.id.0 as i64, msg.chat().id, msg.id());
+      ReplyBot::new(bot, query.from.id.0 as i64, msg.chat().id, msg.id())
+        .with_sender(Some(&query.from));
 
     // answer callback to remove loading state
     bot.inner.answer_callback_query(query.id.clone()).await?;
@@ -77,6 +144,12 @@ struct ReplyBot {
   pub user_id: i64,
   pub chat_id: ChatId,
   pub message_id: MessageId,
+  /// Language of user facing texts, see [`ReplyBot::with_stored_lang`]
+  pub lang: Lang,
+  /// Telegram profile of the sender, cached on the user record
+  pub profile: Profile,
+  /// Document of the message a command replies to, e.g. a backup
+  pub document: Option<Document>,
 }
 
 impl ReplyBot {
@@ -86,7 +159,48 @@ impl ReplyBot {
     chat_id: ChatId,
     message_id: MessageId,
   ) -> Self {
-    Self { inner, user_id, chat_id, message_id }
+    Self {
+      inner,
+      user_id,
+      chat_id,
+      message_id,
+      lang: Lang::default(),
+      profile: Profile::default(),
+      document: None,
+    }
+  }
+
+  /// Profile of the sender, the language is detected from its
+  /// `language_code`
+  pub fn with_sender(mut self, from: Option<&teloxide::types::User>) -> Self {
+    if let Some(from) = from {
+      self.profile = Profile {
+        username: from.username.clone(),
+        first_name: Some(from.first_name.clone()),
+        language_code: from.language_code.clone(),
+      };
+    }
+    self.lang = self
+      .profile
+      .language_code
+      .as_deref()
+      .map(Lang::from_code)
+      .unwrap_or_default();
+    self
+  }
+
+  pub fn with_reply_to(mut self, message: Option<&Message>) -> Self {
+    self.document = message.and_then(|m| m.document()).cloned();
+    self
+  }
+
+  /// Prefer the language the user chose with the language switch over the
+  /// detected one, `users.language` is only set by that switch
+  pub fn with_stored_lang(mut self, user: &user::Model) -> Self {
+    if let Some(code) = &user.language {
+      self.lang = Lang::from_code(code);
+    }
+    self
   }
 
   async fn reply_html(
@@ -150,6 +264,16 @@ impl ReplyBot {
     Ok(())
   }
 
+  /// Replace the message text, dropping its keyboard
+  pub async fn edit_html(&self, text: impl Into<String>) -> ResponseResult<()> {
+    self
+      .inner
+      .edit_message_text(self.chat_id, self.message_id, text.into())
+      .parse_mode(ParseMode::Html)
+      .await?;
+    Ok(())
+  }
+
   async fn send_document(
     &self,
     document: InputFile,
@@ -157,16 +281,17 @@ impl ReplyBot {
     self.inner.send_document(self.chat_id, document).await
   }
 
-  async fn infer_username(&self, chat_id: ChatId) -> String {
-    match self.inner.get_chat(chat_id).await {
-      Ok(chat) => {
-        if let Some(username) = chat.username() {
-          format!("@{}", username)
-        } else {
-          format!("<a href=\"tg://user?id={}\">unknown</a>", chat_id)
-        }
-      }
-      Err(_) => format!("<code>{}</code> (API Error)", chat_id),
-    }
+  /// Contents of a file sent to the bot
+  async fn download(&self, file_id: FileId) -> anyhow::Result<Vec<u8>> {
+    let file = self.inner.get_file(file_id).await?;
+    let mut data = Vec::with_capacity(file.size as usize);
+    self.inner.download_file(&file.path, &mut data).await?;
+    Ok(data)
+  }
+
+  /// `t.me` link that opens the bot with `/start <payload>`
+  async fn deep_link(&self, payload: &str) -> Option<String> {
+    let me = self.inner.get_me().await.ok()?;
+    Some(format!("https://t.me/{}?start={}", me.username(), payload))
   }
 }
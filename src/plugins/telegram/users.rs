@@ -0,0 +1,457 @@
+use std::collections::HashSet;
+
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use super::callback::{AdminCallback, Callback};
+use crate::{
+  entity::{
+    LicenseType, license,
+    staff::{Permission, Role},
+  },
+  prelude::*,
+  state::AppState,
+  sv::{audit::Actor, user::Having},
+};
+
+/// Users shown on a single page of `/users`
+const PAGE_SIZE: usize = 10;
+/// Search queries travel in callback data, limited to 64 bytes by Telegram
+const MAX_QUERY_LEN: usize = 40;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+  #[default]
+  All,
+  Online,
+  Valid,
+  Expired,
+  Blocked,
+}
+
+impl Filter {
+  const ALL: [Filter; 5] = [
+    Filter::All,
+    Filter::Online,
+    Filter::Valid,
+    Filter::Expired,
+    Filter::Blocked,
+  ];
+
+  pub fn parse(input: &str) -> Option<Self> {
+    match input.to_ascii_lowercase().as_str() {
+      "all" => Some(Filter::All),
+      "online" => Some(Filter::Online),
+      "valid" => Some(Filter::Valid),
+      "expired" => Some(Filter::Expired),
+      "blocked" => Some(Filter::Blocked),
+      _ => None,
+    }
+  }
+
+  /// Short form used in callback data
+  pub fn code(self) -> char {
+    match self {
+      Filter::All => 'a',
+      Filter::Online => 'o',
+      Filter::Valid => 'v',
+      Filter::Expired => 'e',
+      Filter::Blocked => 'b',
+    }
+  }
+
+  pub fn from_code(code: char) -> Option<Self> {
+    Self::ALL.into_iter().find(|f| f.code() == code)
+  }
+
+  fn label(self) -> &'static str {
+    match self {
+      Filter::All => "All",
+      Filter::Online => "🟢",
+      Filter::Valid => "⚪",
+      Filter::Expired => "❌",
+      Filter::Blocked => "⛔",
+    }
+  }
+
+  /// Users of the filter, `online` are the keys with live sessions
+  fn having(self, online: &HashSet<String>) -> Having {
+    match self {
+      Filter::All => Having::Any,
+      Filter::Online => Having::ValidKey(online.iter().cloned().collect()),
+      Filter::Valid => Having::Valid,
+      Filter::Expired => Having::Expired,
+      Filter::Blocked => Having::Blocked,
+    }
+  }
+}
+
+/// State of the best license of a user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+  Online,
+  Valid,
+  Blocked,
+  Expired,
+  NoLicense,
+}
+
+impl Status {
+  /// `online` are the license keys with live sessions
+  fn of(
+    online: &HashSet<String>,
+    licenses: &[license::Model],
+    now: DateTime,
+  ) -> Self {
+    if licenses.is_empty() {
+      return Status::NoLicense;
+    }
+
+    let valid: Vec<_> =
+      licenses.iter().filter(|l| !l.is_blocked && l.expires_at > now).collect();
+
+    if valid.iter().any(|l| online.contains(&l.key)) {
+      Status::Online
+    } else if !valid.is_empty() {
+      Status::Valid
+    } else if licenses.iter().any(|l| l.is_blocked) {
+      Status::Blocked
+    } else {
+      Status::Expired
+    }
+  }
+
+  fn icon(self) -> &'static str {
+    match self {
+      Status::Online => "🟢",
+      Status::Valid => "⚪",
+      Status::Blocked => "⛔",
+      Status::Expired => "❌",
+      Status::NoLicense => "📂",
+    }
+  }
+}
+
+/// Trim a search query so it fits into callback data
+pub fn clamp_query(query: &str) -> String {
+  let query = query.trim();
+  let mut end = query.len().min(MAX_QUERY_LEN);
+  while !query.is_char_boundary(end) {
+    end -= 1;
+  }
+  query[..end].to_string()
+}
+
+/// A page of the user list with navigation, filter and tap-through buttons
+pub async fn page(
+  app: &AppState,
+  filter: Filter,
+  query: &str,
+  page: usize,
+) -> Result<(String, InlineKeyboardMarkup)> {
+  let now = Utc::now().naive_utc();
+  // only the online filter needs every live session, otherwise just the
+  // shown valid keys are looked up
+  let mut online = HashSet::new();
+  if filter == Filter::Online {
+    online =
+      app.all_sessions().await?.into_iter().map(|(key, _)| key).collect();
+  }
+
+  let found = app
+    .sv()
+    .user
+    .search(&filter.having(&online), query, page as u64, PAGE_SIZE as u64)
+    .await?;
+
+  if filter != Filter::Online {
+    let valid = found
+      .users
+      .iter()
+      .flat_map(|(_, licenses)| licenses)
+      .filter(|l| !l.is_blocked && l.expires_at > now);
+    for license in valid {
+      if !app.live_sessions(&license.key).await?.is_empty() {
+        online.insert(license.key.clone());
+      }
+    }
+  }
+
+  let page = found.page as usize;
+  let pages = (found.total as usize).div_ceil(PAGE_SIZE).max(1);
+  let shown: Vec<_> = found
+    .users
+    .iter()
+    .map(|(user, licenses)| (user, Status::of(&online, licenses, now)))
+    .collect();
+
+  let mut text = format!("👥 <b>Users ({})</b>", found.total);
+  if filter != Filter::All {
+    text.push_str(&format!(" · {:?}", filter));
+  }
+  if !query.is_empty() {
+    text.push_str(&format!(
+      " · search <code>{}</code>",
+      teloxide::utils::html::escape(query)
+    ));
+  }
+  text.push_str("\n\n");
+
+  if shown.is_empty() {
+    text.push_str("<i>No users found.</i>");
+  }
+  for (i, (user, status)) in shown.iter().enumerate() {
+    text.push_str(&format!(
+      "<b>{}.</b> {} {} <code>{}</code>\n",
+      page * PAGE_SIZE + i + 1,
+      status.icon(),
+      utils::format_user(user),
+      user.tg_user_id
+    ));
+  }
+
+  let mut rows: Vec<Vec<InlineKeyboardButton>> = shown
+    .chunks(2)
+    .map(|pair| {
+      pair
+        .iter()
+        .map(|(user, status)| {
+          InlineKeyboardButton::callback(
+            format!("{} {}", status.icon(), user.tg_user_id),
+            admin(AdminCallback::UserCard(user.tg_user_id)),
+          )
+        })
+        .collect()
+    })
+    .collect();
+
+  let goto = |page: usize| {
+    admin(AdminCallback::Users { filter, page, query: query.to_string() })
+  };
+  let mut nav = Vec::new();
+  if page > 0 {
+    nav.push(InlineKeyboardButton::callback("‹ Prev", goto(page - 1)));
+  }
+  nav.push(InlineKeyboardButton::callback(
+    format!("{}/{}", page + 1, pages),
+    goto(page),
+  ));
+  if page + 1 < pages {
+    nav.push(InlineKeyboardButton::callback("Next ›", goto(page + 1)));
+  }
+  rows.push(nav);
+
+  rows.push(
+    Filter::ALL
+      .into_iter()
+      .map(|f| {
+        let label = if f == filter {
+          format!("· {} ·", f.label())
+        } else {
+          f.label().to_string()
+        };
+        let data =
+          AdminCallback::Users { filter: f, page: 0, query: query.to_string() };
+        InlineKeyboardButton::callback(label, admin(data))
+      })
+      .collect(),
+  );
+
+  Ok((text, InlineKeyboardMarkup::new(rows)))
+}
+
+/// Per-user card with buttons for each license and session control
+pub async fn card(
+  app: &AppState,
+  role: Role,
+  user_id: i64,
+) -> Result<(String, InlineKeyboardMarkup)> {
+  let sv = app.sv();
+  let text =
+    super::command::process_info_command(&sv, app, user_id.to_string()).await?;
+  let licenses = sv.license.by_user(user_id, true).await?;
+  let mut online = false;
+  for license in &licenses {
+    online |= !app.live_sessions(&license.key).await?.is_empty();
+  }
+
+  let mut rows: Vec<Vec<InlineKeyboardButton>> = licenses
+    .iter()
+    .map(|license| {
+      vec![InlineKeyboardButton::callback(
+        format!("🔑 {} ({:?})", license.key, license.license_type),
+        admin(AdminCallback::LicenseCard(license.key.clone())),
+      )]
+    })
+    .collect();
+
+  if role.can(Permission::ManageLicenses) && online {
+    rows.push(vec![InlineKeyboardButton::callback(
+      "🔌 Kick sessions",
+      admin(AdminCallback::UserKick(user_id)),
+    )]);
+  }
+  rows.push(vec![
+    InlineKeyboardButton::callback(
+      "🔄 Refresh",
+      admin(AdminCallback::UserCard(user_id)),
+    ),
+    InlineKeyboardButton::callback(
+      "« Users",
+      admin(AdminCallback::Users {
+        filter: Filter::All,
+        page: 0,
+        query: String::new(),
+      }),
+    ),
+  ]);
+
+  Ok((text, InlineKeyboardMarkup::new(rows)))
+}
+
+/// License card with the admin actions `role` may use, reached from a user
+/// card or `/info`
+pub async fn license_card(
+  app: &AppState,
+  role: Role,
+  key: &str,
+) -> Result<(String, InlineKeyboardMarkup)> {
+  let sv = app.sv();
+  let license = sv.license.by_key(key).await?.ok_or(Error::LicenseNotFound)?;
+  let text =
+    super::command::process_info_command(&sv, app, key.to_string()).await?;
+
+  let key = &license.key;
+  let button = |label: &str, action: AdminCallback| {
+    InlineKeyboardButton::callback(label, admin(action))
+  };
+
+  let mut rows = Vec::new();
+  if role.can(Permission::ExtendLicense) {
+    rows.push(vec![
+      button("+7d", AdminCallback::Extend(key.clone(), 7)),
+      button("+30d", AdminCallback::Extend(key.clone(), 30)),
+      button("✏️ Custom", AdminCallback::ExtendCustom(key.clone())),
+    ]);
+  }
+
+  if role.can(Permission::ManageLicenses) {
+    let mut controls = vec![if license.is_blocked {
+      button("✅ Unblock", AdminCallback::Block(key.clone(), false))
+    } else {
+      button("⛔ Block", AdminCallback::Block(key.clone(), true))
+    }];
+    if !app.live_sessions(key).await?.is_empty() {
+      controls
+        .push(button("🔌 Kick sessions", AdminCallback::Kick(key.clone())));
+    }
+    rows.push(controls);
+
+    rows.push(vec![match license.license_type {
+      LicenseType::Trial => button(
+        "⭐ Make Pro",
+        AdminCallback::SetTier(key.clone(), LicenseType::Pro),
+      ),
+      LicenseType::Pro => button(
+        "Make Trial",
+        AdminCallback::SetTier(key.clone(), LicenseType::Trial),
+      ),
+    }]);
+  }
+
+  rows.push(vec![
+    button("🔄 Refresh", AdminCallback::LicenseCard(key.clone())),
+    button("« User", AdminCallback::UserCard(license.tg_user_id)),
+  ]);
+
+  Ok((text, InlineKeyboardMarkup::new(rows)))
+}
+
+/// Apply a license card action and render the updated card with a notice
+pub async fn license_action(
+  app: &AppState,
+  role: Role,
+  actor: Actor,
+  action: AdminCallback,
+) -> Result<(String, InlineKeyboardMarkup)> {
+  let sv = app.sv_as(actor);
+
+  let (key, notice) = match action {
+    AdminCallback::Extend(key, days) => {
+      let license = sv.license.extend(&key, days).await?;
+      let notice = format!(
+        "✅ Extended by {} days, expires {}",
+        days,
+        utils::format_date(license.expires_at)
+      );
+      (key, notice)
+    }
+    AdminCallback::Block(key, blocked) => {
+      sv.license.set_blocked(&key, blocked).await?;
+      if blocked {
+        app.drop_sessions(&key).await?;
+        (key, "🚫 Key blocked, sessions dropped".into())
+      } else {
+        (key, "✅ Key unblocked".into())
+      }
+    }
+    AdminCallback::Kick(key) => {
+      // machines are only bound through live sessions, dropping them
+      // frees the machines as well
+      app.drop_sessions(&key).await?;
+      (key, "🔌 Sessions dropped".into())
+    }
+    AdminCallback::SetTier(key, ty) => {
+      sv.license.set_type(&key, ty.clone()).await?;
+      app.sessions.set_tier(&key, ty.clone()).await?;
+      (key, format!("✅ Tier changed to {:?}", ty))
+    }
+    other => {
+      return Err(Error::InvalidArgs(format!(
+        "{:?} is not a license action",
+        other
+      )));
+    }
+  };
+
+  let (text, keyboard) = license_card(app, role, &key).await?;
+  Ok((format!("{}\n\n{}", notice, text), keyboard))
+}
+
+/// Drop the sessions of all licenses of a user
+pub async fn kick(app: &AppState, user_id: i64) -> Result<()> {
+  for license in app.sv().license.by_user(user_id, true).await? {
+    app.drop_sessions(&license.key).await?;
+  }
+  Ok(())
+}
+
+fn admin(action: AdminCallback) -> String {
+  Callback::Admin(action).to_data()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_filter_codes() {
+    for filter in Filter::ALL {
+      assert_eq!(Filter::from_code(filter.code()), Some(filter));
+    }
+    assert_eq!(Filter::from_code('x'), None);
+  }
+
+  #[test]
+  fn test_clamp_query() {
+    assert_eq!(clamp_query("  alice "), "alice");
+
+    let long = "a".repeat(MAX_QUERY_LEN + 10);
+    assert_eq!(clamp_query(&long).len(), MAX_QUERY_LEN);
+
+    // never splits a multi-byte character
+    let cyrillic = "я".repeat(MAX_QUERY_LEN);
+    let clamped = clamp_query(&cyrillic);
+    assert!(clamped.len() <= MAX_QUERY_LEN);
+    assert!(clamped.chars().all(|c| c == 'я'));
+  }
+}
@@ -0,0 +1,135 @@
+use std::{convert::Infallible, pin::Pin, sync::Arc};
+
+use futures::{Stream, stream};
+use reqwest::Url;
+use teloxide::{
+  prelude::*,
+  stop::{StopToken, mk_stop_token},
+  types::Update,
+  update_listeners::{StatefulListener, UpdateListener},
+};
+use tokio::sync::{Mutex, mpsc};
+
+use crate::prelude::*;
+
+/// Route of the HTTP server Telegram pushes updates to
+pub const PATH: &str = "/api/telegram";
+
+type Updates = Pin<Box<dyn Stream<Item = Result<Update, Infallible>> + Send>>;
+
+/// Updates pushed by Telegram to [`PATH`], used instead of long polling
+/// when `TELEGRAM_WEBHOOK_SECRET` is set
+pub struct Webhook {
+  /// Expected in the `X-Telegram-Bot-Api-Secret-Token` header
+  pub secret: String,
+  sender: mpsc::UnboundedSender<Update>,
+  /// Locked by the dispatcher for as long as it listens
+  receiver: Arc<Mutex<mpsc::UnboundedReceiver<Update>>>,
+}
+
+impl Webhook {
+  pub fn new(secret: String) -> Self {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    Self { secret, sender, receiver: Arc::new(Mutex::new(receiver)) }
+  }
+
+  /// Hand an update to the dispatcher, `false` if the bot is not running,
+  /// so Telegram delivers it again later
+  pub fn push(&self, update: Update) -> bool {
+    if self.receiver.try_lock().is_ok() {
+      return false;
+    }
+    self.sender.send(update).is_ok()
+  }
+
+  /// Point Telegram to `url` and listen for what it pushes there
+  pub async fn listen(
+    &self,
+    bot: &Bot,
+    url: Url,
+  ) -> anyhow::Result<impl UpdateListener<Err = Infallible> + use<>> {
+    bot
+      .set_webhook(url.clone())
+      .secret_token(self.secret.clone())
+      .await
+      .context("Failed to set the webhook")?;
+    info!("Receiving Telegram updates at {}", url);
+
+    Ok(self.listener().await)
+  }
+
+  async fn listener(&self) -> impl UpdateListener<Err = Infallible> + use<> {
+    let receiver = self.receiver.clone().lock_owned().await;
+    let (token, flag) = mk_stop_token();
+
+    // ends as soon as the dispatcher stops it, queued updates are left to
+    // the next run
+    let updates: Updates = Box::pin(stream::unfold(
+      (receiver, flag),
+      |(mut receiver, flag)| async move {
+        tokio::select! {
+          biased;
+          _ = flag.clone() => None,
+          update = receiver.recv() => {
+            update.map(|update| (Ok(update), (receiver, flag)))
+          }
+        }
+      },
+    ));
+
+    fn updates_of(state: &mut (Updates, StopToken)) -> &mut Updates {
+      &mut state.0
+    }
+    StatefulListener::new(
+      (updates, token),
+      updates_of,
+      |state: &mut (Updates, StopToken)| state.1.clone(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use futures::StreamExt;
+  use teloxide::update_listeners::AsUpdateStream;
+
+  use super::*;
+
+  fn update(id: i32) -> Update {
+    json::from_value(json::json!({
+      "update_id": id,
+      "poll_answer": {
+        "poll_id": "1",
+        "user": { "id": 1, "is_bot": false, "first_name": "A" },
+        "option_ids": [0]
+      }
+    }))
+    .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_updates_reach_the_listener() {
+    let webhook = Webhook::new("secret".into());
+    assert!(!webhook.push(update(1)));
+
+    {
+      let mut listener = webhook.listener().await;
+      assert!(webhook.push(update(2)));
+      assert!(webhook.push(update(3)));
+
+      let stop = listener.stop_token();
+      let mut updates = std::pin::pin!(listener.as_stream());
+      let first = updates.next().await.unwrap().unwrap();
+      assert_eq!(first.id.0, 2);
+
+      stop.stop();
+      assert!(updates.next().await.is_none());
+    }
+
+    // the next listener takes over what is left
+    let mut listener = webhook.listener().await;
+    let mut updates = std::pin::pin!(listener.as_stream());
+    let next = updates.next().await.unwrap().unwrap();
+    assert_eq!(next.id.0, 3);
+  }
+}
@@ -0,0 +1,77 @@
+use std::{
+  path::{Path, PathBuf},
+  sync::Arc,
+  time::SystemTime,
+};
+
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+  config::Config as Settings, plugins::Plugin, prelude::*, state::AppState,
+};
+
+/// Hot-reloads the config file on SIGHUP or when the file changes
+pub struct Config {
+  pub path: PathBuf,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+  std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Config {
+  fn reload(&self, app: &AppState, trigger: &str) {
+    match Settings::load(&self.path) {
+      Ok(new) => {
+        let restart = app.reload_config(new);
+        info!("Configuration reloaded ({})", trigger);
+        if !restart.is_empty() {
+          warn!(
+            "Changed config fields apply after restart only: {}",
+            restart.join(", ")
+          );
+        }
+        app.plugins.report_success(self.name());
+      }
+      Err(msg) => {
+        error!("Config reload failed, keeping current config:\n{}", msg);
+      }
+    }
+  }
+}
+
+#[async_trait]
+impl Plugin for Config {
+  async fn start(
+    &self,
+    app: Arc<AppState>,
+    shutdown: CancellationToken,
+  ) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    let mut hangup =
+      tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+
+    let mut interval = time::interval(Duration::from_secs(5));
+    let mut last_modified = modified(&self.path);
+
+    loop {
+      #[cfg(unix)]
+      let sighup = hangup.recv();
+      #[cfg(not(unix))]
+      let sighup = std::future::pending::<Option<()>>();
+
+      tokio::select! {
+        _ = shutdown.cancelled() => return Ok(()),
+        _ = sighup => self.reload(&app, "SIGHUP"),
+        _ = interval.tick() => {
+          let current = modified(&self.path);
+          if current != last_modified {
+            last_modified = current;
+            self.reload(&app, "file change");
+          }
+        }
+      }
+    }
+  }
+}
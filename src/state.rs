@@ -1,8 +1,10 @@
 use std::{
   collections::HashSet,
-  hash::{DefaultHasher, Hash, Hasher},
-  path::Path,
-  sync::atomic::{AtomicU64, Ordering},
+  path::{Path, PathBuf},
+  sync::{
+    Arc, RwLock,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+  },
 };
 
 use migration::Migrator;
@@ -11,90 +13,80 @@ use teloxide::{
   prelude::*,
   types::{InputFile, ParseMode},
 };
-use tokio::fs;
 use tracing::{debug, info};
 use uuid::Uuid;
 
-use crate::{entity::license, prelude::*, sv};
+use crate::{
+  backup,
+  entity::{LicenseType, staff::Role},
+  metrics::Metrics,
+  plugins::{Registry, telegram::Webhook},
+  prelude::*,
+  store::{SessionStore, Store, TokenStore},
+  sv::{self, audit::Actor},
+};
+pub use crate::{
+  config::Config,
+  store::{DownloadToken, Session},
+};
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
-pub struct Session {
-  pub session_id: String,
-  pub hwid_hash: Option<String>,
-  pub last_seen: DateTime,
-}
+/// Rejected metrics submissions per license key, for abuse monitoring:
+/// (rejections, last rejection)
+pub type MetricsRejections = DashMap<String, (u64, DateTime)>;
 
-pub type Sessions = DashMap<String, Vec<Session>>;
+/// Keys without rejections for this long are forgotten
+const METRICS_REJECTIONS_WINDOW: TimeDelta = TimeDelta::days(1);
 
-/// Download token stored in DashMap with expiry
-#[derive(Debug, Clone)]
-pub struct DownloadToken {
-  pub version: String,
-  pub created_at: DateTime,
-}
+/// Failed code redemptions per user: (failures, window start)
+pub type RedeemAttempts = DashMap<i64, (u32, DateTime)>;
 
-pub type DownloadTokens = DashMap<String, DownloadToken>;
-
-#[derive(Debug, Clone)]
-pub struct Config {
-  pub builds_directory: String,
-  pub session_lifetime: i64,
-  pub backup_hours: u64,
-  pub download_token_lifetime: i64,
-  pub base_url: String,
-  /// Minimum free disk space in bytes before triggering yanked builds GC.
-  /// Default: 500MB (enough for ~2 releases at ~230MB each)
-  pub gc_min_free_space: u64,
-  /// Interval in seconds for checking disk space and triggering GC.
-  /// Default: 60 seconds
-  pub gc_check_interval_secs: u64,
-}
+/// Failed redemptions allowed per user within [`REDEEM_WINDOW`]
+const REDEEM_MAX_FAILURES: u32 = 5;
+const REDEEM_WINDOW: TimeDelta = TimeDelta::minutes(15);
 
-impl Default for Config {
-  fn default() -> Self {
-    Self {
-      builds_directory: String::from("./builds"),
-      session_lifetime: 120,
-      backup_hours: 1,
-      download_token_lifetime: 600, // 10 minutes
-      base_url: String::from("http://localhost:3000"),
-      gc_min_free_space: 500 * 1024 * 1024, // 500MB
-      gc_check_interval_secs: 60,
-    }
-  }
-}
+/// Checked backups not confirmed within this are discarded
+const RESTORE_TTL: TimeDelta = TimeDelta::minutes(30);
 
 pub struct Services<'a> {
+  pub anomaly: sv::Anomaly<'a>,
+  pub audit: sv::Audit<'a>,
   pub user: sv::User<'a>,
   pub stats: sv::Stats<'a>,
+  pub broadcast: sv::Broadcast<'a>,
   pub build: sv::Build<'a>,
+  pub campaign: sv::Campaign<'a>,
+  pub code: sv::Code<'a>,
+  pub leaderboard: sv::Leaderboard<'a>,
+  pub lease: sv::Lease<'a>,
   pub license: sv::License<'a>,
+  pub referral: sv::Referral<'a>,
+  pub staff: sv::Staff<'a>,
   pub steam: sv::Steam<'a>,
+  pub transfer: sv::Transfer<'a>,
 }
 
 pub struct AppState {
   pub db: DatabaseConnection,
   pub bot: Bot,
   pub admins: HashSet<i64>,
-  pub sessions: Sessions,
-  pub download_tokens: DownloadTokens,
+  pub sessions: Box<dyn SessionStore>,
+  pub download_tokens: Box<dyn TokenStore>,
+  /// Identifies this replica in leader election
+  pub instance: String,
+  leader: AtomicBool,
+  pub metrics_rejections: MetricsRejections,
+  pub redeem_attempts: RedeemAttempts,
+  pub metrics: Metrics,
+  pub plugins: Registry,
   pub secret: String,
-  pub config: Config,
-  // Backup deduplication
-  backup_hash: AtomicU64,
-}
-
-fn hash_licenses(licenses: &[license::Model]) -> u64 {
-  let mut hasher = DefaultHasher::new();
-  for lic in licenses {
-    lic.key.hash(&mut hasher);
-    lic.tg_user_id.hash(&mut hasher);
-    lic.is_blocked.hash(&mut hasher);
-    lic.expires_at.and_utc().timestamp().hash(&mut hasher);
-    lic.max_sessions.hash(&mut hasher);
-  }
-  hasher.finish()
+  /// Backups are only written when a key is set
+  pub backup_key: Option<Arc<backup::Key>>,
+  /// Telegram pushes updates to the HTTP server instead of being polled
+  pub webhook: Option<Webhook>,
+  config: RwLock<Arc<Config>>,
+  /// Checked backups waiting for confirmation, with when they were checked
+  restores: DashMap<u64, (DateTime, backup::Restore)>,
+  restore_id: AtomicU64,
 }
 
 impl AppState {
@@ -105,8 +97,16 @@ impl AppState {
     admins: HashSet<i64>,
     secret: String,
   ) -> Self {
-    Self::with_config(db_url, bot_token, admins, secret, Config::default())
-      .await
+    Self::with_config(
+      db_url,
+      bot_token,
+      admins,
+      secret,
+      None,
+      Config::default(),
+      Store::memory(),
+    )
+    .await
   }
 
   pub async fn with_config(
@@ -114,155 +114,416 @@ impl AppState {
     bot_token: &str,
     admins: HashSet<i64>,
     secret: String,
+    backup_key: Option<backup::Key>,
     config: Config,
+    store: Store,
   ) -> Self {
+    let metrics = Metrics::new();
+
     info!("Connecting to database...");
-    let db =
+    let mut db =
       Database::connect(db_url).await.expect("Failed to connect to database");
 
+    let observer = metrics.clone();
+    db.set_metric_callback(move |info| {
+      observer.observe_query(
+        &info.statement.sql,
+        info.elapsed.as_secs_f64(),
+        info.failed,
+      )
+    });
+
     info!("Running migrations...");
     Migrator::up(&db, None).await.expect("Failed to run migrations");
 
     Self {
       db,
-      sessions: DashMap::new(),
-      download_tokens: DashMap::new(),
+      sessions: store.sessions,
+      download_tokens: store.tokens,
+      instance: Uuid::new_v4().to_string(),
+      leader: AtomicBool::new(false),
+      metrics_rejections: DashMap::new(),
+      redeem_attempts: DashMap::new(),
+      metrics,
+      plugins: Registry::default(),
       bot: Bot::new(bot_token),
       admins,
       secret,
-      config,
-      backup_hash: AtomicU64::new(0),
+      backup_key: backup_key.map(Arc::new),
+      webhook: None,
+      config: RwLock::new(Arc::new(config)),
+      restores: DashMap::new(),
+      restore_id: AtomicU64::new(0),
     }
   }
 
+  /// Receive Telegram updates with `secret` in webhook mode
+  pub fn with_webhook(mut self, secret: String) -> Self {
+    self.webhook = Some(Webhook::new(secret));
+    self
+  }
+
+  /// Current configuration snapshot, may change on hot reload
+  pub fn config(&self) -> Arc<Config> {
+    self.config.read().unwrap().clone()
+  }
+
+  /// Apply hot-reloadable fields of `new`, see [`Config::reload`]
+  pub fn reload_config(&self, new: Config) -> Vec<&'static str> {
+    let mut config = self.config.write().unwrap();
+    let mut updated = Config::clone(&config);
+    let restart = updated.reload(new);
+    *config = Arc::new(updated);
+    restart
+  }
+
   pub fn sv(&self) -> Services<'_> {
+    self.sv_as(Actor::System("server"))
+  }
+
+  /// Services whose license and build mutations are audited as `actor`
+  pub fn sv_as(&self, actor: Actor) -> Services<'_> {
     Services {
+      anomaly: sv::Anomaly::new(&self.db),
+      audit: sv::Audit::new(&self.db),
       user: sv::User::new(&self.db),
       stats: sv::Stats::new(&self.db),
-      build: sv::Build::new(&self.db),
-      license: sv::License::new(&self.db),
+      broadcast: sv::Broadcast::new(&self.db),
+      build: sv::Build::new(&self.db).by(actor.clone()),
+      campaign: sv::Campaign::new(&self.db),
+      code: sv::Code::new(&self.db),
+      leaderboard: sv::Leaderboard::new(&self.db),
+      lease: sv::Lease::new(&self.db),
+      license: sv::License::new(&self.db).by(actor.clone()),
+      referral: sv::Referral::new(&self.db),
+      staff: sv::Staff::new(&self.db),
       steam: sv::Steam::new(&self.db),
+      transfer: sv::Transfer::new(&self.db).by(actor),
     }
   }
 
-  /// Perform backup only when license data changes.
-  /// Changes in metrics/stats tables are not a reason to backup.
-  pub async fn perform_smart_backup(&self) -> anyhow::Result<()> {
-    // Hash only license data - stats/metrics changes don't trigger backups
-    let licenses = license::Entity::find()
-      .order_by_asc(license::Column::Key)
-      .all(&self.db)
-      .await?;
-
-    let new_hash = hash_licenses(&licenses);
-    let old_hash = self.backup_hash.load(Ordering::Relaxed);
+  /// Whether this replica runs the singleton jobs, see [`crate::plugins::cron::Leader`]
+  pub fn is_leader(&self) -> bool {
+    self.leader.load(Ordering::Relaxed)
+  }
 
-    self.backup_hash.store(new_hash, Ordering::Relaxed);
+  /// Returns the previous state
+  pub fn set_leader(&self, leader: bool) -> bool {
+    self.leader.swap(leader, Ordering::Relaxed)
+  }
 
-    // Skip backup if no license changes (or first run)
-    if new_hash == old_hash || old_hash == 0 {
-      debug!("No license changes, skipping backup");
-      return Ok(());
+  /// Role of a bot operator, users listed in `ADMIN_IDS` are always owners
+  pub async fn role_of(&self, user_id: i64) -> Option<Role> {
+    if self.admins.contains(&user_id) {
+      return Some(Role::Owner);
     }
+    match sv::Staff::new(&self.db).role_of(user_id).await {
+      Ok(role) => role,
+      Err(e) => {
+        warn!("Failed to look up role of {}: {}", user_id, e);
+        None
+      }
+    }
+  }
 
-    let timestamp = Utc::now().format("%Y-%m-%d_%H-%M-%S");
-    let filename = format!("backup_{}.db", timestamp);
-    let path = Path::new(&filename);
+  /// Perform backup only when the data of `backup_tables` changed since
+  /// the latest backup. Changes in metrics/stats tables are not a reason
+  /// to backup.
+  pub async fn perform_smart_backup(&self) -> anyhow::Result<()> {
+    let config = self.config();
+    let dir = Path::new(&config.backup_directory);
 
-    if path.exists() {
-      let _ = fs::remove_file(path).await;
+    let hash = backup::data_hash(&self.db, &config.backup_tables).await?;
+    let latest = backup::latest(dir).await.and_then(|m| m.data_hash);
+    if latest.as_deref() == Some(hash.as_str()) {
+      debug!("No data changes, skipping backup");
+      return Ok(());
     }
 
-    let query = format!("VACUUM INTO '{}'", filename);
-    self
-      .db
-      .execute(sea_orm::Statement::from_string(
-        sea_orm::DatabaseBackend::Sqlite,
-        query,
-      ))
-      .await?;
+    let (path, manifest) = self.write_backup().await?;
+    if !self.config().backup_telegram {
+      return Ok(());
+    }
 
     for &admin in self.admins.iter() {
-      let doc = InputFile::file(path);
       let caption = format!(
-        "📦 <b>Database Backup</b>\nLicense changes detected.\nTime: {}",
-        timestamp
+        "📦 <b>Database Backup</b>\nData changes detected.\n\
+        Time: {}\nSHA-256: <code>{}</code>",
+        utils::format_date(manifest.created_at),
+        manifest.sha256
       );
 
       let _ = self
         .bot
-        .send_document(ChatId(admin), doc)
+        .send_document(ChatId(admin), InputFile::file(&path))
         .caption(caption)
         .parse_mode(ParseMode::Html)
         .await;
     }
-
-    let _ = fs::remove_file(path).await;
     Ok(())
   }
 
   pub async fn perform_backup(&self, chat_id: ChatId) -> anyhow::Result<()> {
-    let timestamp = Utc::now().format("%Y-%m-%d_%H-%M-%S");
-    let filename = format!("manual_backup_{}.db", timestamp);
+    let (path, manifest) = self.write_backup().await?;
 
-    let query = format!("VACUUM INTO '{}'", filename);
+    let caption = format!("SHA-256: <code>{}</code>", manifest.sha256);
     self
-      .db
-      .execute(sea_orm::Statement::from_string(
-        sea_orm::DatabaseBackend::Sqlite,
-        query,
-      ))
+      .bot
+      .send_document(chat_id, InputFile::file(&path))
+      .caption(caption)
+      .parse_mode(ParseMode::Html)
       .await?;
+    Ok(())
+  }
 
-    let path = Path::new(&filename);
-    let _ = self.bot.send_document(chat_id, InputFile::file(path)).await;
-    let _ = fs::remove_file(path).await;
+  /// Write an encrypted backup to the backup directory and apply retention
+  async fn write_backup(&self) -> anyhow::Result<(PathBuf, backup::Manifest)> {
+    let key = self
+      .backup_key
+      .clone()
+      .context("BACKUP_KEY is not set, refusing to write a plain backup")?;
+
+    let config = self.config();
+    let dir = Path::new(&config.backup_directory);
+    let (path, manifest) =
+      backup::create(&self.db, key, dir, &config.backup_tables).await?;
+    info!("Backup written to {} ({} bytes)", path.display(), manifest.size);
+
+    match backup::prune(dir, config.backup_retention()).await {
+      Ok(0) => {}
+      Ok(deleted) => info!("Deleted {} old backups", deleted),
+      Err(e) => warn!("Failed to prune backups: {}", e),
+    }
+    Ok((path, manifest))
+  }
 
-    Ok(())
+  /// Check a backup and hold it until [`Self::restore`] or
+  /// [`Self::cancel_restore`], returns its id and summary
+  pub async fn prepare_restore(
+    &self,
+    data: Vec<u8>,
+  ) -> anyhow::Result<(u64, String)> {
+    let key = self.backup_key.clone().context("BACKUP_KEY is not set")?;
+    let dir = PathBuf::from(&self.config().backup_directory);
+
+    let restore = backup::prepare(&self.db, key, data, &dir).await?;
+    let summary = restore.summary();
+    let id = self.restore_id.fetch_add(1, Ordering::Relaxed) + 1;
+    self.restores.insert(id, (Utc::now().naive_utc(), restore));
+    Ok((id, summary))
   }
 
-  pub fn gc_sessions(&self) {
+  /// Replace the database with a prepared backup on behalf of `actor`,
+  /// returns the path of the pre-restore snapshot
+  pub async fn restore(
+    &self,
+    id: u64,
+    actor: Actor,
+  ) -> anyhow::Result<PathBuf> {
+    let key = self.backup_key.clone().context("BACKUP_KEY is not set")?;
+    let (_, (prepared_at, restore)) =
+      self.restores.remove(&id).context("Restore is no longer pending")?;
+    let config = self.config();
+    let dir = Path::new(&config.backup_directory);
+
+    let result =
+      backup::restore(&self.db, key, &restore, dir, &config.backup_tables)
+        .await;
+    match &result {
+      Ok(snapshot) => {
+        // sessions may belong to licenses that no longer exist
+        if let Err(e) = self.sessions.clear().await {
+          warn!("Failed to drop sessions after restore: {}", e);
+        }
+        // written to the restored database, the swap already happened
+        let after = json::json!({
+          "snapshot": snapshot,
+          "migration": restore.migration,
+          "counts": restore.counts,
+        });
+        let recorded = sv::Audit::record(
+          &self.db,
+          &actor,
+          "backup.restore",
+          &format!("restore:{}", id),
+          None,
+          None,
+          Some(&after),
+        );
+        if let Err(e) = recorded.await {
+          error!("Failed to audit restore #{}: {}", id, e);
+        }
+      }
+      Err(_) => {
+        self.restores.insert(id, (prepared_at, restore));
+      }
+    }
+    result
+  }
+
+  pub async fn cancel_restore(&self, id: u64) -> bool {
+    match self.restores.remove(&id) {
+      Some((_, (_, restore))) => {
+        backup::discard(&self.db, &restore).await;
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Discard checked backups left unconfirmed for [`RESTORE_TTL`]
+  pub async fn gc_restores(&self) {
     let now = Utc::now().naive_utc();
-    let timeout = self.config.session_lifetime;
+    let expired: Vec<u64> = self
+      .restores
+      .iter()
+      .filter(|entry| now - entry.0 >= RESTORE_TTL)
+      .map(|entry| *entry.key())
+      .collect();
+    for id in expired {
+      if let Some((_, (_, restore))) = self.restores.remove(&id) {
+        info!("Discarding unconfirmed restore #{}", id);
+        backup::discard(&self.db, &restore).await;
+      }
+    }
+  }
 
-    self.sessions.retain(|_key, sessions| {
-      sessions.retain(|s| (now - s.last_seen).num_seconds() < timeout);
-      !sessions.is_empty()
-    });
+  pub async fn gc_sessions(&self) -> Result<()> {
+    self.sessions.gc(self.config().session_lifetime).await
   }
 
-  pub fn drop_sessions(&self, key: &str) {
-    self.sessions.remove(key);
+  pub async fn drop_sessions(&self, key: &str) -> Result<()> {
+    self.sessions.remove(key).await
   }
 
-  pub fn create_download_token(&self, version: &str) -> String {
-    let token = Uuid::new_v4().to_string();
+  /// Sessions of `key` seen within the session lifetime
+  pub async fn live_sessions(&self, key: &str) -> Result<Vec<Session>> {
+    self.sessions.live(key, self.config().session_lifetime).await
+  }
+
+  /// Live sessions of every license key that has any
+  pub async fn all_sessions(&self) -> Result<Vec<(String, Vec<Session>)>> {
+    self.sessions.all(self.config().session_lifetime).await
+  }
+
+  /// Check that `session_id` is a live (not yet GC'd) session of `key`
+  pub async fn has_session(&self, key: &str, session_id: &str) -> Result<bool> {
+    let sessions = self.live_sessions(key).await?;
+    Ok(sessions.iter().any(|s| s.session_id == session_id))
+  }
+
+  /// Count a rejected metrics submission, returns total rejections for
+  /// `key` or `None` when no such license exists, so random keys can not
+  /// grow the map
+  pub async fn reject_metrics(&self, key: &str) -> Result<Option<u64>> {
+    if self.sv().license.by_key(key).await?.is_none() {
+      return Ok(None);
+    }
+
     let now = Utc::now().naive_utc();
-    self.download_tokens.insert(
-      token.clone(),
-      DownloadToken { version: version.to_string(), created_at: now },
-    );
-    token
+    let mut entry =
+      self.metrics_rejections.entry(key.to_string()).or_insert((0, now));
+    entry.0 += 1;
+    entry.1 = now;
+    Ok(Some(entry.0))
   }
 
-  pub fn validate_download_token(&self, token: &str) -> Option<String> {
+  pub fn gc_metrics_rejections(&self) {
     let now = Utc::now().naive_utc();
-    let timeout = self.config.download_token_lifetime;
+    self
+      .metrics_rejections
+      .retain(|_, (_, last)| now - *last < METRICS_REJECTIONS_WINDOW);
+  }
 
-    if let Some(dt) = self.download_tokens.get(token)
-      && (now - dt.created_at).num_seconds() < timeout
+  /// Redeem a code unless the user exceeded the failed attempts limit.
+  /// Only unknown or expired codes count as failures.
+  pub async fn redeem_code(
+    &self,
+    tg_user_id: i64,
+    code: &str,
+  ) -> Result<sv::code::Redeemed> {
+    let now = Utc::now().naive_utc();
+
+    if let Some(attempts) = self.redeem_attempts.get(&tg_user_id)
+      && attempts.0 >= REDEEM_MAX_FAILURES
+      && now - attempts.1 < REDEEM_WINDOW
     {
-      return Some(dt.version.clone());
+      return Err(Error::TooManyAttempts);
+    }
+
+    let result = self.sv().code.redeem(tg_user_id, code).await;
+    if let Err(Error::CodeInvalid) = result {
+      let mut attempts =
+        self.redeem_attempts.entry(tg_user_id).or_insert((0, now));
+      if now - attempts.1 >= REDEEM_WINDOW {
+        *attempts = (0, now);
+      }
+      attempts.0 += 1;
     }
-    None
+    result
   }
 
-  pub fn gc_download_tokens(&self) {
+  pub fn gc_redeem_attempts(&self) {
     let now = Utc::now().naive_utc();
-    let timeout = self.config.download_token_lifetime;
+    self.redeem_attempts.retain(|_, (_, since)| now - *since < REDEEM_WINDOW);
+  }
 
-    self
-      .download_tokens
-      .retain(|_, dt| (now - dt.created_at).num_seconds() < timeout);
+  /// Refresh scrape-time gauges and encode all metrics
+  pub async fn render_metrics(&self) -> String {
+    use sea_orm::{ActiveEnum, Iterable};
+
+    let gauge = &self.metrics.active_sessions;
+    match self.all_sessions().await {
+      Ok(all) => {
+        for ty in LicenseType::iter() {
+          gauge.with_label_values(&[ty.to_value().as_str()]).set(0);
+        }
+        for s in all.iter().flat_map(|(_, sessions)| sessions) {
+          gauge.with_label_values(&[s.license_type.to_value().as_str()]).inc();
+        }
+      }
+      // keep the last values rather than report zero sessions
+      Err(e) => warn!("Failed to count sessions: {}", e),
+    }
+
+    self.metrics.encode()
+  }
+
+  /// Close the database pool before exit. In-memory sessions and download
+  /// tokens are not persisted, clients reopen sessions on the next
+  /// heartbeat; with a shared store they outlive the process anyway.
+  pub async fn close(&self) {
+    if let Err(err) = self.db.clone().close().await {
+      error!("Failed to close database: {}", err);
+    }
+  }
+
+  pub async fn create_download_token(&self, version: &str) -> Result<String> {
+    let token = Uuid::new_v4().to_string();
+    let now = Utc::now().naive_utc();
+    let value = DownloadToken { version: version.to_string(), created_at: now };
+    let lifetime = self.config().download_token_lifetime;
+    self.download_tokens.insert(&token, value, lifetime).await?;
+    Ok(token)
+  }
+
+  pub async fn validate_download_token(
+    &self,
+    token: &str,
+  ) -> Result<Option<String>> {
+    let now = Utc::now().naive_utc();
+    let timeout = self.config().download_token_lifetime;
+
+    if let Some(dt) = self.download_tokens.get(token).await?
+      && (now - dt.created_at).num_seconds() < timeout
+    {
+      return Ok(Some(dt.version));
+    }
+    Ok(None)
+  }
+
+  pub async fn gc_download_tokens(&self) -> Result<()> {
+    let lifetime = self.config().download_token_lifetime;
+    self.download_tokens.gc(lifetime).await
   }
 }
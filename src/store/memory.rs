@@ -0,0 +1,124 @@
+use super::{DownloadToken, Session, SessionStore, TokenStore};
+use crate::{entity::LicenseType, prelude::*};
+
+fn is_live(session: &Session, now: DateTime, lifetime: i64) -> bool {
+  (now - session.last_seen).num_seconds() < lifetime
+}
+
+#[derive(Default)]
+pub struct Sessions(DashMap<String, Vec<Session>>);
+
+#[async_trait]
+impl SessionStore for Sessions {
+  async fn touch(&self, key: &str, session_id: &str, _: i64) -> Result<bool> {
+    let now = Utc::now().naive_utc();
+    if let Some(mut sessions) = self.0.get_mut(key)
+      && let Some(session) =
+        sessions.iter_mut().find(|s| s.session_id == session_id)
+    {
+      session.last_seen = now;
+      return Ok(true);
+    }
+    Ok(false)
+  }
+
+  async fn open(
+    &self,
+    key: &str,
+    session: Session,
+    max: usize,
+    lifetime: i64,
+  ) -> Result<Option<usize>> {
+    let now = Utc::now().naive_utc();
+    let mut entry = self.0.entry(key.to_string()).or_default();
+    entry.retain(|s| is_live(s, now, lifetime));
+
+    if entry.len() >= max {
+      return Ok(Some(entry.len()));
+    }
+    entry.push(session);
+    Ok(None)
+  }
+
+  async fn live(&self, key: &str, lifetime: i64) -> Result<Vec<Session>> {
+    let now = Utc::now().naive_utc();
+    let live = self.0.get(key).map(|sessions| {
+      sessions.iter().filter(|s| is_live(s, now, lifetime)).cloned().collect()
+    });
+    Ok(live.unwrap_or_default())
+  }
+
+  async fn all(&self, lifetime: i64) -> Result<Vec<(String, Vec<Session>)>> {
+    let now = Utc::now().naive_utc();
+    let all = self
+      .0
+      .iter()
+      .map(|kv| {
+        let live =
+          kv.value().iter().filter(|s| is_live(s, now, lifetime)).cloned();
+        (kv.key().clone(), live.collect::<Vec<_>>())
+      })
+      .filter(|(_, sessions)| !sessions.is_empty())
+      .collect();
+    Ok(all)
+  }
+
+  async fn set_tier(&self, key: &str, tier: LicenseType) -> Result<()> {
+    if let Some(mut sessions) = self.0.get_mut(key) {
+      for session in sessions.iter_mut() {
+        session.license_type = tier.clone();
+      }
+    }
+    Ok(())
+  }
+
+  async fn remove(&self, key: &str) -> Result<()> {
+    self.0.remove(key);
+    Ok(())
+  }
+
+  async fn gc(&self, lifetime: i64) -> Result<()> {
+    let now = Utc::now().naive_utc();
+    self.0.retain(|_, sessions| {
+      sessions.retain(|s| is_live(s, now, lifetime));
+      !sessions.is_empty()
+    });
+    Ok(())
+  }
+
+  async fn clear(&self) -> Result<()> {
+    self.0.clear();
+    Ok(())
+  }
+}
+
+#[derive(Default)]
+pub struct Tokens(DashMap<String, DownloadToken>);
+
+#[async_trait]
+impl TokenStore for Tokens {
+  async fn insert(
+    &self,
+    token: &str,
+    value: DownloadToken,
+    _: i64,
+  ) -> Result<()> {
+    self.0.insert(token.to_string(), value);
+    Ok(())
+  }
+
+  async fn get(&self, token: &str) -> Result<Option<DownloadToken>> {
+    Ok(self.0.get(token).map(|value| value.clone()))
+  }
+
+  async fn gc(&self, lifetime: i64) -> Result<()> {
+    let now = Utc::now().naive_utc();
+    self.0.retain(|_, value| (now - value.created_at).num_seconds() < lifetime);
+    Ok(())
+  }
+
+  async fn clear(&self) -> Result<()> {
+    self.0.clear();
+    Ok(())
+  }
+}
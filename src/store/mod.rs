@@ -0,0 +1,217 @@
+//! Sessions and download tokens. They live in memory of the process unless
+//! `REDIS_URL` is set, then all replicas behind a load balancer share them.
+
+mod memory;
+#[cfg(feature = "redis")]
+mod redis;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{entity::LicenseType, prelude::*};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+  pub session_id: String,
+  pub hwid_hash: Option<String>,
+  pub license_type: LicenseType,
+  pub last_seen: DateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadToken {
+  pub version: String,
+  pub created_at: DateTime,
+}
+
+/// Live sessions per license key. Lifetimes are in seconds and passed on
+/// every call, since they are hot-reloadable.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+  /// Mark a session as seen now and keep it for `lifetime`, `false` if it
+  /// is not open
+  async fn touch(
+    &self,
+    key: &str,
+    session_id: &str,
+    lifetime: i64,
+  ) -> Result<bool>;
+
+  /// Open `session` unless `key` already has `max` live sessions, their
+  /// number is returned then
+  async fn open(
+    &self,
+    key: &str,
+    session: Session,
+    max: usize,
+    lifetime: i64,
+  ) -> Result<Option<usize>>;
+
+  /// Sessions of `key` seen within `lifetime`
+  async fn live(&self, key: &str, lifetime: i64) -> Result<Vec<Session>>;
+
+  /// Live sessions of every key that has any
+  async fn all(&self, lifetime: i64) -> Result<Vec<(String, Vec<Session>)>>;
+
+  async fn set_tier(&self, key: &str, tier: LicenseType) -> Result<()>;
+
+  async fn remove(&self, key: &str) -> Result<()>;
+
+  /// Forget sessions not seen within `lifetime`
+  async fn gc(&self, lifetime: i64) -> Result<()>;
+
+  async fn clear(&self) -> Result<()>;
+}
+
+#[async_trait]
+pub trait TokenStore: Send + Sync {
+  /// Keep `value` under `token` for at least `lifetime`
+  async fn insert(
+    &self,
+    token: &str,
+    value: DownloadToken,
+    lifetime: i64,
+  ) -> Result<()>;
+
+  /// The token if it was not collected yet, expiry is up to the caller
+  async fn get(&self, token: &str) -> Result<Option<DownloadToken>>;
+
+  /// Forget tokens older than `lifetime`
+  async fn gc(&self, lifetime: i64) -> Result<()>;
+
+  async fn clear(&self) -> Result<()>;
+}
+
+pub struct Store {
+  pub sessions: Box<dyn SessionStore>,
+  pub tokens: Box<dyn TokenStore>,
+}
+
+impl Store {
+  pub fn memory() -> Self {
+    Self {
+      sessions: Box::new(memory::Sessions::default()),
+      tokens: Box::new(memory::Tokens::default()),
+    }
+  }
+
+  /// Redis at `url` if given, process memory otherwise
+  pub async fn connect(url: Option<&str>) -> anyhow::Result<Self> {
+    match url {
+      None => Ok(Self::memory()),
+      #[cfg(feature = "redis")]
+      Some(url) => redis::connect(url, redis::PREFIX).await,
+      #[cfg(not(feature = "redis"))]
+      Some(_) => {
+        anyhow::bail!("REDIS_URL is set, but the redis feature is not built")
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn session(id: &str, seconds_ago: i64) -> Session {
+    Session {
+      session_id: id.into(),
+      hwid_hash: Some(format!("hwid-{}", id)),
+      license_type: LicenseType::Trial,
+      last_seen: Utc::now().naive_utc() - TimeDelta::seconds(seconds_ago),
+    }
+  }
+
+  fn ids(sessions: &[Session]) -> Vec<&str> {
+    let mut ids: Vec<_> =
+      sessions.iter().map(|s| s.session_id.as_str()).collect();
+    ids.sort();
+    ids
+  }
+
+  /// Behaviour every [`SessionStore`] shares, `key` must be unused
+  pub(super) async fn sessions(store: &dyn SessionStore, key: &str) {
+    let lifetime = 60;
+
+    assert!(!store.touch(key, "a", lifetime).await.unwrap());
+    assert_eq!(
+      store.open(key, session("a", 0), 2, lifetime).await.unwrap(),
+      None
+    );
+    assert_eq!(
+      store.open(key, session("b", 0), 2, lifetime).await.unwrap(),
+      None
+    );
+    assert_eq!(
+      store.open(key, session("c", 0), 2, lifetime).await.unwrap(),
+      Some(2)
+    );
+    assert!(store.touch(key, "a", lifetime).await.unwrap());
+    assert!(!store.touch(key, "c", lifetime).await.unwrap());
+
+    let live = store.live(key, lifetime).await.unwrap();
+    assert_eq!(ids(&live), ["a", "b"]);
+    assert_eq!(live[0].hwid_hash, Some(format!("hwid-{}", live[0].session_id)));
+
+    store.set_tier(key, LicenseType::Pro).await.unwrap();
+    let live = store.live(key, lifetime).await.unwrap();
+    assert!(live.iter().all(|s| s.license_type == LicenseType::Pro));
+
+    let all = store.all(lifetime).await.unwrap();
+    let (_, sessions) = all.iter().find(|(k, _)| k == key).unwrap();
+    assert_eq!(sessions.len(), 2);
+
+    store.remove(key).await.unwrap();
+    assert!(store.live(key, lifetime).await.unwrap().is_empty());
+    assert!(!store.touch(key, "a", lifetime).await.unwrap());
+
+    // stale sessions do not count against the limit
+    let stale = format!("{}-stale", key);
+    store.open(&stale, session("old", 120), 1, 600).await.unwrap();
+    assert_eq!(store.live(&stale, 600).await.unwrap().len(), 1);
+    assert!(store.live(&stale, lifetime).await.unwrap().is_empty());
+    assert_eq!(
+      store.open(&stale, session("new", 0), 1, lifetime).await.unwrap(),
+      None
+    );
+    assert_eq!(ids(&store.live(&stale, 600).await.unwrap()), ["new"]);
+
+    store.open(key, session("gone", 120), 1, 600).await.unwrap();
+    store.gc(lifetime).await.unwrap();
+    assert!(store.live(key, 600).await.unwrap().is_empty());
+    assert_eq!(store.live(&stale, lifetime).await.unwrap().len(), 1);
+    store.remove(&stale).await.unwrap();
+  }
+
+  /// Behaviour every [`TokenStore`] shares, `token` must be unused
+  pub(super) async fn tokens(store: &dyn TokenStore, token: &str) {
+    let now = Utc::now().naive_utc();
+    let value = DownloadToken { version: "1.0".into(), created_at: now };
+
+    assert_eq!(store.get(token).await.unwrap(), None);
+    store.insert(token, value.clone(), 60).await.unwrap();
+    assert_eq!(store.get(token).await.unwrap(), Some(value));
+
+    let old = format!("{}-old", token);
+    let value = DownloadToken {
+      version: "0.9".into(),
+      created_at: now - TimeDelta::seconds(120),
+    };
+    store.insert(&old, value, 600).await.unwrap();
+    store.gc(60).await.unwrap();
+    assert!(store.get(token).await.unwrap().is_some());
+    // Redis expires tokens itself, only after `lifetime` of their insertion
+    store.clear().await.unwrap();
+    assert_eq!(store.get(token).await.unwrap(), None);
+    assert_eq!(store.get(&old).await.unwrap(), None);
+  }
+
+  #[tokio::test]
+  async fn test_memory_sessions() {
+    sessions(&memory::Sessions::default(), "key").await;
+  }
+
+  #[tokio::test]
+  async fn test_memory_tokens() {
+    tokens(&memory::Tokens::default(), "token").await;
+  }
+}
@@ -0,0 +1,335 @@
+//! Sessions of a license key are a sorted set of session ids scored by when
+//! they were last seen, next to a hash with the sessions themselves. Both
+//! are written in one `MULTI`, so replicas never see one without the other.
+//! Download tokens are plain keys expiring with them.
+
+use futures::StreamExt;
+use redis::{AsyncCommands, Client, RedisError, aio::ConnectionManager};
+
+use super::{DownloadToken, Session, SessionStore, Store, TokenStore};
+use crate::{entity::LicenseType, prelude::*};
+
+/// Prefix of every key, so the server can share Redis with others
+pub const PREFIX: &str = "license";
+
+impl From<RedisError> for Error {
+  fn from(e: RedisError) -> Self {
+    Error::Internal(format!("Redis: {}", e))
+  }
+}
+
+fn decode<T: serde::de::DeserializeOwned>(value: &str) -> Result<T> {
+  json::from_str(value).map_err(|e| Error::Internal(e.to_string()))
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> Result<String> {
+  json::to_string(value).map_err(|e| Error::Internal(e.to_string()))
+}
+
+pub async fn connect(url: &str, prefix: &str) -> anyhow::Result<Store> {
+  let client = Client::open(url)?;
+  let conn = ConnectionManager::new(client)
+    .await
+    .context("Failed to connect to Redis")?;
+  info!("Sessions and download tokens are kept in Redis");
+
+  Ok(Store {
+    sessions: Box::new(Sessions { conn: conn.clone(), prefix: prefix.into() }),
+    tokens: Box::new(Tokens { conn, prefix: prefix.into() }),
+  })
+}
+
+pub struct Sessions {
+  conn: ConnectionManager,
+  prefix: String,
+}
+
+impl Sessions {
+  fn seen(&self, key: &str) -> String {
+    format!("{}:seen:{}", self.prefix, key)
+  }
+
+  fn sessions(&self, key: &str) -> String {
+    format!("{}:session:{}", self.prefix, key)
+  }
+
+  /// License keys with sessions, whether live or not
+  async fn keys(&self) -> Result<Vec<String>> {
+    let pattern = self.seen("*");
+    let strip = pattern.len() - 1;
+    let keys: Vec<String> =
+      self.conn.clone().scan_match(&pattern).await?.collect().await;
+    Ok(keys.into_iter().map(|key| key[strip..].to_string()).collect())
+  }
+}
+
+#[async_trait]
+impl SessionStore for Sessions {
+  async fn touch(
+    &self,
+    key: &str,
+    session_id: &str,
+    lifetime: i64,
+  ) -> Result<bool> {
+    let now = Utc::now().timestamp();
+    let (seen,): (Option<f64>,) = redis::pipe()
+      .atomic()
+      // XX never adds the session back if it was dropped meanwhile
+      .cmd("ZADD")
+      .arg(self.seen(key))
+      .arg("XX")
+      .arg(now)
+      .arg(session_id)
+      .ignore()
+      .zscore(self.seen(key), session_id)
+      .expire(self.seen(key), lifetime)
+      .ignore()
+      .expire(self.sessions(key), lifetime)
+      .ignore()
+      .query_async(&mut self.conn.clone())
+      .await?;
+    Ok(seen.is_some())
+  }
+
+  async fn open(
+    &self,
+    key: &str,
+    session: Session,
+    max: usize,
+    lifetime: i64,
+  ) -> Result<Option<usize>> {
+    let mut conn = self.conn.clone();
+    let now = session.last_seen.and_utc().timestamp();
+    let id = session.session_id.clone();
+
+    // add first and check after, so concurrent opens cannot both fit in
+    let (count,): (usize,) = redis::pipe()
+      .atomic()
+      .zrembyscore(self.seen(key), "-inf", Utc::now().timestamp() - lifetime)
+      .ignore()
+      .zadd(self.seen(key), &id, now)
+      .ignore()
+      .hset(self.sessions(key), &id, encode(&session)?)
+      .ignore()
+      .zcard(self.seen(key))
+      .expire(self.seen(key), lifetime)
+      .ignore()
+      .expire(self.sessions(key), lifetime)
+      .ignore()
+      .query_async(&mut conn)
+      .await?;
+
+    if count <= max {
+      return Ok(None);
+    }
+    let _: () = redis::pipe()
+      .atomic()
+      .zrem(self.seen(key), &id)
+      .ignore()
+      .hdel(self.sessions(key), &id)
+      .ignore()
+      .query_async(&mut conn)
+      .await?;
+    Ok(Some(count - 1))
+  }
+
+  async fn live(&self, key: &str, lifetime: i64) -> Result<Vec<Session>> {
+    let mut conn = self.conn.clone();
+    let since = Utc::now().timestamp() - lifetime;
+    let seen: Vec<(String, i64)> = conn
+      .zrangebyscore_withscores(self.seen(key), format!("({}", since), "+inf")
+      .await?;
+    if seen.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let ids: Vec<&str> = seen.iter().map(|(id, _)| id.as_str()).collect();
+    let sessions: Vec<Option<String>> = redis::cmd("HMGET")
+      .arg(self.sessions(key))
+      .arg(&ids)
+      .query_async(&mut conn)
+      .await?;
+
+    let mut live = Vec::with_capacity(seen.len());
+    for ((_, at), session) in seen.iter().zip(sessions) {
+      let Some(session) = session else { continue };
+      let mut session: Session = decode(&session)?;
+      if let Some(at) = chrono::DateTime::from_timestamp(*at, 0) {
+        session.last_seen = at.naive_utc();
+      }
+      live.push(session);
+    }
+    Ok(live)
+  }
+
+  async fn all(&self, lifetime: i64) -> Result<Vec<(String, Vec<Session>)>> {
+    let mut all = Vec::new();
+    for key in self.keys().await? {
+      let sessions = self.live(&key, lifetime).await?;
+      if !sessions.is_empty() {
+        all.push((key, sessions));
+      }
+    }
+    Ok(all)
+  }
+
+  async fn set_tier(&self, key: &str, tier: LicenseType) -> Result<()> {
+    let mut conn = self.conn.clone();
+    let sessions: Vec<(String, String)> =
+      conn.hgetall(self.sessions(key)).await?;
+    if sessions.is_empty() {
+      return Ok(());
+    }
+
+    let mut updated = Vec::with_capacity(sessions.len());
+    for (id, session) in sessions {
+      let mut session: Session = decode(&session)?;
+      session.license_type = tier.clone();
+      updated.push((id, encode(&session)?));
+    }
+    let _: () = redis::cmd("HSET")
+      .arg(self.sessions(key))
+      .arg(&updated)
+      .query_async(&mut conn)
+      .await?;
+    Ok(())
+  }
+
+  async fn remove(&self, key: &str) -> Result<()> {
+    let _: () =
+      self.conn.clone().del(&[self.seen(key), self.sessions(key)]).await?;
+    Ok(())
+  }
+
+  async fn gc(&self, lifetime: i64) -> Result<()> {
+    let mut conn = self.conn.clone();
+    let since = Utc::now().timestamp() - lifetime;
+
+    for key in self.keys().await? {
+      let (ids, stored): (Vec<String>, Vec<String>) = redis::pipe()
+        .atomic()
+        .zrembyscore(self.seen(&key), "-inf", since)
+        .ignore()
+        .zrange(self.seen(&key), 0, -1)
+        .hkeys(self.sessions(&key))
+        .query_async(&mut conn)
+        .await?;
+
+      let stale: Vec<&String> =
+        stored.iter().filter(|id| !ids.contains(id)).collect();
+      if !stale.is_empty() {
+        let _: () = conn.hdel(self.sessions(&key), &stale).await?;
+      }
+    }
+    Ok(())
+  }
+
+  async fn clear(&self) -> Result<()> {
+    for key in self.keys().await? {
+      self.remove(&key).await?;
+    }
+    Ok(())
+  }
+}
+
+pub struct Tokens {
+  conn: ConnectionManager,
+  prefix: String,
+}
+
+impl Tokens {
+  fn token(&self, token: &str) -> String {
+    format!("{}:token:{}", self.prefix, token)
+  }
+}
+
+#[async_trait]
+impl TokenStore for Tokens {
+  async fn insert(
+    &self,
+    token: &str,
+    value: DownloadToken,
+    lifetime: i64,
+  ) -> Result<()> {
+    let value = encode(&value)?;
+    let lifetime = lifetime.max(1) as u64;
+    let _: () =
+      self.conn.clone().set_ex(self.token(token), value, lifetime).await?;
+    Ok(())
+  }
+
+  async fn get(&self, token: &str) -> Result<Option<DownloadToken>> {
+    let value: Option<String> =
+      self.conn.clone().get(self.token(token)).await?;
+    value.as_deref().map(decode).transpose()
+  }
+
+  /// Redis expires them on its own
+  async fn gc(&self, _: i64) -> Result<()> {
+    Ok(())
+  }
+
+  async fn clear(&self) -> Result<()> {
+    let mut conn = self.conn.clone();
+    let keys: Vec<String> =
+      conn.scan_match(self.token("*")).await?.collect().await;
+    if !keys.is_empty() {
+      let _: () = conn.del(&keys).await?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::store::tests;
+
+  /// `TEST_REDIS_URL` and a fresh prefix in it, the tests are skipped
+  /// without it
+  fn setup() -> Option<(String, String)> {
+    let Ok(url) = std::env::var("TEST_REDIS_URL") else {
+      eprintln!("TEST_REDIS_URL is not set, skipping");
+      return None;
+    };
+    Some((url, format!("license-test-{}", uuid::Uuid::new_v4())))
+  }
+
+  #[tokio::test]
+  async fn test_redis_sessions() {
+    let Some((url, prefix)) = setup() else { return };
+    let store = connect(&url, &prefix).await.unwrap();
+    tests::sessions(store.sessions.as_ref(), "key").await;
+  }
+
+  #[tokio::test]
+  async fn test_redis_tokens() {
+    let Some((url, prefix)) = setup() else { return };
+    let store = connect(&url, &prefix).await.unwrap();
+    tests::tokens(store.tokens.as_ref(), "token").await;
+  }
+
+  #[tokio::test]
+  async fn test_replicas_share_sessions() {
+    let Some((url, prefix)) = setup() else { return };
+    let a = connect(&url, &prefix).await.unwrap();
+    let b = connect(&url, &prefix).await.unwrap();
+    let session = Session {
+      session_id: "a".into(),
+      hwid_hash: None,
+      license_type: LicenseType::Pro,
+      last_seen: Utc::now().naive_utc(),
+    };
+
+    assert_eq!(a.sessions.open("key", session, 1, 60).await.unwrap(), None);
+    assert!(b.sessions.touch("key", "a", 60).await.unwrap());
+    b.sessions.remove("key").await.unwrap();
+    assert!(!a.sessions.touch("key", "a", 60).await.unwrap());
+
+    let now = Utc::now().naive_utc();
+    let token = DownloadToken { version: "1.0".into(), created_at: now };
+    a.tokens.insert("token", token.clone(), 60).await.unwrap();
+    assert_eq!(b.tokens.get("token").await.unwrap(), Some(token));
+    b.tokens.clear().await.unwrap();
+  }
+}
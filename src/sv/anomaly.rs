@@ -0,0 +1,349 @@
+use std::{cmp::Reverse, collections::BTreeSet};
+
+use teloxide::utils::html;
+
+use crate::{
+  entity::{key_event, license},
+  prelude::*,
+};
+
+/// Share of the risk score each signal adds once its limit is reached
+const MACHINES_WEIGHT: f64 = 35.0;
+const IPS_WEIGHT: f64 = 25.0;
+const OPENS_WEIGHT: f64 = 20.0;
+const REJECTIONS_WEIGHT: f64 = 20.0;
+
+/// Machines and addresses listed in an alert
+const SHOWN_EVIDENCE: usize = 5;
+
+/// What counts as key sharing, see [`Config::anomaly_policy`]. A limit of 0
+/// ignores its signal.
+///
+/// [`Config::anomaly_policy`]: crate::config::Config::anomaly_policy
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+  /// Events older than this are forgotten
+  pub window: Duration,
+  /// Machines beyond the session limit of the key
+  pub machines: u64,
+  /// Addresses beyond the session limit of the key
+  pub ips: u64,
+  /// Sessions opened, a client keeps one open while it runs
+  pub opens: u64,
+  /// Heartbeats refused by the session limit
+  pub rejections: u64,
+}
+
+/// Sessions opened and refused for one key within the window
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Evidence {
+  pub machines: BTreeSet<String>,
+  pub ips: BTreeSet<String>,
+  pub opens: u64,
+  pub rejections: u64,
+}
+
+impl Evidence {
+  /// Risk from 0 to 100 of a key allowing `max_sessions`
+  pub fn score(&self, max_sessions: u64, policy: &Policy) -> u32 {
+    fn part(value: u64, limit: u64, weight: f64) -> f64 {
+      if limit == 0 {
+        return 0.0;
+      }
+      (value as f64 / limit as f64).min(1.0) * weight
+    }
+
+    let machines = (self.machines.len() as u64).saturating_sub(max_sessions);
+    let ips = (self.ips.len() as u64).saturating_sub(max_sessions);
+    let score = part(machines, policy.machines, MACHINES_WEIGHT)
+      + part(ips, policy.ips, IPS_WEIGHT)
+      + part(self.opens, policy.opens, OPENS_WEIGHT)
+      + part(self.rejections, policy.rejections, REJECTIONS_WEIGHT);
+    score.round() as u32
+  }
+}
+
+/// License whose usage looks shared or resold
+#[derive(Debug, Clone)]
+pub struct Suspect {
+  pub license: license::Model,
+  pub score: u32,
+  pub evidence: Evidence,
+}
+
+impl Suspect {
+  /// HTML alert for admins, `suspended` if the key was blocked for it
+  pub fn summary(&self, window: Duration, suspended: bool) -> String {
+    fn list(values: &BTreeSet<String>) -> String {
+      let mut text = values
+        .iter()
+        .take(SHOWN_EVIDENCE)
+        .map(|value| format!("<code>{}</code>", html::escape(value)))
+        .collect::<Vec<_>>()
+        .join(", ");
+      let more = values.len().saturating_sub(SHOWN_EVIDENCE);
+      if more > 0 {
+        text.push_str(&format!(" and {more} more"));
+      }
+      text
+    }
+
+    let evidence = &self.evidence;
+    let mut text = format!(
+      "{}\n\n\
+      <b>Key:</b> <code>{}</code>\n\
+      <b>User:</b> <code>{}</code>\n\
+      <b>Risk score:</b> {}/100 over the last {} min\n\n\
+      <b>Machines:</b> {} ({} sessions allowed)\n\
+      <b>Addresses:</b> {}\n\
+      <b>Sessions opened:</b> {}\n\
+      <b>Refused by the limit:</b> {}",
+      if suspended {
+        "🔒 <b>License suspended for key sharing</b>"
+      } else {
+        "🚨 <b>Possible key sharing</b>"
+      },
+      self.license.key,
+      self.license.tg_user_id,
+      self.score,
+      window.as_secs() / 60,
+      evidence.machines.len(),
+      self.license.max_sessions,
+      evidence.ips.len(),
+      evidence.opens,
+      evidence.rejections
+    );
+    if !evidence.machines.is_empty() {
+      text.push_str(&format!("\n\n{}", list(&evidence.machines)));
+    }
+    if !evidence.ips.is_empty() {
+      text.push_str(&format!("\n{}", list(&evidence.ips)));
+    }
+    text
+  }
+}
+
+/// Heartbeat history of keys, scored for signs of key sharing
+pub struct Anomaly<'a> {
+  db: &'a DatabaseConnection,
+}
+
+impl<'a> Anomaly<'a> {
+  pub fn new(db: &'a DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  fn since(window: Duration) -> Result<DateTime> {
+    let window = TimeDelta::from_std(window)
+      .map_err(|e| Error::InvalidArgs(e.to_string()))?;
+    Ok(Utc::now().naive_utc() - window)
+  }
+
+  /// Remember a session opened for `key`, or refused if `rejected`
+  pub async fn record(
+    &self,
+    key: &str,
+    machine_id: &str,
+    ip: Option<String>,
+    rejected: bool,
+  ) -> Result<()> {
+    let event = key_event::ActiveModel {
+      key: Set(key.into()),
+      machine_id: Set(machine_id.into()),
+      ip: Set(ip),
+      rejected: Set(rejected),
+      created_at: Set(Utc::now().naive_utc()),
+      ..Default::default()
+    };
+    event.insert(self.db).await?;
+    Ok(())
+  }
+
+  /// Evidence of every key with events within `window`
+  pub async fn evidence(
+    &self,
+    window: Duration,
+  ) -> Result<HashMap<String, Evidence>> {
+    let events = key_event::Entity::find()
+      .filter(key_event::Column::CreatedAt.gte(Self::since(window)?))
+      .all(self.db)
+      .await?;
+
+    let mut keys: HashMap<String, Evidence> = HashMap::new();
+    for event in events {
+      let evidence = keys.entry(event.key).or_default();
+      evidence.machines.insert(event.machine_id);
+      evidence.ips.extend(event.ip);
+      if event.rejected {
+        evidence.rejections += 1;
+      } else {
+        evidence.opens += 1;
+      }
+    }
+    Ok(keys)
+  }
+
+  /// Unblocked licenses scoring at least `min_score`, riskiest first
+  pub async fn suspects(
+    &self,
+    policy: &Policy,
+    min_score: u32,
+  ) -> Result<Vec<Suspect>> {
+    let mut evidence = self.evidence(policy.window).await?;
+    if evidence.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let licenses = license::Entity::find()
+      .filter(license::Column::Key.is_in(evidence.keys().cloned()))
+      .filter(license::Column::IsBlocked.eq(false))
+      .all(self.db)
+      .await?;
+
+    let mut suspects: Vec<Suspect> = licenses
+      .into_iter()
+      .filter_map(|license| {
+        let evidence = evidence.remove(&license.key)?;
+        let score = evidence.score(license.max_sessions as u64, policy);
+        (score >= min_score).then_some(Suspect { license, score, evidence })
+      })
+      .collect();
+    suspects.sort_by_key(|suspect| Reverse(suspect.score));
+    Ok(suspects)
+  }
+
+  /// Forget all events of `key`, e.g. once an admin unblocks it
+  pub async fn forget(db: &impl ConnectionTrait, key: &str) -> Result<()> {
+    key_event::Entity::delete_many()
+      .filter(key_event::Column::Key.eq(key))
+      .exec(db)
+      .await?;
+    Ok(())
+  }
+
+  /// Forget events older than `window`
+  pub async fn prune(&self, window: Duration) -> Result<u64> {
+    let deleted = key_event::Entity::delete_many()
+      .filter(key_event::Column::CreatedAt.lt(Self::since(window)?))
+      .exec(self.db)
+      .await?;
+    Ok(deleted.rows_affected)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+  use crate::{
+    entity::{LicenseType, audit, user},
+    sv,
+  };
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(user::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(license::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(key_event::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  fn policy() -> Policy {
+    Policy {
+      window: Duration::from_secs(3600),
+      machines: 2,
+      ips: 4,
+      opens: 10,
+      rejections: 10,
+    }
+  }
+
+  #[test]
+  fn test_score() {
+    let policy = policy();
+    let mut evidence = Evidence {
+      machines: ["a".into()].into(),
+      ips: ["1.1.1.1".into()].into(),
+      opens: 1,
+      ..Default::default()
+    };
+    assert_eq!(evidence.score(1, &policy), 2);
+
+    // one machine over the limit is half of its signal
+    evidence.machines.insert("b".into());
+    assert_eq!(evidence.score(1, &policy), 20);
+    assert_eq!(evidence.score(2, &policy), 2);
+
+    evidence.machines.extend(["c".into(), "d".into()]);
+    evidence.ips.extend((2..=9).map(|i| format!("1.1.1.{i}")));
+    evidence.opens = 50;
+    evidence.rejections = 50;
+    assert_eq!(evidence.score(1, &policy), 100);
+
+    let ignored = Policy { rejections: 0, ..policy };
+    assert_eq!(evidence.score(1, &ignored), 80);
+  }
+
+  #[tokio::test]
+  async fn test_suspects() {
+    let db = setup_test_db().await;
+    let licenses = sv::License::new(&db);
+    let sv = Anomaly::new(&db);
+
+    let shared = licenses.create(1, LicenseType::Pro, 30).await.unwrap();
+    let normal = licenses.create(2, LicenseType::Pro, 30).await.unwrap();
+    for i in 0..4 {
+      let ip = Some(format!("10.0.0.{i}"));
+      sv.record(&shared.key, &format!("m{i}"), ip, false).await.unwrap();
+      sv.record(&shared.key, "m9", None, true).await.unwrap();
+    }
+    sv.record(&normal.key, "m1", Some("10.0.1.1".into()), false).await.unwrap();
+
+    let evidence = sv.evidence(policy().window).await.unwrap();
+    assert_eq!(evidence[&shared.key].machines.len(), 5);
+    assert_eq!(evidence[&shared.key].ips.len(), 4);
+    assert_eq!(evidence[&shared.key].opens, 4);
+    assert_eq!(evidence[&shared.key].rejections, 4);
+
+    let suspects = sv.suspects(&policy(), 50).await.unwrap();
+    assert_eq!(suspects.len(), 1);
+    assert_eq!(suspects[0].license.key, shared.key);
+    assert_eq!(suspects[0].score, 35 + 19 + 8 + 8);
+    assert!(suspects[0].summary(policy().window, true).contains("m9"));
+
+    // blocked keys are dealt with already
+    licenses.set_blocked(&shared.key, true).await.unwrap();
+    assert!(sv.suspects(&policy(), 50).await.unwrap().is_empty());
+
+    // unblocking clears the evidence, so it is not suspended again
+    licenses.set_blocked(&shared.key, false).await.unwrap();
+    assert!(sv.suspects(&policy(), 50).await.unwrap().is_empty());
+    let evidence = sv.evidence(policy().window).await.unwrap();
+    assert!(evidence.contains_key(&normal.key));
+  }
+
+  #[tokio::test]
+  async fn test_prune() {
+    let db = setup_test_db().await;
+    let sv = Anomaly::new(&db);
+
+    sv.record("key", "m1", None, false).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(sv.prune(Duration::from_secs(60)).await.unwrap(), 0);
+    assert_eq!(sv.prune(Duration::ZERO).await.unwrap(), 1);
+    assert!(sv.evidence(Duration::from_secs(60)).await.unwrap().is_empty());
+  }
+}
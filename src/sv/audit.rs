@@ -0,0 +1,181 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{entity::audit, prelude::*};
+
+/// Who performed a mutation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Actor {
+  /// Admin acting through the bot
+  Admin(i64),
+  /// User acting on their own behalf, e.g. claiming a promo
+  User(i64),
+  /// Background job or automatic reward
+  System(&'static str),
+}
+
+impl fmt::Display for Actor {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Actor::Admin(id) => write!(f, "admin:{}", id),
+      Actor::User(id) => write!(f, "user:{}", id),
+      Actor::System(job) => write!(f, "system:{}", job),
+    }
+  }
+}
+
+/// Fields that differ between two JSON snapshots, as `(field, old, new)`
+pub fn changes(
+  before: Option<&str>,
+  after: Option<&str>,
+) -> Vec<(String, String, String)> {
+  let parse = |raw: Option<&str>| {
+    raw
+      .and_then(|raw| {
+        json::from_str::<json::Map<String, json::Value>>(raw).ok()
+      })
+      .unwrap_or_default()
+  };
+  let (before, after) = (parse(before), parse(after));
+
+  let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+  fields.sort_unstable();
+  fields.dedup();
+
+  let show = |value: Option<&json::Value>| match value {
+    Some(json::Value::String(s)) => s.clone(),
+    Some(value) => value.to_string(),
+    None => "-".into(),
+  };
+
+  fields
+    .into_iter()
+    .filter(|field| before.get(*field) != after.get(*field))
+    .map(|field| {
+      (field.clone(), show(before.get(field)), show(after.get(field)))
+    })
+    .collect()
+}
+
+pub struct Audit<'a> {
+  db: &'a DatabaseConnection,
+}
+
+impl<'a> Audit<'a> {
+  pub fn new(db: &'a DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  /// Append an entry, snapshots are stored as JSON. Mutations pass their
+  /// transaction, so they are never committed without an entry.
+  pub async fn record<T: Serialize>(
+    conn: &impl ConnectionTrait,
+    actor: &Actor,
+    action: &str,
+    target: &str,
+    user_id: Option<i64>,
+    before: Option<&T>,
+    after: Option<&T>,
+  ) -> Result<audit::Model> {
+    let snapshot =
+      |value: Option<&T>| value.and_then(|v| json::to_string(v).ok());
+
+    let entry = audit::ActiveModel {
+      id: NotSet,
+      actor: Set(actor.to_string()),
+      action: Set(action.into()),
+      target: Set(target.into()),
+      user_id: Set(user_id),
+      before: Set(snapshot(before)),
+      after: Set(snapshot(after)),
+      created_at: Set(Utc::now().naive_utc()),
+    };
+    Ok(entry.insert(conn).await?)
+  }
+
+  /// Latest entries, optionally only those of a license/build or a user
+  pub async fn recent(
+    &self,
+    target: Option<&str>,
+    user_id: Option<i64>,
+    limit: u64,
+  ) -> Result<Vec<audit::Model>> {
+    let mut query = audit::Entity::find().order_by_desc(audit::Column::Id);
+    if let Some(target) = target {
+      query = query.filter(audit::Column::Target.eq(target));
+    }
+    if let Some(user_id) = user_id {
+      query = query.filter(audit::Column::UserId.eq(user_id));
+    }
+    Ok(query.limit(limit).all(self.db).await?)
+  }
+
+  /// Entries newer than `id`, oldest first
+  pub async fn since(&self, id: i64, limit: u64) -> Result<Vec<audit::Model>> {
+    let entries = audit::Entity::find()
+      .filter(audit::Column::Id.gt(id))
+      .order_by_asc(audit::Column::Id)
+      .limit(limit)
+      .all(self.db)
+      .await?;
+    Ok(entries)
+  }
+
+  /// Id of the newest entry, 0 if the log is empty
+  pub async fn last_id(&self) -> Result<i64> {
+    let last = audit::Entity::find()
+      .order_by_desc(audit::Column::Id)
+      .one(self.db)
+      .await?;
+    Ok(last.map(|entry| entry.id).unwrap_or(0))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+  use crate::{entity::*, sv};
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(user::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(license::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  #[tokio::test]
+  async fn test_license_mutations_are_audited() {
+    let db = setup_test_db().await;
+    let licenses = sv::License::new(&db).by(Actor::Admin(1));
+
+    let license = licenses.create(42, LicenseType::Trial, 7).await.unwrap();
+    licenses.set_blocked(&license.key, true).await.unwrap();
+
+    let entries =
+      Audit::new(&db).recent(Some(&license.key), None, 10).await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].action, "license.block");
+    assert_eq!(entries[0].actor, "admin:1");
+    assert_eq!(entries[0].user_id, Some(42));
+
+    let changes =
+      changes(entries[0].before.as_deref(), entries[0].after.as_deref());
+    assert_eq!(
+      changes,
+      vec![("is_blocked".into(), "false".into(), "true".into())]
+    );
+  }
+}
@@ -0,0 +1,270 @@
+use sea_orm::sea_query::Expr;
+
+use crate::{
+  entity::{
+    LicenseType,
+    broadcast::{self, Audience, Status},
+    license, user,
+  },
+  prelude::*,
+};
+
+/// Delivery counters of a running broadcast
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Delivery {
+  pub sent: u64,
+  pub blocked: u64,
+  pub failed: u64,
+  /// Last user delivered to
+  pub last_recipient: Option<i64>,
+}
+
+impl From<&broadcast::Model> for Delivery {
+  fn from(model: &broadcast::Model) -> Self {
+    Self {
+      sent: model.sent as u64,
+      blocked: model.blocked as u64,
+      failed: model.failed as u64,
+      last_recipient: model.last_recipient,
+    }
+  }
+}
+
+fn matches(
+  audience: Audience,
+  licenses: &[license::Model],
+  now: DateTime,
+) -> bool {
+  let valid = |l: &&license::Model| !l.is_blocked && l.expires_at > now;
+  match audience {
+    Audience::All => true,
+    Audience::Active => licenses.iter().any(|l| valid(&l)),
+    Audience::Expired => {
+      !licenses.is_empty() && !licenses.iter().any(|l| valid(&l))
+    }
+    Audience::Trial => licenses
+      .iter()
+      .filter(valid)
+      .any(|l| l.license_type == LicenseType::Trial),
+    Audience::Pro => {
+      licenses.iter().filter(valid).any(|l| l.license_type == LicenseType::Pro)
+    }
+  }
+}
+
+pub struct Broadcast<'a> {
+  db: &'a DatabaseConnection,
+}
+
+impl<'a> Broadcast<'a> {
+  pub fn new(db: &'a DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  /// Telegram ids of all users in `audience`
+  pub async fn recipients(&self, audience: Audience) -> Result<Vec<i64>> {
+    let now = Utc::now().naive_utc();
+    let users = user::Entity::find()
+      .order_by_asc(user::Column::TgUserId)
+      .find_with_related(license::Entity)
+      .all(self.db)
+      .await?;
+
+    Ok(
+      users
+        .into_iter()
+        .filter(|(_, licenses)| matches(audience, licenses, now))
+        .map(|(user, _)| user.tg_user_id)
+        .collect(),
+    )
+  }
+
+  /// Recipients of `broadcast` not delivered to yet
+  pub async fn remaining(
+    &self,
+    broadcast: &broadcast::Model,
+  ) -> Result<Vec<i64>> {
+    let mut recipients = self.recipients(broadcast.audience).await?;
+    if let Some(last) = broadcast.last_recipient {
+      recipients.retain(|&user_id| user_id > last);
+    }
+    Ok(recipients)
+  }
+
+  /// Store a pending broadcast, sent only after [`Self::start`]
+  pub async fn create(
+    &self,
+    text: String,
+    audience: Audience,
+    build_version: Option<String>,
+    created_by: i64,
+  ) -> Result<broadcast::Model> {
+    let total = self.recipients(audience).await?.len();
+
+    let broadcast = broadcast::ActiveModel {
+      text: Set(text),
+      audience: Set(audience),
+      build_version: Set(build_version),
+      status: Set(Status::Pending),
+      total: Set(total as i32),
+      sent: Set(0),
+      blocked: Set(0),
+      failed: Set(0),
+      created_by: Set(created_by),
+      created_at: Set(Utc::now().naive_utc()),
+      finished_at: Set(None),
+      ..Default::default()
+    };
+
+    Ok(broadcast.insert(self.db).await?)
+  }
+
+  pub async fn by_id(&self, id: i64) -> Result<Option<broadcast::Model>> {
+    Ok(broadcast::Entity::find_by_id(id).one(self.db).await?)
+  }
+
+  /// Confirmed broadcasts that are not delivered completely, oldest first
+  pub async fn sending(&self) -> Result<Vec<broadcast::Model>> {
+    let broadcasts = broadcast::Entity::find()
+      .filter(broadcast::Column::Status.eq(Status::Sending))
+      .order_by_asc(broadcast::Column::Id)
+      .all(self.db)
+      .await?;
+    Ok(broadcasts)
+  }
+
+  pub async fn recent(&self, limit: u64) -> Result<Vec<broadcast::Model>> {
+    let broadcasts = broadcast::Entity::find()
+      .order_by_desc(broadcast::Column::Id)
+      .limit(limit)
+      .all(self.db)
+      .await?;
+    Ok(broadcasts)
+  }
+
+  /// Move a pending broadcast to `status`, `false` if it is not pending.
+  /// Guards against confirming the same preview twice.
+  async fn transition(&self, id: i64, status: Status) -> Result<bool> {
+    let updated = broadcast::Entity::update_many()
+      .col_expr(broadcast::Column::Status, Expr::value(status))
+      .filter(broadcast::Column::Id.eq(id))
+      .filter(broadcast::Column::Status.eq(Status::Pending))
+      .exec(self.db)
+      .await?;
+    Ok(updated.rows_affected == 1)
+  }
+
+  pub async fn start(&self, id: i64) -> Result<bool> {
+    self.transition(id, Status::Sending).await
+  }
+
+  pub async fn cancel(&self, id: i64) -> Result<bool> {
+    self.transition(id, Status::Cancelled).await
+  }
+
+  /// Persist delivery progress, marking the broadcast done if `finished`
+  pub async fn progress(
+    &self,
+    id: i64,
+    delivery: Delivery,
+    finished: bool,
+  ) -> Result<()> {
+    let broadcast = self
+      .by_id(id)
+      .await?
+      .ok_or(Error::InvalidArgs(format!("Broadcast #{} not found", id)))?;
+
+    let mut model = broadcast::ActiveModel {
+      sent: Set(delivery.sent as i32),
+      blocked: Set(delivery.blocked as i32),
+      failed: Set(delivery.failed as i32),
+      last_recipient: Set(delivery.last_recipient),
+      ..broadcast.into()
+    };
+    if finished {
+      model.status = Set(Status::Done);
+      model.finished_at = Set(Some(Utc::now().naive_utc()));
+    }
+    model.update(self.db).await?;
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+  use crate::{entity::*, sv};
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(user::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(license::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(broadcast::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  #[tokio::test]
+  async fn test_audience_and_confirm_once() {
+    let db = setup_test_db().await;
+    let sv = Broadcast::new(&db);
+
+    sv::User::new(&db).get_or_create(1).await.unwrap();
+    let licenses = sv::License::new(&db);
+    licenses.create(2, LicenseType::Trial, 7).await.unwrap();
+    let expired = licenses.create(3, LicenseType::Pro, 7).await.unwrap();
+    licenses.set_blocked(&expired.key, true).await.unwrap();
+
+    assert_eq!(sv.recipients(Audience::All).await.unwrap(), vec![1, 2, 3]);
+    assert_eq!(sv.recipients(Audience::Trial).await.unwrap(), vec![2]);
+    assert_eq!(sv.recipients(Audience::Expired).await.unwrap(), vec![3]);
+    assert!(sv.recipients(Audience::Pro).await.unwrap().is_empty());
+
+    let broadcast =
+      sv.create("hi".into(), Audience::Active, None, 1).await.unwrap();
+    assert_eq!(broadcast.total, 1);
+    assert!(sv.start(broadcast.id).await.unwrap());
+    assert!(!sv.start(broadcast.id).await.unwrap());
+    assert!(!sv.cancel(broadcast.id).await.unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_resume_after_last_recipient() {
+    let db = setup_test_db().await;
+    let sv = Broadcast::new(&db);
+
+    for user_id in [1, 2, 3] {
+      sv::User::new(&db).get_or_create(user_id).await.unwrap();
+    }
+
+    let broadcast =
+      sv.create("hi".into(), Audience::All, None, 1).await.unwrap();
+    assert!(sv.start(broadcast.id).await.unwrap());
+
+    let delivery =
+      Delivery { sent: 2, last_recipient: Some(2), ..Default::default() };
+    sv.progress(broadcast.id, delivery, false).await.unwrap();
+
+    let sending = sv.sending().await.unwrap();
+    assert_eq!(sending.len(), 1);
+    assert_eq!(Delivery::from(&sending[0]).sent, 2);
+    assert_eq!(sv.remaining(&sending[0]).await.unwrap(), vec![3]);
+
+    sv.progress(broadcast.id, delivery, true).await.unwrap();
+    assert!(sv.sending().await.unwrap().is_empty());
+  }
+}
@@ -1,16 +1,50 @@
 use std::path::Path;
 
+use sea_orm::DatabaseTransaction;
 use tokio::fs;
 
-use crate::{entity::*, prelude::*};
+use crate::{
+  entity::*,
+  prelude::*,
+  sv::{self, audit::Actor},
+};
 
 pub struct Build<'a> {
   db: &'a DatabaseConnection,
+  actor: Actor,
 }
 
 impl<'a> Build<'a> {
   pub fn new(db: &'a DatabaseConnection) -> Self {
-    Self { db }
+    Self { db, actor: Actor::System("server") }
+  }
+
+  /// Attribute following mutations to `actor` in the audit log
+  pub fn by(mut self, actor: Actor) -> Self {
+    self.actor = actor;
+    self
+  }
+
+  /// Record a mutation in its transaction `txn`, a failure rolls it back
+  async fn audit(
+    &self,
+    txn: &DatabaseTransaction,
+    action: &str,
+    before: Option<&build::Model>,
+    after: Option<&build::Model>,
+  ) -> Result<()> {
+    let Some(build) = after.or(before) else { return Ok(()) };
+    sv::Audit::record(
+      txn,
+      &self.actor,
+      action,
+      &build.version,
+      None,
+      before,
+      after,
+    )
+    .await?;
+    Ok(())
   }
 
   #[allow(dead_code)]
@@ -52,7 +86,11 @@ impl<'a> Build<'a> {
       downloads: Set(0),
     };
 
-    Ok(build.insert(self.db).await?)
+    let txn = self.db.begin().await?;
+    let build = build.insert(&txn).await?;
+    self.audit(&txn, "build.publish", None, Some(&build)).await?;
+    txn.commit().await?;
+    Ok(build)
   }
 
   pub async fn increment_downloads(&self, version: &str) -> Result<()> {
@@ -70,31 +108,41 @@ impl<'a> Build<'a> {
   }
 
   pub async fn deactivate(&self, version: &str) -> Result<()> {
+    let txn = self.db.begin().await?;
+
     let build = build::Entity::find()
       .filter(build::Column::Version.eq(version))
-      .one(self.db)
+      .one(&txn)
       .await?
       .ok_or(Error::BuildNotFound)?;
 
-    build::ActiveModel { is_active: Set(false), ..build.into() }
-      .update(self.db)
-      .await?;
+    let updated =
+      build::ActiveModel { is_active: Set(false), ..build.clone().into() }
+        .update(&txn)
+        .await?;
 
+    self.audit(&txn, "build.yank", Some(&build), Some(&updated)).await?;
+    txn.commit().await?;
     Ok(())
   }
 
   /// Reactivate (un-yank) a previously yanked build
   pub async fn activate(&self, version: &str) -> Result<()> {
+    let txn = self.db.begin().await?;
+
     let build = build::Entity::find()
       .filter(build::Column::Version.eq(version))
-      .one(self.db)
+      .one(&txn)
       .await?
       .ok_or(Error::BuildNotFound)?;
 
-    build::ActiveModel { is_active: Set(true), ..build.into() }
-      .update(self.db)
-      .await?;
+    let updated =
+      build::ActiveModel { is_active: Set(true), ..build.clone().into() }
+        .update(&txn)
+        .await?;
 
+    self.audit(&txn, "build.unyank", Some(&build), Some(&updated)).await?;
+    txn.commit().await?;
     Ok(())
   }
 
@@ -125,11 +173,13 @@ impl<'a> Build<'a> {
 
   #[allow(dead_code)]
   pub async fn total_downloads(&self) -> Result<u64> {
-    use sea_orm::sea_query::Expr;
+    use sea_orm::sea_query::{Alias, Expr};
 
+    let total = Expr::col(build::Column::Downloads).sum();
     let result: Option<i64> = build::Entity::find()
       .select_only()
-      .column_as(Expr::col(build::Column::Downloads).sum(), "total")
+      // postgres sums bigints into numerics
+      .column_as(total.cast_as(Alias::new("BIGINT")), "total")
       .into_tuple()
       .one(self.db)
       .await?;
@@ -150,21 +200,25 @@ impl<'a> Build<'a> {
 
   /// Delete a build from database and remove its file from disk
   pub async fn delete(&self, version: &str) -> Result<build::Model> {
+    let txn = self.db.begin().await?;
+
     let build = build::Entity::find()
       .filter(build::Column::Version.eq(version))
-      .one(self.db)
+      .one(&txn)
       .await?
       .ok_or(Error::BuildNotFound)?;
 
+    // Delete from database
+    build::Entity::delete_by_id(build.id).exec(&txn).await?;
+    self.audit(&txn, "build.delete", Some(&build), None).await?;
+    txn.commit().await?;
+
     // Remove file from disk if it exists
     let path = Path::new(&build.file_path);
     if path.exists() {
       fs::remove_file(path).await.ok();
     }
 
-    // Delete from database
-    build::Entity::delete_by_id(build.id).exec(self.db).await?;
-
     Ok(build)
   }
 }
@@ -0,0 +1,356 @@
+use crate::{
+  entity::{
+    LicenseType,
+    campaign::{self, Eligibility},
+    license, promo,
+  },
+  prelude::*,
+  sv::{self, audit::Actor},
+};
+
+/// Lifecycle of a promo, derived from its dates, pause flag and claims
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+  Scheduled,
+  Active,
+  Paused,
+  Exhausted,
+  Ended,
+}
+
+/// Parameters of a new promo
+#[derive(Debug, Clone)]
+pub struct NewCampaign {
+  pub name: String,
+  pub title: String,
+  pub starts_at: DateTime,
+  pub ends_at: DateTime,
+  pub license_type: LicenseType,
+  pub days: u64,
+  pub max_claims: Option<u64>,
+  pub eligibility: Eligibility,
+}
+
+pub struct Campaign<'a> {
+  db: &'a DatabaseConnection,
+}
+
+/// Maximum length of a promo name, it is embedded in callback data
+pub const MAX_NAME_LEN: usize = 32;
+
+fn status_of(model: &campaign::Model, claims: u64, now: DateTime) -> Status {
+  if now > model.ends_at {
+    Status::Ended
+  } else if model.is_paused {
+    Status::Paused
+  } else if model.max_claims.is_some_and(|max| claims >= max as u64) {
+    Status::Exhausted
+  } else if now < model.starts_at {
+    Status::Scheduled
+  } else {
+    Status::Active
+  }
+}
+
+impl<'a> Campaign<'a> {
+  pub fn new(db: &'a DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  pub async fn create(&self, new: NewCampaign) -> Result<campaign::Model> {
+    let valid_name = !new.name.is_empty()
+      && new.name.len() <= MAX_NAME_LEN
+      && new.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+      return Err(Error::InvalidArgs(format!(
+        "Promo name must be 1-{MAX_NAME_LEN} characters of [a-z0-9_]"
+      )));
+    }
+    if new.ends_at <= new.starts_at {
+      return Err(Error::InvalidArgs("Promo must end after it starts".into()));
+    }
+    if new.days == 0 {
+      return Err(Error::InvalidArgs("Promo duration must be positive".into()));
+    }
+    if self.by_name(&new.name).await?.is_some() {
+      return Err(Error::InvalidArgs(format!(
+        "Promo '{}' already exists",
+        new.name
+      )));
+    }
+
+    let campaign = campaign::ActiveModel {
+      name: Set(new.name.to_ascii_lowercase()),
+      title: Set(new.title),
+      starts_at: Set(new.starts_at),
+      ends_at: Set(new.ends_at),
+      license_type: Set(new.license_type),
+      days: Set(new.days as i32),
+      max_claims: Set(new.max_claims.map(|max| max as i32)),
+      eligibility: Set(new.eligibility),
+      is_paused: Set(false),
+      created_at: Set(Utc::now().naive_utc()),
+    };
+
+    Ok(campaign.insert(self.db).await?)
+  }
+
+  /// Promo named `name`, names are stored in lower case
+  pub async fn by_name(&self, name: &str) -> Result<Option<campaign::Model>> {
+    let name = name.to_ascii_lowercase();
+    Ok(campaign::Entity::find_by_id(name).one(self.db).await?)
+  }
+
+  pub async fn claims(&self, name: &str) -> Result<u64> {
+    let count = promo::Entity::find()
+      .filter(promo::Column::PromoName.eq(name))
+      .count(self.db)
+      .await?;
+    Ok(count)
+  }
+
+  /// All promos with their status and claim count, newest first
+  pub async fn all(&self) -> Result<Vec<(campaign::Model, Status, u64)>> {
+    let campaigns = campaign::Entity::find()
+      .order_by_desc(campaign::Column::StartsAt)
+      .all(self.db)
+      .await?;
+
+    let now = Utc::now().naive_utc();
+    let mut all = Vec::with_capacity(campaigns.len());
+    for campaign in campaigns {
+      let claims = self.claims(&campaign.name).await?;
+      let status = status_of(&campaign, claims, now);
+      all.push((campaign, status, claims));
+    }
+    Ok(all)
+  }
+
+  /// Promos that can be claimed right now
+  pub async fn active(&self) -> Result<Vec<campaign::Model>> {
+    let now = Utc::now().naive_utc();
+    let campaigns = campaign::Entity::find()
+      .filter(campaign::Column::IsPaused.eq(false))
+      .filter(campaign::Column::StartsAt.lte(now))
+      .filter(campaign::Column::EndsAt.gte(now))
+      .order_by_asc(campaign::Column::EndsAt)
+      .all(self.db)
+      .await?;
+
+    let mut active = Vec::with_capacity(campaigns.len());
+    for campaign in campaigns {
+      let claims = self.claims(&campaign.name).await?;
+      if status_of(&campaign, claims, now) == Status::Active {
+        active.push(campaign);
+      }
+    }
+    Ok(active)
+  }
+
+  pub async fn set_paused(&self, name: &str, paused: bool) -> Result<()> {
+    let campaign =
+      self.by_name(name).await?.ok_or(Error::Promo(Promo::NotFound))?;
+
+    campaign::ActiveModel { is_paused: Set(paused), ..campaign.into() }
+      .update(self.db)
+      .await?;
+
+    Ok(())
+  }
+
+  /// End the promo now, claims made so far are kept
+  pub async fn end(&self, name: &str) -> Result<()> {
+    let campaign =
+      self.by_name(name).await?.ok_or(Error::Promo(Promo::NotFound))?;
+
+    let now = Utc::now().naive_utc();
+    campaign::ActiveModel {
+      ends_at: Set(now.min(campaign.ends_at)),
+      starts_at: Set(now.min(campaign.starts_at)),
+      ..campaign.into()
+    }
+    .update(self.db)
+    .await?;
+
+    Ok(())
+  }
+
+  pub async fn claim(
+    &self,
+    tg_user_id: i64,
+    name: &str,
+  ) -> Result<license::Model> {
+    let name = &name.to_ascii_lowercase();
+
+    // ensure exists
+    sv::User::new(self.db).get_or_create(tg_user_id).await?;
+
+    // the claim row is inserted together with the checks under a lock of
+    // the promo row, so concurrent claims are counted one after another.
+    // SQLite has no row locks, but serialises writers anyway.
+    let txn = self.db.begin().await?;
+
+    let campaign = campaign::Entity::find_by_id(name)
+      .lock_exclusive()
+      .one(&txn)
+      .await?
+      .ok_or(Error::Promo(Promo::NotFound))?;
+
+    let claimed = promo::Entity::find_by_id((tg_user_id, name.to_string()))
+      .one(&txn)
+      .await?;
+    if claimed.is_some() {
+      return Err(Error::Promo(Promo::Claimed));
+    }
+
+    let claims = promo::Entity::find()
+      .filter(promo::Column::PromoName.eq(name))
+      .count(&txn)
+      .await?;
+    match status_of(&campaign, claims, Utc::now().naive_utc()) {
+      Status::Active => {}
+      Status::Exhausted => return Err(Error::Promo(Promo::Exhausted)),
+      _ => return Err(Error::Promo(Promo::Inactive)),
+    }
+
+    if campaign.eligibility == Eligibility::NewUsers {
+      let licenses = license::Entity::find()
+        .filter(license::Column::TgUserId.eq(tg_user_id))
+        .count(&txn)
+        .await?;
+      if licenses > 0 {
+        return Err(Error::Promo(Promo::NotEligible));
+      }
+    }
+
+    promo::ActiveModel {
+      tg_user_id: Set(tg_user_id),
+      promo_name: Set(name.to_string()),
+      claimed_at: Set(Utc::now().naive_utc()),
+    }
+    .insert(&txn)
+    .await?;
+
+    // committed together with the claim, a failed grant leaves no claim
+    let license = sv::License::new(self.db)
+      .by(Actor::User(tg_user_id))
+      .create_in(&txn, tg_user_id, campaign.license_type, campaign.days as u64)
+      .await?;
+
+    txn.commit().await?;
+    Ok(license)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+  use crate::entity::*;
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(user::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(license::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(campaign::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(promo::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  fn new_campaign(name: &str) -> NewCampaign {
+    let now = Utc::now().naive_utc();
+    NewCampaign {
+      name: name.into(),
+      title: "Free Trial".into(),
+      starts_at: now - TimeDelta::hours(1),
+      ends_at: now + TimeDelta::days(7),
+      license_type: LicenseType::Trial,
+      days: 7,
+      max_claims: Some(1),
+      eligibility: Eligibility::All,
+    }
+  }
+
+  #[tokio::test]
+  async fn test_claim_respects_cap_and_pause() {
+    let db = setup_test_db().await;
+    let sv = Campaign::new(&db);
+
+    sv.create(new_campaign("xmas")).await.unwrap();
+    assert_eq!(sv.active().await.unwrap().len(), 1);
+
+    sv.set_paused("xmas", true).await.unwrap();
+    assert!(matches!(
+      sv.claim(1, "xmas").await,
+      Err(Error::Promo(Promo::Inactive))
+    ));
+
+    sv.set_paused("Xmas", false).await.unwrap();
+    let license = sv.claim(1, "XMAS").await.unwrap();
+    assert_eq!(license.license_type, LicenseType::Trial);
+
+    assert!(matches!(
+      sv.claim(1, "xmas").await,
+      Err(Error::Promo(Promo::Claimed))
+    ));
+    assert!(matches!(
+      sv.claim(2, "xmas").await,
+      Err(Error::Promo(Promo::Exhausted))
+    ));
+    assert!(sv.active().await.unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn test_claim_new_users_only() {
+    let db = setup_test_db().await;
+    let sv = Campaign::new(&db);
+
+    sv.create(NewCampaign {
+      max_claims: None,
+      eligibility: Eligibility::NewUsers,
+      ..new_campaign("welcome")
+    })
+    .await
+    .unwrap();
+
+    sv::License::new(&db).create(1, LicenseType::Pro, 30).await.unwrap();
+
+    assert!(matches!(
+      sv.claim(1, "welcome").await,
+      Err(Error::Promo(Promo::NotEligible))
+    ));
+    assert!(sv.claim(2, "welcome").await.is_ok());
+  }
+
+  #[tokio::test]
+  async fn test_failed_grant_keeps_claim_open() {
+    let db = setup_test_db().await;
+    let sv = Campaign::new(&db);
+    sv.create(new_campaign("xmas")).await.unwrap();
+
+    let stmt =
+      sea_orm::sea_query::Table::drop().table(audit::Entity).to_owned();
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+    assert!(sv.claim(1, "xmas").await.is_err());
+
+    let stmt =
+      Schema::new(DbBackend::Sqlite).create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+    assert!(sv.claim(1, "xmas").await.is_ok());
+  }
+}
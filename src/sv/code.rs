@@ -0,0 +1,327 @@
+use sea_orm::sea_query::Expr;
+use uuid::Uuid;
+
+use crate::{
+  entity::{LicenseType, code, license, redemption},
+  prelude::*,
+  sv::{self, audit::Actor},
+};
+
+/// Unambiguous characters only, no `0/O` or `1/I`
+const ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const GROUPS: usize = 3;
+const GROUP_LEN: usize = 4;
+
+/// Maximum number of codes generated at once
+pub const MAX_BATCH: u64 = 1000;
+
+/// Parameters of a code batch
+#[derive(Debug, Clone)]
+pub struct NewBatch {
+  pub count: u64,
+  pub license_type: LicenseType,
+  pub days: u64,
+  pub max_redemptions: u64,
+  pub expires_at: Option<DateTime>,
+}
+
+/// Outcome of a successful redemption
+#[derive(Debug, Clone)]
+pub struct Redeemed {
+  pub license: license::Model,
+  /// An existing license was extended instead of creating a new one
+  pub extended: bool,
+}
+
+/// Redemption summary of a single batch
+#[derive(Debug, Clone)]
+pub struct BatchSummary {
+  pub batch: String,
+  pub license_type: LicenseType,
+  pub days: i32,
+  pub codes: u64,
+  pub redemptions: u64,
+  pub created_at: DateTime,
+}
+
+fn generate_code() -> String {
+  let mut bytes = [0u8; GROUPS * GROUP_LEN];
+  getrandom::fill(&mut bytes).expect("OS random source is unavailable");
+  bytes
+    .chunks(GROUP_LEN)
+    .map(|group| {
+      // 256 is a multiple of 32, so `% 32` keeps the distribution uniform
+      group.iter().map(|b| ALPHABET[*b as usize % 32] as char).collect()
+    })
+    .collect::<Vec<String>>()
+    .join("-")
+}
+
+/// Canonical `XXXX-XXXX-XXXX` form of user input, `None` if malformed
+pub fn normalize(input: &str) -> Option<String> {
+  let raw: Vec<u8> = input
+    .bytes()
+    .filter(|b| !matches!(b, b'-' | b' '))
+    .map(|b| b.to_ascii_uppercase())
+    .collect();
+
+  if raw.len() != GROUPS * GROUP_LEN
+    || !raw.iter().all(|b| ALPHABET.contains(b))
+  {
+    return None;
+  }
+
+  let groups: Vec<&str> = raw
+    .chunks(GROUP_LEN)
+    .map(|group| std::str::from_utf8(group).expect("ascii"))
+    .collect();
+  Some(groups.join("-"))
+}
+
+/// Render codes as CSV for resellers
+pub fn to_csv(codes: &[code::Model]) -> String {
+  let mut csv = String::from(
+    "code,license_type,days,max_redemptions,redemptions,expires_at,batch\n",
+  );
+  for code in codes {
+    csv.push_str(&format!(
+      "{},{:?},{},{},{},{},{}\n",
+      code.code,
+      code.license_type,
+      code.days,
+      code.max_redemptions,
+      code.redemptions,
+      code.expires_at.map(|at| at.to_string()).unwrap_or_default(),
+      code.batch
+    ));
+  }
+  csv
+}
+
+pub struct Code<'a> {
+  db: &'a DatabaseConnection,
+}
+
+impl<'a> Code<'a> {
+  pub fn new(db: &'a DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  /// Generate a batch of unique codes
+  pub async fn generate(&self, new: NewBatch) -> Result<Vec<code::Model>> {
+    if !(1..=MAX_BATCH).contains(&new.count) {
+      return Err(Error::InvalidArgs(format!(
+        "Code count must be between 1 and {MAX_BATCH}"
+      )));
+    }
+    if new.days == 0 || new.max_redemptions == 0 {
+      return Err(Error::InvalidArgs(
+        "Duration and max redemptions must be positive".into(),
+      ));
+    }
+
+    let now = Utc::now().naive_utc();
+    let batch = Uuid::new_v4().simple().to_string()[..8].to_string();
+
+    let codes: Vec<code::Model> = (0..new.count)
+      .map(|_| code::Model {
+        code: generate_code(),
+        license_type: new.license_type.clone(),
+        days: new.days as i32,
+        max_redemptions: new.max_redemptions as i32,
+        redemptions: 0,
+        expires_at: new.expires_at,
+        batch: batch.clone(),
+        created_at: now,
+      })
+      .collect();
+
+    code::Entity::insert_many(
+      codes.iter().cloned().map(code::ActiveModel::from),
+    )
+    .exec(self.db)
+    .await?;
+
+    Ok(codes)
+  }
+
+  /// All codes, or only the codes of `batch`
+  pub async fn export(&self, batch: Option<&str>) -> Result<Vec<code::Model>> {
+    let mut query = code::Entity::find()
+      .order_by_asc(code::Column::CreatedAt)
+      .order_by_asc(code::Column::Code);
+    if let Some(batch) = batch {
+      query = query.filter(code::Column::Batch.eq(batch));
+    }
+    Ok(query.all(self.db).await?)
+  }
+
+  /// Batches with their redemption counts, newest first
+  pub async fn batches(&self) -> Result<Vec<BatchSummary>> {
+    let codes = code::Entity::find()
+      .order_by_desc(code::Column::CreatedAt)
+      .all(self.db)
+      .await?;
+
+    let mut batches: Vec<BatchSummary> = Vec::new();
+    for code in codes {
+      match batches.iter_mut().find(|b| b.batch == code.batch) {
+        Some(summary) => {
+          summary.codes += 1;
+          summary.redemptions += code.redemptions as u64;
+        }
+        None => batches.push(BatchSummary {
+          batch: code.batch,
+          license_type: code.license_type,
+          days: code.days,
+          codes: 1,
+          redemptions: code.redemptions as u64,
+          created_at: code.created_at,
+        }),
+      }
+    }
+    Ok(batches)
+  }
+
+  /// Redeem `input` for the user: extends the user's latest license of the
+  /// same type, or creates a new one
+  pub async fn redeem(&self, tg_user_id: i64, input: &str) -> Result<Redeemed> {
+    let code = normalize(input).ok_or(Error::CodeInvalid)?;
+
+    // ensure exists
+    sv::User::new(self.db).get_or_create(tg_user_id).await?;
+
+    let txn = self.db.begin().await?;
+
+    let model = code::Entity::find_by_id(&code)
+      .one(&txn)
+      .await?
+      .ok_or(Error::CodeInvalid)?;
+
+    if model.expires_at.is_some_and(|at| at < Utc::now().naive_utc()) {
+      return Err(Error::CodeInvalid);
+    }
+
+    let redeemed = redemption::Entity::find_by_id((code.clone(), tg_user_id))
+      .one(&txn)
+      .await?;
+    if redeemed.is_some() {
+      return Err(Error::CodeUsed);
+    }
+
+    // conditional increment, so concurrent redemptions cannot exceed the cap
+    let updated = code::Entity::update_many()
+      .col_expr(
+        code::Column::Redemptions,
+        Expr::col(code::Column::Redemptions).add(1),
+      )
+      .filter(code::Column::Code.eq(&code))
+      .filter(
+        Expr::col(code::Column::Redemptions)
+          .lt(Expr::col(code::Column::MaxRedemptions)),
+      )
+      .exec(&txn)
+      .await?;
+    if updated.rows_affected == 0 {
+      return Err(Error::CodeUsed);
+    }
+
+    redemption::ActiveModel {
+      code: Set(code.clone()),
+      tg_user_id: Set(tg_user_id),
+      redeemed_at: Set(Utc::now().naive_utc()),
+    }
+    .insert(&txn)
+    .await?;
+
+    // committed together with the redemption, a failed grant keeps the code
+    let (license, extended) = sv::License::new(self.db)
+      .by(Actor::User(tg_user_id))
+      .grant_in(&txn, tg_user_id, model.license_type, model.days as u64)
+      .await?;
+
+    txn.commit().await?;
+    Ok(Redeemed { license, extended })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+  use crate::entity::*;
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(user::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(license::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(code::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(redemption::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  #[test]
+  fn test_normalize() {
+    let code = generate_code();
+    assert_eq!(normalize(&code.to_lowercase().replace('-', " ")), Some(code));
+    assert_eq!(normalize("ABCD-EFGH-IJK0"), None);
+  }
+
+  #[test]
+  fn test_generate_code_uses_whole_alphabet() {
+    let codes: Vec<_> = (0..2000).map(|_| generate_code()).collect();
+    for i in (0..GROUPS * (GROUP_LEN + 1) - 1).filter(|i| i % 5 != 4) {
+      let symbols: HashSet<u8> =
+        codes.iter().map(|c| c.as_bytes()[i]).collect();
+      assert_eq!(symbols.len(), ALPHABET.len(), "position {i}");
+    }
+  }
+
+  #[tokio::test]
+  async fn test_redeem_creates_then_extends() {
+    let db = setup_test_db().await;
+    let sv = Code::new(&db);
+
+    let codes = sv
+      .generate(NewBatch {
+        count: 2,
+        license_type: LicenseType::Pro,
+        days: 30,
+        max_redemptions: 1,
+        expires_at: None,
+      })
+      .await
+      .unwrap();
+
+    let first = sv.redeem(1, &codes[0].code).await.unwrap();
+    assert!(!first.extended);
+
+    assert!(matches!(sv.redeem(2, &codes[0].code).await, Err(Error::CodeUsed)));
+    assert!(matches!(
+      sv.redeem(1, "AAAA-AAAA-AAAA").await,
+      Err(Error::CodeInvalid)
+    ));
+
+    let second = sv.redeem(1, &codes[1].code).await.unwrap();
+    assert!(second.extended);
+    assert_eq!(second.license.key, first.license.key);
+    assert!(second.license.expires_at > first.license.expires_at);
+  }
+}
@@ -0,0 +1,292 @@
+use sea_orm::{Condition, sea_query::OnConflict};
+
+use crate::{entity::*, prelude::*, sv};
+
+/// Single leaderboard row, either live or from a weekly snapshot
+#[derive(Debug, Clone)]
+pub struct Entry {
+  pub rank: u32,
+  pub tg_user_id: i64,
+  pub display_name: Option<String>,
+  pub weekly_xp: u64,
+}
+
+impl From<leaderboard::Model> for Entry {
+  fn from(model: leaderboard::Model) -> Self {
+    Self {
+      rank: model.rank as u32,
+      tg_user_id: model.tg_user_id,
+      display_name: model.display_name,
+      weekly_xp: model.weekly_xp as u64,
+    }
+  }
+}
+
+pub struct Leaderboard<'a> {
+  db: &'a DatabaseConnection,
+}
+
+impl<'a> Leaderboard<'a> {
+  pub fn new(db: &'a DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  /// Live ranking of the current week
+  pub async fn current(&self, limit: u64) -> Result<Vec<Entry>> {
+    let rows = stats::Entity::find()
+      .filter(stats::Column::WeeklyXp.gt(0))
+      .order_by_desc(stats::Column::WeeklyXp)
+      .order_by_asc(stats::Column::TgUserId)
+      .find_also_related(user::Entity)
+      .limit(limit)
+      .all(self.db)
+      .await?;
+
+    let entries = rows
+      .into_iter()
+      .enumerate()
+      .map(|(i, (stats, user))| Entry {
+        rank: i as u32 + 1,
+        tg_user_id: stats.tg_user_id,
+        display_name: user.and_then(|u| u.display_name),
+        weekly_xp: stats.weekly_xp as u64,
+      })
+      .collect();
+
+    Ok(entries)
+  }
+
+  /// Current week rank of the user, `None` if no XP earned yet.
+  /// Ties are ranked like [`Self::current`] lists them, by user id.
+  pub async fn rank_of(&self, tg_user_id: i64) -> Result<Option<u32>> {
+    let Some(stats) =
+      stats::Entity::find_by_id(tg_user_id).one(self.db).await?
+    else {
+      return Ok(None);
+    };
+
+    if stats.weekly_xp <= 0 {
+      return Ok(None);
+    }
+
+    let ahead = stats::Entity::find()
+      .filter(
+        Condition::any().add(stats::Column::WeeklyXp.gt(stats.weekly_xp)).add(
+          stats::Column::WeeklyXp
+            .eq(stats.weekly_xp)
+            .and(stats::Column::TgUserId.lt(tg_user_id)),
+        ),
+      )
+      .count(self.db)
+      .await?;
+
+    Ok(Some(ahead as u32 + 1))
+  }
+
+  /// Top of the most recently snapshotted week
+  pub async fn last_week(
+    &self,
+    limit: u64,
+  ) -> Result<Option<(DateTime, Vec<Entry>)>> {
+    let Some(latest) = leaderboard::Entity::find()
+      .order_by_desc(leaderboard::Column::WeekStart)
+      .one(self.db)
+      .await?
+    else {
+      return Ok(None);
+    };
+
+    let rows = leaderboard::Entity::find()
+      .filter(leaderboard::Column::WeekStart.eq(latest.week_start))
+      .order_by_asc(leaderboard::Column::Rank)
+      .limit(limit)
+      .all(self.db)
+      .await?;
+
+    Ok(Some((latest.week_start, rows.into_iter().map(Entry::from).collect())))
+  }
+
+  /// Start of the most recently closed week, `None` before the first close
+  pub async fn last_closed(&self) -> Result<Option<DateTime>> {
+    let latest = week_close::Entity::find()
+      .order_by_desc(week_close::Column::WeekStart)
+      .one(self.db)
+      .await?;
+    Ok(latest.map(|close| close.week_start))
+  }
+
+  /// Record the week started at `week_start` as closed without touching XP
+  pub async fn mark_closed(&self, week_start: DateTime) -> Result<bool> {
+    Self::insert_close(self.db, week_start).await
+  }
+
+  /// Snapshot weekly XP of the week started at `week_start` and reset it.
+  /// Both happen in one transaction with the close marker, so a week is
+  /// never lost or doubled; `None` if the week was already closed.
+  pub async fn close_week(
+    &self,
+    week_start: DateTime,
+  ) -> Result<Option<Vec<Entry>>> {
+    let txn = self.db.begin().await?;
+
+    if !Self::insert_close(&txn, week_start).await? {
+      return Ok(None);
+    }
+
+    let rows = stats::Entity::find()
+      .filter(stats::Column::WeeklyXp.gt(0))
+      .order_by_desc(stats::Column::WeeklyXp)
+      .order_by_asc(stats::Column::TgUserId)
+      .find_also_related(user::Entity)
+      .all(&txn)
+      .await?;
+
+    let now = Utc::now().naive_utc();
+    let models: Vec<_> = rows
+      .into_iter()
+      .enumerate()
+      .map(|(i, (stats, user))| leaderboard::ActiveModel {
+        id: NotSet,
+        week_start: Set(week_start),
+        tg_user_id: Set(stats.tg_user_id),
+        rank: Set(i as i32 + 1),
+        weekly_xp: Set(stats.weekly_xp),
+        display_name: Set(user.and_then(|u| u.display_name)),
+        created_at: Set(now),
+      })
+      .collect();
+
+    if !models.is_empty() {
+      leaderboard::Entity::insert_many(models).exec(&txn).await?;
+    }
+
+    sv::Stats::reset_weekly_xp(&txn).await?;
+    txn.commit().await?;
+
+    let entries = leaderboard::Entity::find()
+      .filter(leaderboard::Column::WeekStart.eq(week_start))
+      .order_by_asc(leaderboard::Column::Rank)
+      .all(self.db)
+      .await?;
+
+    Ok(Some(entries.into_iter().map(Entry::from).collect()))
+  }
+
+  async fn insert_close(
+    db: &impl ConnectionTrait,
+    week_start: DateTime,
+  ) -> Result<bool> {
+    let close = week_close::ActiveModel {
+      week_start: Set(week_start),
+      closed_at: Set(Utc::now().naive_utc()),
+    };
+    let inserted = week_close::Entity::insert(close)
+      .on_conflict(
+        OnConflict::column(week_close::Column::WeekStart)
+          .do_nothing()
+          .to_owned(),
+      )
+      .exec_without_returning(db)
+      .await?;
+    Ok(inserted > 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(user::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(stats::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(leaderboard::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(week_close::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  async fn set_weekly_xp(db: &DatabaseConnection, tg_user_id: i64, xp: i64) {
+    let stats = sv::Stats::new(db).get_or_create(tg_user_id).await.unwrap();
+    stats::ActiveModel { weekly_xp: Set(xp), ..stats.into() }
+      .update(db)
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_close_week_snapshots_and_resets() {
+    let db = setup_test_db().await;
+
+    set_weekly_xp(&db, 1, 100).await;
+    set_weekly_xp(&db, 2, 300).await;
+    set_weekly_xp(&db, 3, 0).await;
+    sv::User::new(&db)
+      .set_display_name(2, Some("winner".into()))
+      .await
+      .unwrap();
+
+    let sv = Leaderboard::new(&db);
+    assert_eq!(sv.rank_of(1).await.unwrap(), Some(2));
+
+    let week_start = Utc::now().naive_utc();
+    let winners = sv.close_week(week_start).await.unwrap().unwrap();
+
+    assert_eq!(winners.len(), 2);
+    assert_eq!(winners[0].tg_user_id, 2);
+    assert_eq!(winners[0].display_name.as_deref(), Some("winner"));
+    assert_eq!(winners[1].rank, 2);
+
+    assert!(sv.current(10).await.unwrap().is_empty());
+    assert_eq!(sv.rank_of(1).await.unwrap(), None);
+
+    let (week, last) = sv.last_week(1).await.unwrap().unwrap();
+    assert_eq!(week, week_start);
+    assert_eq!(last.len(), 1);
+    assert_eq!(last[0].weekly_xp, 300);
+  }
+
+  #[tokio::test]
+  async fn test_close_week_runs_once() {
+    let db = setup_test_db().await;
+    let sv = Leaderboard::new(&db);
+    let week_start = Utc::now().naive_utc();
+
+    assert_eq!(sv.last_closed().await.unwrap(), None);
+    assert!(sv.close_week(week_start).await.unwrap().unwrap().is_empty());
+    assert_eq!(sv.last_closed().await.unwrap(), Some(week_start));
+
+    set_weekly_xp(&db, 1, 100).await;
+    assert!(sv.close_week(week_start).await.unwrap().is_none());
+    assert!(!sv.mark_closed(week_start).await.unwrap());
+    assert_eq!(sv.rank_of(1).await.unwrap(), Some(1));
+  }
+
+  #[tokio::test]
+  async fn test_rank_of_matches_current_on_ties() {
+    let db = setup_test_db().await;
+
+    set_weekly_xp(&db, 7, 50).await;
+    set_weekly_xp(&db, 3, 50).await;
+    set_weekly_xp(&db, 5, 80).await;
+
+    let sv = Leaderboard::new(&db);
+    for entry in sv.current(10).await.unwrap() {
+      assert_eq!(sv.rank_of(entry.tg_user_id).await.unwrap(), Some(entry.rank));
+    }
+    assert_eq!(sv.rank_of(3).await.unwrap(), Some(2));
+    assert_eq!(sv.rank_of(7).await.unwrap(), Some(3));
+  }
+}
@@ -0,0 +1,130 @@
+use sea_orm::{Condition, sea_query::OnConflict};
+
+use crate::{entity::lease, prelude::*};
+
+/// Leases in the database, so replicas sharing it agree on who runs what.
+/// Expiry follows the clocks of the replicas, keep them in sync.
+pub struct Lease<'a> {
+  db: &'a DatabaseConnection,
+}
+
+impl<'a> Lease<'a> {
+  pub fn new(db: &'a DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  /// Take `name` for `ttl` unless another holder has it, renews it if
+  /// `holder` already does. Returns whether `holder` has it now.
+  pub async fn acquire(
+    &self,
+    name: &str,
+    holder: &str,
+    ttl: Duration,
+  ) -> Result<bool> {
+    use sea_orm::sea_query::Expr;
+
+    let now = Utc::now().naive_utc();
+    let expires_at = now
+      + TimeDelta::from_std(ttl)
+        .map_err(|e| Error::InvalidArgs(e.to_string()))?;
+
+    let updated = lease::Entity::update_many()
+      .col_expr(lease::Column::Holder, Expr::value(holder))
+      .col_expr(lease::Column::ExpiresAt, Expr::value(expires_at))
+      .filter(lease::Column::Name.eq(name))
+      .filter(
+        Condition::any()
+          .add(lease::Column::Holder.eq(holder))
+          .add(lease::Column::ExpiresAt.lt(now)),
+      )
+      .exec(self.db)
+      .await?;
+    if updated.rows_affected > 0 {
+      return Ok(true);
+    }
+
+    let lease = lease::ActiveModel {
+      name: Set(name.into()),
+      holder: Set(holder.into()),
+      expires_at: Set(expires_at),
+    };
+    let inserted = lease::Entity::insert(lease)
+      .on_conflict(
+        OnConflict::column(lease::Column::Name).do_nothing().to_owned(),
+      )
+      .exec_without_returning(self.db)
+      .await?;
+    Ok(inserted > 0)
+  }
+
+  /// Give `name` up early if `holder` has it
+  pub async fn release(&self, name: &str, holder: &str) -> Result<()> {
+    lease::Entity::delete_many()
+      .filter(lease::Column::Name.eq(name))
+      .filter(lease::Column::Holder.eq(holder))
+      .exec(self.db)
+      .await?;
+    Ok(())
+  }
+
+  pub async fn holder(&self, name: &str) -> Result<Option<lease::Model>> {
+    let now = Utc::now().naive_utc();
+    let lease = lease::Entity::find_by_id(name)
+      .filter(lease::Column::ExpiresAt.gt(now))
+      .one(self.db)
+      .await?;
+    Ok(lease)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(lease::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  #[tokio::test]
+  async fn test_acquire_and_release() {
+    let db = setup_test_db().await;
+    let sv = Lease::new(&db);
+    let ttl = Duration::from_secs(30);
+
+    assert!(sv.acquire("leader", "a", ttl).await.unwrap());
+    assert!(!sv.acquire("leader", "b", ttl).await.unwrap());
+    // renewal by the holder
+    assert!(sv.acquire("leader", "a", ttl).await.unwrap());
+    assert!(sv.acquire("backup", "b", ttl).await.unwrap());
+    assert_eq!(sv.holder("leader").await.unwrap().unwrap().holder, "a");
+
+    // releasing a lease of someone else does nothing
+    sv.release("leader", "b").await.unwrap();
+    assert!(!sv.acquire("leader", "b", ttl).await.unwrap());
+
+    sv.release("leader", "a").await.unwrap();
+    assert!(sv.holder("leader").await.unwrap().is_none());
+    assert!(sv.acquire("leader", "b", ttl).await.unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_expired_lease_is_taken_over() {
+    let db = setup_test_db().await;
+    let sv = Lease::new(&db);
+
+    assert!(sv.acquire("leader", "a", Duration::ZERO).await.unwrap());
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(sv.holder("leader").await.unwrap().is_none());
+    assert!(sv.acquire("leader", "b", Duration::from_secs(30)).await.unwrap());
+    assert!(!sv.acquire("leader", "a", Duration::from_secs(30)).await.unwrap());
+  }
+}
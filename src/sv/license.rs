@@ -1,18 +1,48 @@
+use sea_orm::DatabaseTransaction;
 use uuid::Uuid;
 
 pub use crate::prelude::*;
 use crate::{
-  entity::{LicenseType, license, promo},
-  sv,
+  entity::{LicenseType, license},
+  sv::{self, audit::Actor},
 };
 
 pub struct License<'a> {
   db: &'a DatabaseConnection,
+  actor: Actor,
 }
 
 impl<'a> License<'a> {
   pub fn new(db: &'a DatabaseConnection) -> Self {
-    Self { db }
+    Self { db, actor: Actor::System("server") }
+  }
+
+  /// Attribute following mutations to `actor` in the audit log
+  pub fn by(mut self, actor: Actor) -> Self {
+    self.actor = actor;
+    self
+  }
+
+  /// Record a mutation in its transaction `txn`, a failure rolls it back
+  async fn audit(
+    &self,
+    txn: &DatabaseTransaction,
+    action: &str,
+    before: Option<&license::Model>,
+    after: Option<&license::Model>,
+  ) -> Result<()> {
+    let Some(license) = after.or(before) else { return Ok(()) };
+    sv::Audit::record(
+      txn,
+      &self.actor,
+      action,
+      &license.key,
+      Some(license.tg_user_id),
+      before,
+      after,
+    )
+    .await?;
+    Ok(())
   }
 
   pub async fn create(
@@ -23,6 +53,21 @@ impl<'a> License<'a> {
   ) -> Result<license::Model> {
     sv::User::new(self.db).get_or_create(tg_user_id).await?;
 
+    let txn = self.db.begin().await?;
+    let license = self.create_in(&txn, tg_user_id, ty, days).await?;
+    txn.commit().await?;
+    Ok(license)
+  }
+
+  /// [`Self::create`] as part of the caller's transaction `txn`,
+  /// the user must already exist
+  pub async fn create_in(
+    &self,
+    txn: &DatabaseTransaction,
+    tg_user_id: i64,
+    ty: LicenseType,
+    days: u64,
+  ) -> Result<license::Model> {
     let now = Utc::now().naive_utc();
     let expires_at = now + Duration::from_hours(24 * days);
     let key = Uuid::new_v4();
@@ -37,7 +82,9 @@ impl<'a> License<'a> {
       max_sessions: Set(1), // TODO: based on buy
     };
 
-    Ok(license.insert(self.db).await?)
+    let license = license.insert(txn).await?;
+    self.audit(txn, "license.create", None, Some(&license)).await?;
+    Ok(license)
   }
 
   pub async fn by_key(&self, key: &str) -> Result<Option<license::Model>> {
@@ -60,6 +107,14 @@ impl<'a> License<'a> {
     Ok(query.all(self.db).await?)
   }
 
+  pub async fn all(&self) -> Result<Vec<license::Model>> {
+    let licenses = license::Entity::find()
+      .order_by_asc(license::Column::CreatedAt)
+      .all(self.db)
+      .await?;
+    Ok(licenses)
+  }
+
   pub async fn validate(&self, key: &str) -> Result<license::Model> {
     let license = license::Entity::find_by_id(key)
       .one(self.db)
@@ -89,37 +144,141 @@ impl<'a> License<'a> {
     let delta = TimeDelta::from_std(duration).unwrap_or(TimeDelta::zero());
     let new_exp = Utc::now().naive_utc() + delta;
 
-    license::ActiveModel {
+    let updated = license::ActiveModel {
       expires_at: Set(new_exp),
       is_blocked: Set(false),
-      ..license.into()
+      ..license.clone().into()
     }
     .update(&txn)
     .await?;
 
+    self
+      .audit(&txn, "license.set_expiry", Some(&license), Some(&updated))
+      .await?;
     txn.commit().await?;
     Ok(new_exp)
   }
 
-  pub async fn set_blocked(&self, key: &str, blocked: bool) -> Result<()> {
+  /// Add `days` on top of the current expiry, counting from now if the
+  /// license has already expired
+  pub async fn extend(&self, key: &str, days: u64) -> Result<license::Model> {
+    let txn = self.db.begin().await?;
+    let updated = self.extend_in(&txn, key, days).await?;
+    txn.commit().await?;
+    Ok(updated)
+  }
+
+  /// [`Self::extend`] as part of the caller's transaction `txn`
+  pub async fn extend_in(
+    &self,
+    txn: &DatabaseTransaction,
+    key: &str,
+    days: u64,
+  ) -> Result<license::Model> {
     let license = license::Entity::find_by_id(key)
-      .one(self.db)
+      .one(txn)
       .await?
       .ok_or(Error::LicenseNotFound)?;
 
-    license::ActiveModel { is_blocked: Set(blocked), ..license.into() }
-      .update(self.db)
+    let from = license.expires_at.max(Utc::now().naive_utc());
+    let expires_at = from + Duration::from_hours(24 * days);
+
+    let updated = license::ActiveModel {
+      expires_at: Set(expires_at),
+      ..license.clone().into()
+    }
+    .update(txn)
+    .await?;
+
+    self.audit(txn, "license.extend", Some(&license), Some(&updated)).await?;
+    Ok(updated)
+  }
+
+  /// Extend the user's latest unblocked license of type `ty` by `days`,
+  /// or create a new one. Returns `true` if an existing license was extended.
+  pub async fn grant(
+    &self,
+    tg_user_id: i64,
+    ty: LicenseType,
+    days: u64,
+  ) -> Result<(license::Model, bool)> {
+    sv::User::new(self.db).get_or_create(tg_user_id).await?;
+
+    let txn = self.db.begin().await?;
+    let granted = self.grant_in(&txn, tg_user_id, ty, days).await?;
+    txn.commit().await?;
+    Ok(granted)
+  }
+
+  /// [`Self::grant`] as part of the caller's transaction `txn`,
+  /// the user must already exist
+  pub async fn grant_in(
+    &self,
+    txn: &DatabaseTransaction,
+    tg_user_id: i64,
+    ty: LicenseType,
+    days: u64,
+  ) -> Result<(license::Model, bool)> {
+    let current = license::Entity::find()
+      .filter(license::Column::TgUserId.eq(tg_user_id))
+      .filter(license::Column::IsBlocked.eq(false))
+      .filter(license::Column::LicenseType.eq(ty))
+      .order_by_desc(license::Column::ExpiresAt)
+      .one(txn)
       .await?;
 
+    match current {
+      Some(license) => {
+        Ok((self.extend_in(txn, &license.key, days).await?, true))
+      }
+      None => Ok((self.create_in(txn, tg_user_id, ty, days).await?, false)),
+    }
+  }
+
+  pub async fn set_blocked(&self, key: &str, blocked: bool) -> Result<()> {
+    let txn = self.db.begin().await?;
+
+    let license = license::Entity::find_by_id(key)
+      .one(&txn)
+      .await?
+      .ok_or(Error::LicenseNotFound)?;
+
+    let updated = license::ActiveModel {
+      is_blocked: Set(blocked),
+      ..license.clone().into()
+    }
+    .update(&txn)
+    .await?;
+
+    if !blocked {
+      // the key was cleared, its old events must not suspend it again
+      sv::Anomaly::forget(&txn, key).await?;
+    }
+
+    let action = if blocked { "license.block" } else { "license.unblock" };
+    self.audit(&txn, action, Some(&license), Some(&updated)).await?;
+    txn.commit().await?;
     Ok(())
   }
 
-  pub fn is_promo_active(&self) -> bool {
-    let now = Utc::now();
-    // TODO: configurable promo periods
-    let start = Utc.with_ymd_and_hms(2025, 12, 14, 13, 0, 0).unwrap();
-    let end = Utc.with_ymd_and_hms(2025, 12, 21, 23, 59, 59).unwrap();
-    now >= start && now <= end
+  pub async fn set_type(&self, key: &str, ty: LicenseType) -> Result<()> {
+    let txn = self.db.begin().await?;
+
+    let license = license::Entity::find_by_id(key)
+      .one(&txn)
+      .await?
+      .ok_or(Error::LicenseNotFound)?;
+
+    let updated =
+      license::ActiveModel { license_type: Set(ty), ..license.clone().into() }
+        .update(&txn)
+        .await?;
+
+    self
+      .audit(&txn, "license.set_tier", Some(&license), Some(&updated))
+      .await?;
+    txn.commit().await?;
+    Ok(())
   }
 
   #[allow(dead_code)]
@@ -138,41 +297,6 @@ impl<'a> License<'a> {
       .await?;
     Ok(count)
   }
-
-  pub async fn claim_promo(
-    &self,
-    tg_user_id: i64,
-    promo_name: &str,
-  ) -> Result<license::Model> {
-    if !self.is_promo_active() {
-      return Err(Error::Promo(Promo::Inactive));
-    }
-
-    // ensure exists
-    sv::User::new(self.db).get_or_create(tg_user_id).await?;
-
-    let existing =
-      promo::Entity::find_by_id((tg_user_id, promo_name.to_string()))
-        .one(self.db)
-        .await?;
-
-    if existing.is_some() {
-      return Err(Error::Promo(Promo::Claimed));
-    }
-
-    let license = self.create(tg_user_id, LicenseType::Trial, 7).await?;
-    let now = Utc::now().naive_utc();
-
-    promo::ActiveModel {
-      tg_user_id: Set(tg_user_id),
-      promo_name: Set(promo_name.to_string()),
-      claimed_at: Set(now),
-    }
-    .insert(self.db)
-    .await?;
-
-    Ok(license)
-  }
 }
 
 #[cfg(test)]
@@ -193,6 +317,9 @@ mod tests {
     let stmt = schema.create_table_from_entity(license::Entity);
     db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
 
+    let stmt = schema.create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
     let stmt = schema.create_table_from_entity(promo::Entity);
     db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
 
@@ -252,4 +379,21 @@ mod tests {
 
     assert!(new_exp > old_exp);
   }
+
+  #[tokio::test]
+  async fn test_unaudited_mutation_is_rolled_back() {
+    let db = setup_test_db().await;
+    let sv = License::new(&db);
+
+    let license = sv.create(12345, LicenseType::Trial, 30).await.unwrap();
+
+    let stmt =
+      sea_orm::sea_query::Table::drop().table(audit::Entity).to_owned();
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    assert!(sv.set_blocked(&license.key, true).await.is_err());
+    assert!(sv.validate(&license.key).await.is_ok());
+    assert!(sv.create(1, LicenseType::Pro, 30).await.is_err());
+    assert_eq!(sv.count().await.unwrap(), 1);
+  }
 }
@@ -1,11 +1,31 @@
+pub mod anomaly;
+pub mod audit;
+pub mod broadcast;
 pub mod build;
+pub mod campaign;
+pub mod code;
+pub mod leaderboard;
+pub mod lease;
 pub mod license;
+pub mod referral;
+pub mod staff;
 pub mod stats;
 pub mod steam;
+pub mod transfer;
 pub mod user;
 
+pub use anomaly::Anomaly;
+pub use audit::Audit;
+pub use broadcast::Broadcast;
 pub use build::Build;
+pub use campaign::Campaign;
+pub use code::Code;
+pub use leaderboard::Leaderboard;
+pub use lease::Lease;
 pub use license::License;
+pub use referral::Referral;
+pub use staff::Staff;
 pub use stats::Stats;
 pub use steam::Steam;
+pub use transfer::Transfer;
 pub use user::User;
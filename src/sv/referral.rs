@@ -0,0 +1,206 @@
+use sea_orm::sea_query::Expr;
+
+use crate::{
+  entity::{LicenseType, license, referral, user},
+  prelude::*,
+  sv::{self, audit::Actor},
+};
+
+/// Deep link payload prefix, `/start ref_<id>`
+pub const PAYLOAD_PREFIX: &str = "ref_";
+
+/// Referral totals of a single referrer
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+  pub referrer_id: i64,
+  pub referrals: u64,
+  pub rewarded: u64,
+  pub earned_days: u64,
+}
+
+/// Reward granted to a referrer for a referee's first paid order
+#[derive(Debug, Clone)]
+pub struct Reward {
+  pub referrer_id: i64,
+  pub license: license::Model,
+}
+
+/// Referrer id from a `/start` payload like `ref_12345`
+pub fn parse_payload(payload: &str) -> Option<i64> {
+  payload.trim().strip_prefix(PAYLOAD_PREFIX)?.parse().ok()
+}
+
+pub struct Referral<'a> {
+  db: &'a DatabaseConnection,
+}
+
+impl<'a> Referral<'a> {
+  pub fn new(db: &'a DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  /// Link a new user to their referrer. Self-referrals, unknown referrers
+  /// and users that were already referred are ignored with `false`.
+  pub async fn record(
+    &self,
+    referrer_id: i64,
+    referee_id: i64,
+  ) -> Result<bool> {
+    if referrer_id == referee_id {
+      return Ok(false);
+    }
+    if user::Entity::find_by_id(referrer_id).one(self.db).await?.is_none() {
+      return Ok(false);
+    }
+    if referral::Entity::find_by_id(referee_id).one(self.db).await?.is_some() {
+      return Ok(false);
+    }
+
+    sv::User::new(self.db).get_or_create(referee_id).await?;
+
+    referral::ActiveModel {
+      referee_id: Set(referee_id),
+      referrer_id: Set(referrer_id),
+      reward_days: Set(0),
+      created_at: Set(Utc::now().naive_utc()),
+      rewarded_at: Set(None),
+    }
+    .insert(self.db)
+    .await?;
+
+    Ok(true)
+  }
+
+  /// Reward the referrer of `referee_id` with `days` of Pro, once.
+  /// Call after every paid order, only the first one grants a reward.
+  /// A failed grant leaves the referral unrewarded, so the next order
+  /// retries it.
+  pub async fn reward(
+    &self,
+    referee_id: i64,
+    days: u64,
+  ) -> Result<Option<Reward>> {
+    if days == 0 {
+      return Ok(None);
+    }
+
+    let Some(referral) =
+      referral::Entity::find_by_id(referee_id).one(self.db).await?
+    else {
+      return Ok(None);
+    };
+
+    // conditional update, so concurrent orders cannot reward twice. The
+    // grant commits with it, a failed one leaves the referral unrewarded
+    let txn = self.db.begin().await?;
+    let updated = referral::Entity::update_many()
+      .col_expr(
+        referral::Column::RewardedAt,
+        Expr::value(Some(Utc::now().naive_utc())),
+      )
+      .col_expr(referral::Column::RewardDays, Expr::value(days as i32))
+      .filter(referral::Column::RefereeId.eq(referee_id))
+      .filter(referral::Column::RewardedAt.is_null())
+      .exec(&txn)
+      .await?;
+    if updated.rows_affected == 0 {
+      return Ok(None);
+    }
+
+    let (license, _) = sv::License::new(self.db)
+      .by(Actor::System("referral"))
+      .grant_in(&txn, referral.referrer_id, LicenseType::Pro, days)
+      .await?;
+    txn.commit().await?;
+
+    Ok(Some(Reward { referrer_id: referral.referrer_id, license }))
+  }
+
+  /// Totals of referrals made by `referrer_id`
+  pub async fn summary(&self, referrer_id: i64) -> Result<Summary> {
+    let referrals = referral::Entity::find()
+      .filter(referral::Column::ReferrerId.eq(referrer_id))
+      .all(self.db)
+      .await?;
+
+    Ok(Self::summarize(referrer_id, &referrals))
+  }
+
+  /// Totals of all referrers, most referrals first
+  pub async fn report(&self) -> Result<Vec<Summary>> {
+    let referrals = referral::Entity::find()
+      .order_by_asc(referral::Column::ReferrerId)
+      .all(self.db)
+      .await?;
+
+    let mut report: Vec<Summary> = referrals
+      .chunk_by(|a, b| a.referrer_id == b.referrer_id)
+      .map(|group| Self::summarize(group[0].referrer_id, group))
+      .collect();
+    report.sort_by_key(|s| (std::cmp::Reverse(s.referrals), s.referrer_id));
+    Ok(report)
+  }
+
+  fn summarize(referrer_id: i64, referrals: &[referral::Model]) -> Summary {
+    Summary {
+      referrer_id,
+      referrals: referrals.len() as u64,
+      rewarded: referrals.iter().filter(|r| r.rewarded_at.is_some()).count()
+        as u64,
+      earned_days: referrals.iter().map(|r| r.reward_days as u64).sum(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+  use crate::entity::*;
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(user::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(license::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(referral::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  #[tokio::test]
+  async fn test_reward_once() {
+    let db = setup_test_db().await;
+    let sv = Referral::new(&db);
+
+    sv::User::new(&db).get_or_create(1).await.unwrap();
+    assert!(!sv.record(1, 1).await.unwrap());
+    assert!(sv.record(1, 2).await.unwrap());
+    assert!(!sv.record(3, 2).await.unwrap());
+
+    let reward = sv.reward(2, 7).await.unwrap().unwrap();
+    assert_eq!(reward.referrer_id, 1);
+    assert_eq!(reward.license.license_type, LicenseType::Pro);
+    assert!(sv.reward(2, 7).await.unwrap().is_none());
+
+    let summary = sv.summary(1).await.unwrap();
+    assert_eq!((summary.referrals, summary.earned_days), (1, 7));
+  }
+
+  #[test]
+  fn test_parse_payload() {
+    assert_eq!(parse_payload("ref_42"), Some(42));
+    assert_eq!(parse_payload("promo_42"), None);
+  }
+}
@@ -0,0 +1,153 @@
+use sea_orm::DatabaseTransaction;
+
+use crate::{
+  entity::staff::{self, Role},
+  prelude::*,
+  sv::{self, audit::Actor},
+};
+
+pub struct Staff<'a> {
+  db: &'a DatabaseConnection,
+}
+
+impl<'a> Staff<'a> {
+  pub fn new(db: &'a DatabaseConnection) -> Self {
+    Self { db }
+  }
+
+  pub async fn role_of(&self, tg_user_id: i64) -> Result<Option<Role>> {
+    let member = staff::Entity::find_by_id(tg_user_id).one(self.db).await?;
+    Ok(member.map(|m| m.role))
+  }
+
+  pub async fn all(&self) -> Result<Vec<staff::Model>> {
+    let staff = staff::Entity::find()
+      .order_by_asc(staff::Column::GrantedAt)
+      .all(self.db)
+      .await?;
+    Ok(staff)
+  }
+
+  /// Give `tg_user_id` a role, replacing the previous one
+  pub async fn grant(
+    &self,
+    tg_user_id: i64,
+    role: Role,
+    granted_by: i64,
+  ) -> Result<staff::Model> {
+    let txn = self.db.begin().await?;
+    let before = staff::Entity::find_by_id(tg_user_id).one(&txn).await?;
+
+    let member = staff::ActiveModel {
+      tg_user_id: Set(tg_user_id),
+      role: Set(role),
+      granted_by: Set(Some(granted_by)),
+      granted_at: Set(Utc::now().naive_utc()),
+    };
+    let member = match before {
+      Some(_) => member.update(&txn).await?,
+      None => member.insert(&txn).await?,
+    };
+
+    self
+      .audit(
+        &txn,
+        granted_by,
+        "role.grant",
+        tg_user_id,
+        before.as_ref(),
+        Some(&member),
+      )
+      .await?;
+    txn.commit().await?;
+    Ok(member)
+  }
+
+  /// Remove the role of `tg_user_id`, `false` if they had none
+  pub async fn revoke(&self, tg_user_id: i64, revoked_by: i64) -> Result<bool> {
+    let txn = self.db.begin().await?;
+    let Some(member) = staff::Entity::find_by_id(tg_user_id).one(&txn).await?
+    else {
+      return Ok(false);
+    };
+
+    staff::Entity::delete_by_id(tg_user_id).exec(&txn).await?;
+    self
+      .audit(&txn, revoked_by, "role.revoke", tg_user_id, Some(&member), None)
+      .await?;
+    txn.commit().await?;
+    Ok(true)
+  }
+
+  /// Record a change in its transaction `txn`, a failure rolls it back
+  async fn audit(
+    &self,
+    txn: &DatabaseTransaction,
+    admin_id: i64,
+    action: &str,
+    tg_user_id: i64,
+    before: Option<&staff::Model>,
+    after: Option<&staff::Model>,
+  ) -> Result<()> {
+    sv::Audit::record(
+      txn,
+      &Actor::Admin(admin_id),
+      action,
+      &format!("staff:{}", tg_user_id),
+      Some(tg_user_id),
+      before,
+      after,
+    )
+    .await?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+  use crate::entity::{audit, staff::Permission};
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(staff::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  #[tokio::test]
+  async fn test_grant_and_revoke() {
+    let db = setup_test_db().await;
+    let sv = Staff::new(&db);
+
+    sv.grant(2, Role::Support, 1).await.unwrap();
+    sv.grant(2, Role::Release, 1).await.unwrap();
+    assert_eq!(sv.role_of(2).await.unwrap(), Some(Role::Release));
+
+    assert!(sv.revoke(2, 1).await.unwrap());
+    assert!(!sv.revoke(2, 1).await.unwrap());
+    assert_eq!(sv.role_of(2).await.unwrap(), None);
+
+    let log = sv::Audit::new(&db).recent(None, Some(2), 10).await.unwrap();
+    assert_eq!(log.len(), 3);
+  }
+
+  #[test]
+  fn test_permission_matrix() {
+    assert!(Role::Owner.can(Permission::Backup));
+    assert!(!Role::Admin.can(Permission::ManageRoles));
+    assert!(Role::Support.can(Permission::ExtendLicense));
+    assert!(!Role::Support.can(Permission::ManageLicenses));
+    assert!(Role::Release.can(Permission::ManageBuilds));
+    assert!(!Role::Release.can(Permission::ViewUsers));
+  }
+}
@@ -100,7 +100,13 @@ impl<'a> Stats<'a> {
     Ok(stats.insert(self.db).await?)
   }
 
-  pub async fn process_metric(&self, raw_base64: &str) -> Result<()> {
+  /// Apply a metric event submitted by an authenticated session of `key`.
+  /// The payload must belong to the same license the session was opened for.
+  pub async fn process_metric(
+    &self,
+    key: &str,
+    raw_base64: &str,
+  ) -> Result<()> {
     let compressed = base64::prelude::BASE64_STANDARD
       .decode(raw_base64)
       .map_err(|_| Error::InvalidArgs("Invalid base64".into()))?;
@@ -114,6 +120,10 @@ impl<'a> Stats<'a> {
     let payload: MetricPayload = json::from_str(&json_str)
       .map_err(|e| Error::InvalidArgs(format!("Invalid JSON: {}", e)))?;
 
+    if payload.license_key != key {
+      return Err(Error::InvalidArgs("License key mismatch".into()));
+    }
+
     let license = sv::License::new(self.db)
       .by_key(&payload.license_key)
       .await?
@@ -186,7 +196,8 @@ impl<'a> Stats<'a> {
       meta,
     })
   }
-  pub async fn reset_weekly_xp(db: &DatabaseConnection) -> Result<()> {
+
+  pub async fn reset_weekly_xp(db: &impl ConnectionTrait) -> Result<()> {
     use sea_orm::sea_query::Expr;
 
     stats::Entity::update_many()
@@ -199,22 +210,38 @@ impl<'a> Stats<'a> {
 
   #[allow(dead_code)]
   pub async fn aggregate(&self) -> Result<AggregatedStats> {
-    use sea_orm::sea_query::Expr;
+    use sea_orm::sea_query::{Alias, Expr};
+
+    // postgres sums bigints into numerics
+    let bigint = || Alias::new("BIGINT");
 
     type StatsRow = (Option<i64>, Option<i64>, Option<i64>, Option<f64>);
     let result: Option<StatsRow> = stats::Entity::find()
       .select_only()
-      .column_as(Expr::col(stats::Column::TotalXp).sum(), "total_xp")
-      .column_as(Expr::col(stats::Column::WeeklyXp).sum(), "weekly_xp")
-      .column_as(Expr::col(stats::Column::DropsCount).sum(), "drops")
+      .column_as(
+        Expr::col(stats::Column::TotalXp).sum().cast_as(bigint()),
+        "total_xp",
+      )
+      .column_as(
+        Expr::col(stats::Column::WeeklyXp).sum().cast_as(bigint()),
+        "weekly_xp",
+      )
+      .column_as(
+        Expr::col(stats::Column::DropsCount).sum().cast_as(bigint()),
+        "drops",
+      )
       .column_as(Expr::col(stats::Column::RuntimeHours).sum(), "runtime")
       .into_tuple()
       .one(self.db)
       .await?;
 
-    let active_instances: Option<i64> = stats::Entity::find()
+    // the sum is NULL without any stats
+    let active_instances: Option<Option<i64>> = stats::Entity::find()
       .select_only()
-      .column_as(Expr::col(stats::Column::Instances).sum(), "instances")
+      .column_as(
+        Expr::col(stats::Column::Instances).sum().cast_as(bigint()),
+        "instances",
+      )
       .into_tuple()
       .one(self.db)
       .await?;
@@ -224,7 +251,7 @@ impl<'a> Stats<'a> {
       weekly_xp: result.and_then(|r| r.1).unwrap_or(0) as u64,
       total_drops: result.and_then(|r| r.2).unwrap_or(0) as u64,
       total_runtime_hours: result.and_then(|r| r.3).unwrap_or(0.0),
-      active_instances: active_instances.unwrap_or(0) as u32,
+      active_instances: active_instances.flatten().unwrap_or(0) as u32,
     })
   }
 }
@@ -238,3 +265,78 @@ pub struct AggregatedStats {
   pub total_runtime_hours: f64,
   pub active_instances: u32,
 }
+
+#[cfg(test)]
+mod tests {
+  use std::io::Write;
+
+  use flate2::{Compression, write::GzEncoder};
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(user::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(license::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(stats::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  fn encode(payload: json::Value) -> String {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(payload.to_string().as_bytes()).unwrap();
+    base64::prelude::BASE64_STANDARD.encode(encoder.finish().unwrap())
+  }
+
+  #[tokio::test]
+  async fn test_process_metric() {
+    let db = setup_test_db().await;
+    let license =
+      sv::License::new(&db).create(12345, LicenseType::Pro, 30).await.unwrap();
+
+    let raw = encode(json!({
+      "type": "shutdown",
+      "license_key": license.key,
+      "data": { "uptime": 7200.0 }
+    }));
+
+    let sv = Stats::new(&db);
+    sv.process_metric(&license.key, &raw).await.unwrap();
+
+    let stats = sv.display_stats(12345).await.unwrap();
+    assert_eq!(stats.runtime_hours, 2.0);
+  }
+
+  #[tokio::test]
+  async fn test_process_metric_foreign_key() {
+    let db = setup_test_db().await;
+    let sv = sv::License::new(&db);
+
+    let own = sv.create(1, LicenseType::Pro, 30).await.unwrap();
+    let victim = sv.create(2, LicenseType::Pro, 30).await.unwrap();
+
+    let raw = encode(json!({
+      "type": "shutdown",
+      "license_key": victim.key,
+      "data": { "uptime": 7200.0 }
+    }));
+
+    assert!(matches!(
+      Stats::new(&db).process_metric(&own.key, &raw).await,
+      Err(Error::InvalidArgs(_))
+    ));
+  }
+}
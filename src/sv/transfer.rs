@@ -0,0 +1,590 @@
+use std::{collections::HashSet, str::FromStr};
+
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  entity::{LicenseType, license, user},
+  prelude::*,
+  sv::{self, audit::Actor},
+};
+
+/// Dates are exported like this, imports also take ISO 8601 and plain dates
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Longest importable key, the length of generated UUID keys. Admin
+/// buttons carry keys in callback data Telegram limits to 64 bytes.
+const MAX_KEY_LEN: usize = 36;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  Json,
+  Csv,
+}
+
+impl Format {
+  /// Guess the format of an uploaded file from its extension
+  pub fn of_file(name: &str) -> Option<Self> {
+    let (_, ext) = name.rsplit_once('.')?;
+    ext.parse().ok()
+  }
+
+  pub fn extension(self) -> &'static str {
+    match self {
+      Format::Json => "json",
+      Format::Csv => "csv",
+    }
+  }
+
+  pub fn content_type(self) -> &'static str {
+    match self {
+      Format::Json => "application/json",
+      Format::Csv => "text/csv",
+    }
+  }
+}
+
+impl FromStr for Format {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s.to_ascii_lowercase().as_str() {
+      "json" => Ok(Format::Json),
+      "csv" => Ok(Format::Csv),
+      _ => Err(Error::InvalidArgs(format!(
+        "Unknown format '{}', use json or csv",
+        s
+      ))),
+    }
+  }
+}
+
+/// What to do with an imported key that already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+  /// Keep the existing license
+  Skip,
+  /// Replace owner, tier, expiry, sessions and block state
+  Overwrite,
+  /// Add the time the imported license has left on top of the existing one
+  Extend,
+}
+
+impl FromStr for Strategy {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s.to_ascii_lowercase().as_str() {
+      "skip" => Ok(Strategy::Skip),
+      "overwrite" => Ok(Strategy::Overwrite),
+      "extend" => Ok(Strategy::Extend),
+      _ => Err(Error::InvalidArgs(format!(
+        "Unknown strategy '{}', use skip, overwrite or extend",
+        s
+      ))),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+  Licenses,
+  Users,
+}
+
+impl Table {
+  pub fn name(self) -> &'static str {
+    match self {
+      Table::Licenses => "licenses",
+      Table::Users => "users",
+    }
+  }
+}
+
+impl FromStr for Table {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self> {
+    match s.to_ascii_lowercase().as_str() {
+      "licenses" => Ok(Table::Licenses),
+      "users" => Ok(Table::Users),
+      _ => Err(Error::InvalidArgs(format!(
+        "Unknown table '{}', use licenses or users",
+        s
+      ))),
+    }
+  }
+}
+
+/// License as it is exported and imported, exports can be imported as is
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LicenseRow {
+  pub key: String,
+  /// Telegram id of the owner, created as a user if unknown
+  pub owner: i64,
+  /// `trial` or `pro`
+  pub tier: String,
+  pub expires_at: String,
+  /// 1 if not given
+  #[serde(default)]
+  pub max_sessions: Option<i32>,
+  #[serde(default)]
+  pub blocked: Option<bool>,
+  /// Time of the import if not given
+  #[serde(default)]
+  pub created_at: Option<String>,
+}
+
+impl From<license::Model> for LicenseRow {
+  fn from(license: license::Model) -> Self {
+    let tier = match license.license_type {
+      LicenseType::Trial => "trial",
+      LicenseType::Pro => "pro",
+    };
+    Self {
+      key: license.key,
+      owner: license.tg_user_id,
+      tier: tier.into(),
+      expires_at: license.expires_at.format(DATE_FORMAT).to_string(),
+      max_sessions: Some(license.max_sessions),
+      blocked: Some(license.is_blocked),
+      created_at: Some(license.created_at.format(DATE_FORMAT).to_string()),
+    }
+  }
+}
+
+fn parse_date(input: &str) -> Option<DateTime> {
+  let input = input.trim();
+  DateTime::parse_from_str(input, DATE_FORMAT)
+    .or_else(|_| DateTime::parse_from_str(input, "%Y-%m-%dT%H:%M:%S%.f"))
+    .ok()
+    .or_else(|| {
+      chrono::DateTime::parse_from_rfc3339(input).ok().map(|at| at.naive_utc())
+    })
+    .or_else(|| {
+      NaiveDate::parse_from_str(input, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.and_time(NaiveTime::MIN))
+    })
+}
+
+/// Validated [`LicenseRow`]
+struct Import {
+  key: String,
+  owner: i64,
+  tier: LicenseType,
+  expires_at: DateTime,
+  max_sessions: i32,
+  blocked: bool,
+  created_at: Option<DateTime>,
+}
+
+impl LicenseRow {
+  fn validate(self) -> std::result::Result<Import, String> {
+    let key = self.key.trim().to_string();
+    let valid_key =
+      key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if key.is_empty() || key.len() > MAX_KEY_LEN || !valid_key {
+      return Err(format!("invalid key '{}'", self.key));
+    }
+    if self.owner <= 0 {
+      return Err(format!("invalid owner {}", self.owner));
+    }
+    let tier = match self.tier.trim().to_ascii_lowercase().as_str() {
+      "trial" => LicenseType::Trial,
+      "pro" => LicenseType::Pro,
+      _ => return Err(format!("unknown tier '{}'", self.tier)),
+    };
+    let expires_at = parse_date(&self.expires_at)
+      .ok_or_else(|| format!("invalid expiry '{}'", self.expires_at))?;
+    let max_sessions = self.max_sessions.unwrap_or(1);
+    if max_sessions < 1 {
+      return Err(format!("invalid max sessions {}", max_sessions));
+    }
+    let created_at = match self.created_at.as_deref() {
+      Some(raw) if !raw.trim().is_empty() => Some(
+        parse_date(raw).ok_or_else(|| format!("invalid creation '{}'", raw))?,
+      ),
+      _ => None,
+    };
+
+    Ok(Import {
+      key,
+      owner: self.owner,
+      tier,
+      expires_at,
+      max_sessions,
+      blocked: self.blocked.unwrap_or(false),
+      created_at,
+    })
+  }
+}
+
+/// Validate all rows, any error rejects the whole file
+fn parse(
+  format: Format,
+  data: &[u8],
+) -> std::result::Result<Vec<Import>, Vec<String>> {
+  let rows: Vec<std::result::Result<LicenseRow, String>> = match format {
+    Format::Json => match json::from_slice::<Vec<LicenseRow>>(data) {
+      Ok(rows) => rows.into_iter().map(Ok).collect(),
+      Err(e) => return Err(vec![format!("Invalid JSON: {}", e)]),
+    },
+    Format::Csv => csv::ReaderBuilder::new()
+      .trim(csv::Trim::All)
+      .from_reader(data)
+      .deserialize()
+      .map(|row| row.map_err(|e| e.to_string()))
+      .collect(),
+  };
+  if rows.is_empty() {
+    return Err(vec!["No rows to import".into()]);
+  }
+
+  let mut keys = HashSet::new();
+  let mut imports = Vec::new();
+  let mut errors = Vec::new();
+  for (i, row) in rows.into_iter().enumerate() {
+    match row.and_then(LicenseRow::validate) {
+      Ok(import) if !keys.insert(import.key.clone()) => {
+        errors.push(format!("Row {}: duplicate key {}", i + 1, import.key))
+      }
+      Ok(import) => imports.push(import),
+      Err(e) => errors.push(format!("Row {}: {}", i + 1, e)),
+    }
+  }
+
+  if errors.is_empty() { Ok(imports) } else { Err(errors) }
+}
+
+fn encode<T: Serialize>(format: Format, rows: &[T]) -> Result<Vec<u8>> {
+  let internal = |e: &dyn std::fmt::Display| Error::Internal(e.to_string());
+  match format {
+    Format::Json => json::to_vec_pretty(rows).map_err(|e| internal(&e)),
+    Format::Csv => {
+      let mut writer = csv::Writer::from_writer(Vec::new());
+      for row in rows {
+        writer.serialize(row).map_err(|e| internal(&e))?;
+      }
+      writer.into_inner().map_err(|e| internal(&e))
+    }
+  }
+}
+
+/// Outcome of an import, nothing is written on errors or in a dry run
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+  pub dry_run: bool,
+  pub created: usize,
+  pub updated: usize,
+  pub skipped: usize,
+  pub errors: Vec<String>,
+}
+
+impl Report {
+  /// HTML summary for the bot, long error lists are cut
+  pub fn summary(&self) -> String {
+    use teloxide::utils::html::escape;
+
+    const SHOWN_ERRORS: usize = 10;
+
+    if !self.errors.is_empty() {
+      let mut text = format!(
+        "❌ <b>Import rejected</b>, {} invalid rows:\n",
+        self.errors.len()
+      );
+      for error in self.errors.iter().take(SHOWN_ERRORS) {
+        text.push_str(&format!("\n{}", escape(error)));
+      }
+      if self.errors.len() > SHOWN_ERRORS {
+        text.push_str(&format!(
+          "\n... and {} more",
+          self.errors.len() - SHOWN_ERRORS
+        ));
+      }
+      return text;
+    }
+
+    let title = if self.dry_run {
+      "🔎 <b>Import dry run</b>"
+    } else {
+      "✅ <b>Imported</b>"
+    };
+    format!(
+      "{}\n\nCreated: {}\nUpdated: {}\nSkipped: {}",
+      title, self.created, self.updated, self.skipped
+    )
+  }
+}
+
+pub struct Transfer<'a> {
+  db: &'a DatabaseConnection,
+  actor: Actor,
+}
+
+impl<'a> Transfer<'a> {
+  pub fn new(db: &'a DatabaseConnection) -> Self {
+    Self { db, actor: Actor::System("server") }
+  }
+
+  /// Attribute imported licenses to `actor` in the audit log
+  pub fn by(mut self, actor: Actor) -> Self {
+    self.actor = actor;
+    self
+  }
+
+  /// Import licenses in one transaction, a dry run rolls it back to only
+  /// report what would change
+  pub async fn import(
+    &self,
+    format: Format,
+    data: &[u8],
+    strategy: Strategy,
+    dry_run: bool,
+  ) -> Result<Report> {
+    let mut report = Report { dry_run, ..Default::default() };
+    let imports = match parse(format, data) {
+      Ok(imports) => imports,
+      Err(errors) => {
+        report.errors = errors;
+        return Ok(report);
+      }
+    };
+
+    let now = Utc::now().naive_utc();
+    let txn = self.db.begin().await?;
+    let mut changes = Vec::new();
+
+    for import in imports {
+      if user::Entity::find_by_id(import.owner).one(&txn).await?.is_none() {
+        user::ActiveModel {
+          tg_user_id: Set(import.owner),
+          reg_date: Set(now),
+          display_name: Set(None),
+          language: Set(None),
+          username: Set(None),
+          first_name: Set(None),
+          language_code: Set(None),
+          profile_synced_at: Set(None),
+        }
+        .insert(&txn)
+        .await?;
+      }
+
+      let existing = license::Entity::find_by_id(&import.key).one(&txn).await?;
+      let Some(license) = existing else {
+        let created = license::ActiveModel {
+          key: Set(import.key),
+          tg_user_id: Set(import.owner),
+          license_type: Set(import.tier),
+          is_blocked: Set(import.blocked),
+          expires_at: Set(import.expires_at),
+          created_at: Set(import.created_at.unwrap_or(now)),
+          max_sessions: Set(import.max_sessions),
+        }
+        .insert(&txn)
+        .await?;
+        report.created += 1;
+        changes.push((None, created));
+        continue;
+      };
+
+      let update = match strategy {
+        Strategy::Skip => None,
+        Strategy::Overwrite => Some(license::ActiveModel {
+          tg_user_id: Set(import.owner),
+          license_type: Set(import.tier),
+          is_blocked: Set(import.blocked),
+          expires_at: Set(import.expires_at),
+          max_sessions: Set(import.max_sessions),
+          ..license.clone().into()
+        }),
+        Strategy::Extend if import.expires_at > now => {
+          let from = license.expires_at.max(now);
+          Some(license::ActiveModel {
+            expires_at: Set(from + (import.expires_at - now)),
+            max_sessions: Set(license.max_sessions.max(import.max_sessions)),
+            ..license.clone().into()
+          })
+        }
+        // nothing left to add
+        Strategy::Extend => None,
+      };
+
+      match update {
+        Some(update) => {
+          let updated = update.update(&txn).await?;
+          report.updated += 1;
+          changes.push((Some(license), updated));
+        }
+        None => report.skipped += 1,
+      }
+    }
+
+    if dry_run {
+      txn.rollback().await?;
+      return Ok(report);
+    }
+
+    for (before, after) in &changes {
+      sv::Audit::record(
+        &txn,
+        &self.actor,
+        "license.import",
+        &after.key,
+        Some(after.tg_user_id),
+        before.as_ref(),
+        Some(after),
+      )
+      .await?;
+    }
+    txn.commit().await?;
+    Ok(report)
+  }
+
+  /// Whole table as a file, licenses in the importable [`LicenseRow`] shape
+  pub async fn export(&self, table: Table, format: Format) -> Result<Vec<u8>> {
+    match table {
+      Table::Licenses => {
+        let rows: Vec<LicenseRow> = sv::License::new(self.db)
+          .all()
+          .await?
+          .into_iter()
+          .map(LicenseRow::from)
+          .collect();
+        encode(format, &rows)
+      }
+      Table::Users => encode(format, &sv::User::new(self.db).all().await?),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+  use crate::entity::audit;
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(user::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(license::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(audit::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  const CSV: &str = "\
+key,owner,tier,expires_at,max_sessions
+old-1,42,pro,2099-01-01,2
+old-2,43,Trial,2099-01-01 12:00:00,
+";
+
+  #[tokio::test]
+  async fn test_import_strategies() {
+    let db = setup_test_db().await;
+    let transfer = Transfer::new(&db).by(Actor::Admin(1));
+    let licenses = sv::License::new(&db);
+
+    let report =
+      transfer.import(Format::Csv, CSV.as_bytes(), Strategy::Skip, true);
+    let report = report.await.unwrap();
+    assert_eq!((report.created, report.errors.len()), (2, 0));
+    assert!(licenses.all().await.unwrap().is_empty());
+
+    transfer
+      .import(Format::Csv, CSV.as_bytes(), Strategy::Skip, false)
+      .await
+      .unwrap();
+    let old = licenses.by_key("old-1").await.unwrap().unwrap();
+    assert_eq!((old.tg_user_id, old.max_sessions), (42, 2));
+    assert_eq!(old.license_type, LicenseType::Pro);
+    assert!(sv::User::new(&db).by_id(43).await.unwrap().is_some());
+
+    let report = transfer
+      .import(Format::Csv, CSV.as_bytes(), Strategy::Skip, false)
+      .await
+      .unwrap();
+    assert_eq!((report.created, report.skipped), (0, 2));
+
+    let row = br#"[{"key":"old-1","owner":7,"tier":"trial","expires_at":"2099-06-01T00:00:00Z"}]"#;
+    transfer
+      .import(Format::Json, row, Strategy::Overwrite, false)
+      .await
+      .unwrap();
+    let old = licenses.by_key("old-1").await.unwrap().unwrap();
+    assert_eq!((old.tg_user_id, old.max_sessions), (7, 1));
+
+    let before = old.expires_at;
+    let report = transfer
+      .import(Format::Json, row, Strategy::Extend, false)
+      .await
+      .unwrap();
+    assert_eq!(report.updated, 1);
+    let old = licenses.by_key("old-1").await.unwrap().unwrap();
+    assert!(old.expires_at > before + TimeDelta::days(365 * 70));
+
+    let audited =
+      sv::Audit::new(&db).recent(Some("old-1"), None, 10).await.unwrap();
+    assert_eq!(audited.len(), 3);
+    assert_eq!(audited[0].action, "license.import");
+  }
+
+  #[tokio::test]
+  async fn test_import_validation_and_export() {
+    let db = setup_test_db().await;
+    let transfer = Transfer::new(&db);
+
+    let invalid = "\
+key,owner,tier,expires_at
+ok,1,pro,2099-01-01
+bad key,1,pro,2099-01-01
+ok,1,gold,tomorrow
+0123456789abcdef0123456789abcdef01234,1,pro,2099-01-01
+";
+    let report = transfer
+      .import(Format::Csv, invalid.as_bytes(), Strategy::Skip, false)
+      .await
+      .unwrap();
+    assert_eq!(report.errors.len(), 3);
+    assert!(report.errors[0].starts_with("Row 2: invalid key"));
+    assert!(report.errors[2].starts_with("Row 4: invalid key"));
+    assert!(sv::License::new(&db).all().await.unwrap().is_empty());
+
+    transfer
+      .import(Format::Csv, CSV.as_bytes(), Strategy::Skip, false)
+      .await
+      .unwrap();
+    let exported =
+      transfer.export(Table::Licenses, Format::Json).await.unwrap();
+    let rows: Vec<LicenseRow> = json::from_slice(&exported).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].expires_at, "2099-01-01 12:00:00");
+
+    let csv = transfer.export(Table::Licenses, Format::Csv).await.unwrap();
+    let db = setup_test_db().await;
+    let report = Transfer::new(&db)
+      .import(Format::Csv, &csv, Strategy::Skip, false)
+      .await
+      .unwrap();
+    assert_eq!(report.created, 2);
+    assert_eq!(
+      sv::License::new(&db)
+        .by_key("old-2")
+        .await
+        .unwrap()
+        .unwrap()
+        .max_sessions,
+      1
+    );
+  }
+}
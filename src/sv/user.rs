@@ -1,8 +1,129 @@
+use sea_orm::{
+  Condition, LoaderTrait,
+  sea_query::{
+    Alias, Expr, Func, LikeExpr, Query, SelectStatement, SimpleExpr,
+  },
+};
+
 use crate::{
   entity::{license, user},
+  i18n::Lang,
   prelude::*,
 };
 
+/// Profiles seen within this window are not written again on every update
+pub const PROFILE_TTL: Duration = Duration::from_hours(24);
+
+/// Telegram profile of a user, as seen in updates or `get_chat`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+  pub username: Option<String>,
+  pub first_name: Option<String>,
+  pub language_code: Option<String>,
+}
+
+impl Profile {
+  fn of(user: &user::Model) -> Self {
+    Self {
+      username: user.username.clone(),
+      first_name: user.first_name.clone(),
+      language_code: user.language_code.clone(),
+    }
+  }
+}
+
+/// Users of [`User::search`] by the state of their best license
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Having {
+  #[default]
+  Any,
+  /// At least one valid license
+  Valid,
+  /// A valid license among these keys, e.g. the ones with live sessions
+  ValidKey(Vec<String>),
+  /// Licenses, but neither a valid nor a blocked one
+  Expired,
+  /// No valid license, but a blocked one
+  Blocked,
+}
+
+/// A page of [`User::search`] results, with the licenses of each user
+#[derive(Debug, Clone)]
+pub struct Page {
+  /// Users matching the search on all pages
+  pub total: u64,
+  /// Page shown, the last one if the requested page is past the end
+  pub page: u64,
+  pub users: Vec<(user::Model, Vec<license::Model>)>,
+}
+
+/// Licenses of the user in the outer query matching `condition` exist
+fn licenses_where(condition: Condition) -> SimpleExpr {
+  let select: SelectStatement = Query::select()
+    .expr(Expr::val(1))
+    .from(license::Entity)
+    .and_where(
+      Expr::col((license::Entity, license::Column::TgUserId))
+        .equals((user::Entity, user::Column::TgUserId)),
+    )
+    .cond_where(condition)
+    .to_owned();
+  Expr::exists(select)
+}
+
+impl Having {
+  fn condition(&self, now: DateTime) -> Condition {
+    let valid = || {
+      Condition::all()
+        .add(license::Column::IsBlocked.eq(false))
+        .add(license::Column::ExpiresAt.gt(now))
+    };
+    let blocked = || Condition::all().add(license::Column::IsBlocked.eq(true));
+
+    let condition = Condition::all();
+    match self {
+      Having::Any => condition,
+      Having::Valid => condition.add(licenses_where(valid())),
+      Having::ValidKey(keys) => condition.add(licenses_where(
+        valid().add(license::Column::Key.is_in(keys.iter().cloned())),
+      )),
+      Having::Expired => condition
+        .add(licenses_where(Condition::all()))
+        .add(licenses_where(valid()).not())
+        .add(licenses_where(blocked()).not()),
+      Having::Blocked => condition
+        .add(licenses_where(valid()).not())
+        .add(licenses_where(blocked())),
+    }
+  }
+}
+
+/// Id search matches any part of the id, text search the cached username,
+/// first name and leaderboard name, both ignore case and a leading `@`
+fn search_condition(query: &str) -> Condition {
+  let query = query.trim().trim_start_matches('@').to_lowercase();
+  if query.is_empty() {
+    return Condition::all();
+  }
+
+  let escaped =
+    query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+  let pattern = || LikeExpr::new(format!("%{escaped}%")).escape('\\');
+
+  if query.chars().all(|c| c.is_ascii_digit()) {
+    let id = Expr::col((user::Entity, user::Column::TgUserId))
+      .cast_as(Alias::new("text"));
+    return Condition::all().add(Expr::expr(id).like(pattern()));
+  }
+
+  [user::Column::Username, user::Column::FirstName, user::Column::DisplayName]
+    .into_iter()
+    .fold(Condition::any(), |condition, column| {
+      let name = Func::lower(Expr::col((user::Entity, column)));
+      condition.add(Expr::expr(name).like(pattern()))
+    })
+}
+
 pub struct User<'a> {
   db: &'a DatabaseConnection,
 }
@@ -20,8 +141,16 @@ impl<'a> User<'a> {
     }
 
     let now = Utc::now().naive_utc();
-    let user =
-      user::ActiveModel { tg_user_id: Set(tg_user_id), reg_date: Set(now) };
+    let user = user::ActiveModel {
+      tg_user_id: Set(tg_user_id),
+      reg_date: Set(now),
+      display_name: Set(None),
+      language: Set(None),
+      username: Set(None),
+      first_name: Set(None),
+      language_code: Set(None),
+      profile_synced_at: Set(None),
+    };
 
     Ok(user.insert(self.db).await?)
   }
@@ -31,28 +160,237 @@ impl<'a> User<'a> {
     Ok(user)
   }
 
-  #[allow(dead_code)]
-  pub async fn all(&self) -> Result<Vec<user::Model>> {
+  /// Opt in (`Some`) or out (`None`) of showing a name on the leaderboard
+  pub async fn set_display_name(
+    &self,
+    tg_user_id: i64,
+    name: Option<String>,
+  ) -> Result<user::Model> {
+    let user = self.get_or_create(tg_user_id).await?;
+
+    let user = user::ActiveModel { display_name: Set(name), ..user.into() };
+    Ok(user.update(self.db).await?)
+  }
+
+  /// Store the bot language the user chose
+  pub async fn set_language(
+    &self,
+    tg_user_id: i64,
+    lang: Lang,
+  ) -> Result<user::Model> {
+    let user = self.get_or_create(tg_user_id).await?;
+
+    let user = user::ActiveModel {
+      language: Set(Some(lang.code().into())),
+      ..user.into()
+    };
+    Ok(user.update(self.db).await?)
+  }
+
+  /// Store the Telegram profile of the user, creating them if needed.
+  /// Unchanged profiles are only written again once [`PROFILE_TTL`] passed.
+  pub async fn update_profile(
+    &self,
+    tg_user_id: i64,
+    profile: Profile,
+  ) -> Result<user::Model> {
+    let user = self.get_or_create(tg_user_id).await?;
+
+    let now = Utc::now().naive_utc();
+    let fresh = user.profile_synced_at.is_some_and(|at| {
+      (now - at).to_std().is_ok_and(|elapsed| elapsed < PROFILE_TTL)
+    });
+    if fresh && Profile::of(&user) == profile {
+      return Ok(user);
+    }
+
+    let user = user::ActiveModel {
+      username: Set(profile.username),
+      first_name: Set(profile.first_name),
+      language_code: Set(profile.language_code),
+      profile_synced_at: Set(Some(now)),
+      ..user.into()
+    };
+    Ok(user.update(self.db).await?)
+  }
+
+  /// Users whose profile was never synced or is older than `max_age`,
+  /// least recently synced first
+  pub async fn stale_profiles(
+    &self,
+    max_age: Duration,
+    limit: u64,
+  ) -> Result<Vec<user::Model>> {
+    let now = Utc::now().naive_utc();
+    let cutoff = TimeDelta::from_std(max_age)
+      .ok()
+      .and_then(|age| now.checked_sub_signed(age))
+      .unwrap_or(DateTime::MIN);
     let users = user::Entity::find()
-      .order_by_asc(user::Column::RegDate)
+      .filter(
+        Condition::any()
+          .add(user::Column::ProfileSyncedAt.is_null())
+          .add(user::Column::ProfileSyncedAt.lt(cutoff)),
+      )
+      .order_by_asc(user::Column::ProfileSyncedAt)
+      .limit(limit)
       .all(self.db)
       .await?;
     Ok(users)
   }
 
-  pub async fn all_with_licenses(
-    &self,
-  ) -> Result<Vec<(user::Model, Vec<license::Model>)>> {
+  #[allow(dead_code)]
+  pub async fn all(&self) -> Result<Vec<user::Model>> {
     let users = user::Entity::find()
       .order_by_asc(user::Column::RegDate)
-      .find_with_related(license::Entity)
       .all(self.db)
       .await?;
     Ok(users)
   }
 
+  /// Users matching `query` and `having`, by registration date, `per_page`
+  /// at a time with their licenses
+  pub async fn search(
+    &self,
+    having: &Having,
+    query: &str,
+    page: u64,
+    per_page: u64,
+  ) -> Result<Page> {
+    let now = Utc::now().naive_utc();
+    let select = user::Entity::find()
+      .filter(having.condition(now))
+      .filter(search_condition(query))
+      .order_by_asc(user::Column::RegDate)
+      .order_by_asc(user::Column::TgUserId);
+
+    let total = select.clone().count(self.db).await?;
+    let page = page.min(total.div_ceil(per_page).max(1) - 1);
+    let users =
+      select.offset(page * per_page).limit(per_page).all(self.db).await?;
+    let licenses = users.load_many(license::Entity, self.db).await?;
+
+    Ok(Page { total, page, users: users.into_iter().zip(licenses).collect() })
+  }
+
   #[allow(dead_code)]
   pub async fn count(&self) -> Result<u64> {
     Ok(user::Entity::find().count(self.db).await?)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use sea_orm::{DbBackend, Schema};
+
+  use super::*;
+
+  async fn setup_test_db() -> DatabaseConnection {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+
+    let schema = Schema::new(DbBackend::Sqlite);
+
+    let stmt = schema.create_table_from_entity(user::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    let stmt = schema.create_table_from_entity(license::Entity);
+    db.execute(db.get_database_backend().build(&stmt)).await.unwrap();
+
+    db
+  }
+
+  async fn add_license(
+    db: &DatabaseConnection,
+    tg_user_id: i64,
+    key: &str,
+    days: i64,
+    blocked: bool,
+  ) {
+    let now = Utc::now().naive_utc();
+    license::ActiveModel {
+      key: Set(key.into()),
+      tg_user_id: Set(tg_user_id),
+      license_type: Set(Default::default()),
+      expires_at: Set(now + TimeDelta::days(days)),
+      is_blocked: Set(blocked),
+      created_at: Set(now),
+      max_sessions: Set(1),
+    }
+    .insert(db)
+    .await
+    .unwrap();
+  }
+
+  async fn ids(sv: &User<'_>, having: Having, query: &str) -> Vec<i64> {
+    let page = sv.search(&having, query, 0, 10).await.unwrap();
+    page.users.iter().map(|(user, _)| user.tg_user_id).collect()
+  }
+
+  #[tokio::test]
+  async fn test_search() {
+    let db = setup_test_db().await;
+    let sv = User::new(&db);
+
+    for id in [1001, 1002, 1003, 2004] {
+      sv.get_or_create(id).await.unwrap();
+    }
+    let alice =
+      Profile { username: Some("Alice_W".into()), ..Default::default() };
+    sv.update_profile(1001, alice).await.unwrap();
+
+    add_license(&db, 1001, "valid", 7, false).await;
+    add_license(&db, 1001, "old", -7, false).await;
+    add_license(&db, 1002, "blocked", 7, true).await;
+    add_license(&db, 1003, "expired", -1, false).await;
+
+    assert_eq!(ids(&sv, Having::Any, "").await, [1001, 1002, 1003, 2004]);
+    assert_eq!(ids(&sv, Having::Valid, "").await, [1001]);
+    assert_eq!(ids(&sv, Having::Expired, "").await, [1003]);
+    assert_eq!(ids(&sv, Having::Blocked, "").await, [1002]);
+    assert_eq!(
+      ids(&sv, Having::ValidKey(vec!["valid".into()]), "").await,
+      [1001]
+    );
+    assert!(
+      ids(&sv, Having::ValidKey(vec!["old".into()]), "").await.is_empty()
+    );
+    assert!(ids(&sv, Having::ValidKey(vec![]), "").await.is_empty());
+
+    assert_eq!(ids(&sv, Having::Any, "00").await, [1001, 1002, 1003, 2004]);
+    assert_eq!(ids(&sv, Having::Any, "200").await, [2004]);
+    assert_eq!(ids(&sv, Having::Any, "@alice").await, [1001]);
+    assert_eq!(ids(&sv, Having::Any, "e_w").await, [1001]);
+    // `_` is not a wildcard
+    assert!(ids(&sv, Having::Any, "alic_").await.is_empty());
+    assert!(ids(&sv, Having::Any, "bob").await.is_empty());
+
+    let page = sv.search(&Having::Any, "", 5, 3).await.unwrap();
+    assert_eq!((page.total, page.page, page.users.len()), (4, 1, 1));
+    assert_eq!(page.users[0].1.len(), 0);
+
+    let page = sv.search(&Having::Valid, "", 0, 3).await.unwrap();
+    assert_eq!(page.users[0].1.len(), 2);
+  }
+
+  #[tokio::test]
+  async fn test_update_profile() {
+    let db = setup_test_db().await;
+    let sv = User::new(&db);
+
+    sv.get_or_create(1).await.unwrap();
+    assert_eq!(sv.stale_profiles(PROFILE_TTL, 10).await.unwrap().len(), 1);
+
+    let profile = Profile {
+      username: Some("alice".into()),
+      first_name: Some("Alice".into()),
+      language_code: Some("en".into()),
+    };
+    let user = sv.update_profile(1, profile.clone()).await.unwrap();
+    assert_eq!(user.username.as_deref(), Some("alice"));
+    assert!(sv.stale_profiles(PROFILE_TTL, 10).await.unwrap().is_empty());
+
+    // an unchanged profile is not written again
+    let again = sv.update_profile(1, profile).await.unwrap();
+    assert_eq!(again.profile_synced_at, user.profile_synced_at);
+  }
+}
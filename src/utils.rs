@@ -1,4 +1,10 @@
-use crate::prelude::*;
+use teloxide::utils::html;
+
+use crate::{
+  entity::{audit, user},
+  prelude::*,
+  sv::{self, leaderboard::Entry},
+};
 
 pub fn format_date(date: DateTime) -> String {
   date.format("%d.%m.%Y %H:%M").to_string()
@@ -13,6 +19,86 @@ pub fn format_duration(duration: TimeDelta) -> String {
   )
 }
 
+/// Render an audit log entry as HTML, listing changed fields of updates
+pub fn format_audit(entry: &audit::Model) -> String {
+  let mut text = format!(
+    "<b>#{}</b> {} · {}\n{} <code>{}</code>",
+    entry.id,
+    format_date(entry.created_at),
+    html::escape(&entry.actor),
+    html::escape(&entry.action),
+    html::escape(&entry.target)
+  );
+
+  match (&entry.before, &entry.after) {
+    (None, Some(_)) => text.push_str(" (created)"),
+    (Some(_), None) => text.push_str(" (deleted)"),
+    (before, after) => {
+      for (field, old, new) in
+        sv::audit::changes(before.as_deref(), after.as_deref())
+      {
+        text.push_str(&format!(
+          "\n  {}: {} → {}",
+          field,
+          html::escape(&old),
+          html::escape(&new)
+        ));
+      }
+    }
+  }
+  text.push('\n');
+  text
+}
+
+/// Cached Telegram name of a user as HTML, for admin views
+pub fn format_user(user: &user::Model) -> String {
+  match (&user.username, &user.first_name) {
+    (Some(username), _) => format!("@{}", username),
+    (None, Some(name)) => format!(
+      "<a href=\"tg://user?id={}\">{}</a>",
+      user.tg_user_id,
+      html::escape(name)
+    ),
+    (None, None) => {
+      format!("<a href=\"tg://user?id={}\">unknown</a>", user.tg_user_id)
+    }
+  }
+}
+
+/// Render leaderboard rows as HTML, users without a display name stay anonymous.
+/// `show_ids` is meant for admin views only.
+pub fn format_leaderboard(
+  entries: &[Entry],
+  show_ids: bool,
+  anonymous: &str,
+) -> String {
+  let mut text = String::new();
+
+  for entry in entries {
+    let medal = match entry.rank {
+      1 => "🥇",
+      2 => "🥈",
+      3 => "🥉",
+      _ => "▫️",
+    };
+    let name = match &entry.display_name {
+      Some(name) => html::escape(name),
+      None => format!("<i>{}</i>", anonymous),
+    };
+
+    text.push_str(&format!(
+      "{} <b>{}.</b> {} — {} XP",
+      medal, entry.rank, name, entry.weekly_xp
+    ));
+    if show_ids {
+      text.push_str(&format!(" (<code>{}</code>)", entry.tg_user_id));
+    }
+    text.push('\n');
+  }
+
+  text
+}
+
 /// Maximum message length for Telegram Bot API (4096 characters).
 /// We use a slightly smaller limit to account for potential HTML entity expansion.
 const TELEGRAM_MAX_MESSAGE_LENGTH: usize = 4000;